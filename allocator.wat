@@ -1,6 +1,6 @@
 (module
     (memory $memory (export "memory") 1)
-    (global $last (mut i32) (i32.const 8))
+    (global $last (export "last") (mut i32) (i32.const 8))
     (func $realloc (export "realloc")
         (param $old_ptr i32)
         (param $old_size i32)
@@ -0,0 +1,52 @@
+use wasm_encoder::{BlockType, Function, Instruction, TypeSection, ValType};
+
+use super::filters::{gen_increment, gen_load_byte};
+
+/// Adds the core type of the function comparing strings,
+/// which takes two strings and returns whether they're equal
+pub fn gen_compare_strings_type(types: &mut TypeSection) {
+    types.function(vec![ValType::I32; 4], vec![ValType::I32]);
+}
+
+/// Generates the function comparing strings byte by byte
+pub fn gen_compare_strings_function() -> Function {
+    const LEFT_OFFSET_LOCAL: u32 = 0;
+    const LEFT_LEN_LOCAL: u32 = 1;
+    const RIGHT_OFFSET_LOCAL: u32 = 2;
+    const RIGHT_LEN_LOCAL: u32 = 3;
+    const INDEX_LOCAL: u32 = 4;
+    let mut func = Function::new(vec![(1, ValType::I32)]);
+
+    // strings of different lengths are never equal
+    func.instruction(&Instruction::LocalGet(LEFT_LEN_LOCAL));
+    func.instruction(&Instruction::LocalGet(RIGHT_LEN_LOCAL));
+    func.instruction(&Instruction::I32Ne);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::Return);
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    // exit once every byte has matched
+    func.instruction(&Instruction::LocalGet(INDEX_LOCAL));
+    func.instruction(&Instruction::LocalGet(LEFT_LEN_LOCAL));
+    func.instruction(&Instruction::I32GeU);
+    func.instruction(&Instruction::BrIf(1));
+    // return early at the first byte that differs
+    gen_load_byte(&mut func, LEFT_OFFSET_LOCAL, INDEX_LOCAL);
+    gen_load_byte(&mut func, RIGHT_OFFSET_LOCAL, INDEX_LOCAL);
+    func.instruction(&Instruction::I32Ne);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::Return);
+    func.instruction(&Instruction::End);
+    gen_increment(&mut func, INDEX_LOCAL, 1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::I32Const(1));
+    func.instruction(&Instruction::End);
+    func
+}
@@ -7,7 +7,10 @@ use wasm_encoder::{
 
 use crate::Config;
 
-use super::{module::gen_module, template::TemplateGenerator};
+use super::{
+    module::{gen_module, post_return_name},
+    template::TemplateGenerator,
+};
 
 /// Generate a component representing the given file data
 pub fn gen_component(config: &Config, template: &TemplateGenerator) -> Component {
@@ -51,18 +54,16 @@ pub fn gen_component(config: &Config, template: &TemplateGenerator) -> Component
         kind: ExportKind::Func,
         name: &config.export_func_name,
     });
+    let post_return_name = post_return_name(config);
+    aliases.alias(Alias::CoreInstanceExport {
+        instance: inner_module_index,
+        kind: ExportKind::Func,
+        name: &post_return_name,
+    });
     component.section(&aliases);
 
-    // Define the component-level argument type
-    let types = template.params().record_type();
-    let params_type_index = 0;
-    component.section(&types);
-
-    // Export the component-level argument type
-    let mut exports = ComponentExportSection::new();
-    exports.export("params", ComponentExportKind::Type, params_type_index, None);
-    let params_export_index = 1;
-    component.section(&exports);
+    // Define and export the component-level argument type
+    let params_export_index = template.params().record_type(&mut component);
 
     // Define the component-level function type
     let mut types = ComponentTypeSection::new();
@@ -70,7 +71,7 @@ pub fn gen_component(config: &Config, template: &TemplateGenerator) -> Component
         .function()
         .params([("params", ComponentValType::Type(params_export_index))])
         .result(ComponentValType::Primitive(PrimitiveValType::String));
-    let apply_type_index = 2;
+    let apply_type_index = params_export_index + 1;
     component.section(&types);
 
     // Define the component-level function
@@ -82,6 +83,7 @@ pub fn gen_component(config: &Config, template: &TemplateGenerator) -> Component
             CanonicalOption::UTF8,
             CanonicalOption::Memory(0),
             CanonicalOption::Realloc(0),
+            CanonicalOption::PostReturn(2),
         ],
     );
     component.section(&functions);
@@ -0,0 +1,562 @@
+use wasm_encoder::{BlockType, Function, Instruction, MemArg, TypeSection, ValType};
+
+use crate::parse::{FilterKind, LiteralKind};
+
+use super::template::{MEMORY_INDEX, REALLOC_FUNC_INDEX};
+
+// Every filter takes the input string as its first two parameters
+const INPUT_OFFSET_LOCAL: u32 = 0;
+const INPUT_LEN_LOCAL: u32 = 1;
+
+pub(super) const BYTE_MEM_ARG: MemArg = MemArg {
+    offset: 0,
+    align: 0,
+    memory_index: MEMORY_INDEX,
+};
+
+/// Adds the core type of the function implementing a filter.
+/// Filters take the input string followed by their arguments and return a string.
+pub fn gen_filter_type(kind: FilterKind, types: &mut TypeSection) {
+    let mut params = vec![ValType::I32; 2];
+    for arg_kind in kind.arg_kinds() {
+        match arg_kind {
+            LiteralKind::String => params.extend([ValType::I32; 2]),
+            LiteralKind::Integer => params.push(ValType::I32),
+        }
+    }
+    types.function(params, vec![ValType::I32; 2]);
+}
+
+/// Generates the function implementing a filter
+pub fn gen_filter_function(kind: FilterKind) -> Function {
+    match kind {
+        FilterKind::Upper => gen_change_case(b'a'),
+        FilterKind::Lower => gen_change_case(b'A'),
+        FilterKind::Trim => gen_trim(),
+        FilterKind::Truncate => gen_truncate(),
+        FilterKind::Replace => gen_replace(),
+        FilterKind::Default => gen_default(),
+        FilterKind::Escape => gen_escape(),
+        FilterKind::Safe => unreachable!("safe only marks a parameter as not needing escaping"),
+    }
+}
+
+/// Flips the case of ASCII letters starting at `first`,
+/// writing the result to a new string of the same length
+fn gen_change_case(first: u8) -> Function {
+    let out_local = 2;
+    let index_local = 3;
+    let char_local = 4;
+    let mut func = Function::new(vec![(3, ValType::I32)]);
+
+    // allocate the output
+    gen_alloc(&mut func, INPUT_LEN_LOCAL);
+    func.instruction(&Instruction::LocalSet(out_local));
+
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    // exit at the end of the input
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::I32GeU);
+    func.instruction(&Instruction::BrIf(1));
+    // load the next byte
+    gen_load_byte(&mut func, INPUT_OFFSET_LOCAL, index_local);
+    func.instruction(&Instruction::LocalSet(char_local));
+    // push the output address
+    func.instruction(&Instruction::LocalGet(out_local));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::I32Add);
+    // select the flipped byte if it is one of the 26 letters starting at `first`
+    func.instruction(&Instruction::LocalGet(char_local));
+    func.instruction(&Instruction::I32Const(0x20));
+    func.instruction(&Instruction::I32Xor);
+    func.instruction(&Instruction::LocalGet(char_local));
+    func.instruction(&Instruction::LocalGet(char_local));
+    func.instruction(&Instruction::I32Const(first as i32));
+    func.instruction(&Instruction::I32Sub);
+    func.instruction(&Instruction::I32Const(26));
+    func.instruction(&Instruction::I32LtU);
+    func.instruction(&Instruction::Select);
+    func.instruction(&Instruction::I32Store8(BYTE_MEM_ARG));
+    gen_increment(&mut func, index_local, 1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::LocalGet(out_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::End);
+    func
+}
+
+/// Removes leading and trailing ASCII whitespace without copying
+fn gen_trim() -> Function {
+    let start_local = 2;
+    let end_local = 3;
+    let char_local = 4;
+    let mut func = Function::new(vec![(3, ValType::I32)]);
+
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::LocalSet(end_local));
+
+    // advance the start past whitespace
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(start_local));
+    func.instruction(&Instruction::LocalGet(end_local));
+    func.instruction(&Instruction::I32GeU);
+    func.instruction(&Instruction::BrIf(1));
+    gen_load_byte(&mut func, INPUT_OFFSET_LOCAL, start_local);
+    func.instruction(&Instruction::LocalSet(char_local));
+    gen_is_space(&mut func, char_local);
+    func.instruction(&Instruction::I32Eqz);
+    func.instruction(&Instruction::BrIf(1));
+    gen_increment(&mut func, start_local, 1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    // retreat the end past whitespace
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(end_local));
+    func.instruction(&Instruction::LocalGet(start_local));
+    func.instruction(&Instruction::I32LeU);
+    func.instruction(&Instruction::BrIf(1));
+    func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+    func.instruction(&Instruction::LocalGet(end_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::I32Const(1));
+    func.instruction(&Instruction::I32Sub);
+    func.instruction(&Instruction::I32Load8U(BYTE_MEM_ARG));
+    func.instruction(&Instruction::LocalSet(char_local));
+    gen_is_space(&mut func, char_local);
+    func.instruction(&Instruction::I32Eqz);
+    func.instruction(&Instruction::BrIf(1));
+    gen_increment(&mut func, end_local, -1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+    func.instruction(&Instruction::LocalGet(start_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalGet(end_local));
+    func.instruction(&Instruction::LocalGet(start_local));
+    func.instruction(&Instruction::I32Sub);
+    func.instruction(&Instruction::End);
+    func
+}
+
+/// Keeps the first `n` characters, followed by "..." if any were removed
+fn gen_truncate() -> Function {
+    let max_chars_local = 2;
+    let index_local = 3;
+    let count_local = 4;
+    let out_local = 5;
+    let mut func = Function::new(vec![(3, ValType::I32)]);
+
+    // find the byte index of the first character past the limit
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    // return the input unchanged if the end is reached
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::I32GeU);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::Return);
+    func.instruction(&Instruction::End);
+    // count bytes that aren't UTF-8 continuation bytes
+    gen_load_byte(&mut func, INPUT_OFFSET_LOCAL, index_local);
+    func.instruction(&Instruction::I32Const(0xC0));
+    func.instruction(&Instruction::I32And);
+    func.instruction(&Instruction::I32Const(0x80));
+    func.instruction(&Instruction::I32Ne);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(count_local));
+    func.instruction(&Instruction::LocalGet(max_chars_local));
+    func.instruction(&Instruction::I32Eq);
+    func.instruction(&Instruction::BrIf(2));
+    gen_increment(&mut func, count_local, 1);
+    func.instruction(&Instruction::End);
+    gen_increment(&mut func, index_local, 1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    // allocate the output with room for "..."
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::I32Const(1));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::I32Const(3));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::Call(REALLOC_FUNC_INDEX));
+    func.instruction(&Instruction::LocalSet(out_local));
+    // copy the kept characters
+    func.instruction(&Instruction::LocalGet(out_local));
+    func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::MemoryCopy {
+        src_mem: MEMORY_INDEX,
+        dst_mem: MEMORY_INDEX,
+    });
+    // write "..."
+    for offset in 0..3 {
+        func.instruction(&Instruction::LocalGet(out_local));
+        func.instruction(&Instruction::LocalGet(index_local));
+        func.instruction(&Instruction::I32Add);
+        func.instruction(&Instruction::I32Const(b'.' as i32));
+        func.instruction(&Instruction::I32Store8(MemArg {
+            offset,
+            ..BYTE_MEM_ARG
+        }));
+    }
+
+    func.instruction(&Instruction::LocalGet(out_local));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::I32Const(3));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::End);
+    func
+}
+
+/// Replaces every occurrence of one string with another
+fn gen_replace() -> Function {
+    let from_offset_local = 2;
+    let from_len_local = 3;
+    let to_offset_local = 4;
+    let to_len_local = 5;
+    let index_local = 6;
+    let match_index_local = 7;
+    let matched_local = 8;
+    let count_local = 9;
+    let out_local = 10;
+    let cursor_local = 11;
+    let out_len_local = 12;
+    let mut func = Function::new(vec![(7, ValType::I32)]);
+    let matcher = Matcher {
+        index_local,
+        match_index_local,
+        matched_local,
+        from_offset_local,
+        from_len_local,
+    };
+
+    // an empty pattern never matches
+    func.instruction(&Instruction::LocalGet(from_len_local));
+    func.instruction(&Instruction::I32Eqz);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::Return);
+    func.instruction(&Instruction::End);
+
+    // count the occurrences
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(from_len_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::I32GtU);
+    func.instruction(&Instruction::BrIf(1));
+    matcher.gen(&mut func);
+    func.instruction(&Instruction::LocalGet(matched_local));
+    func.instruction(&Instruction::If(BlockType::Empty));
+    gen_increment(&mut func, count_local, 1);
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(from_len_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalSet(index_local));
+    func.instruction(&Instruction::Else);
+    gen_increment(&mut func, index_local, 1);
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    // return the input unchanged if nothing matched
+    func.instruction(&Instruction::LocalGet(count_local));
+    func.instruction(&Instruction::I32Eqz);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::Return);
+    func.instruction(&Instruction::End);
+
+    // allocate the output
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::LocalGet(count_local));
+    func.instruction(&Instruction::LocalGet(to_len_local));
+    func.instruction(&Instruction::LocalGet(from_len_local));
+    func.instruction(&Instruction::I32Sub);
+    func.instruction(&Instruction::I32Mul);
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalSet(out_len_local));
+    gen_alloc(&mut func, out_len_local);
+    func.instruction(&Instruction::LocalTee(out_local));
+    func.instruction(&Instruction::LocalSet(cursor_local));
+
+    // copy the input, substituting the replacement for each occurrence
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::LocalSet(index_local));
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::I32GeU);
+    func.instruction(&Instruction::BrIf(1));
+    // only test for a match if the pattern fits in the rest of the input
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::LocalSet(matched_local));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(from_len_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::I32LeU);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    matcher.gen(&mut func);
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::LocalGet(matched_local));
+    func.instruction(&Instruction::If(BlockType::Empty));
+    // write the replacement
+    func.instruction(&Instruction::LocalGet(cursor_local));
+    func.instruction(&Instruction::LocalGet(to_offset_local));
+    func.instruction(&Instruction::LocalGet(to_len_local));
+    func.instruction(&Instruction::MemoryCopy {
+        src_mem: MEMORY_INDEX,
+        dst_mem: MEMORY_INDEX,
+    });
+    func.instruction(&Instruction::LocalGet(cursor_local));
+    func.instruction(&Instruction::LocalGet(to_len_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalSet(cursor_local));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(from_len_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalSet(index_local));
+    func.instruction(&Instruction::Else);
+    // copy a single byte
+    func.instruction(&Instruction::LocalGet(cursor_local));
+    gen_load_byte(&mut func, INPUT_OFFSET_LOCAL, index_local);
+    func.instruction(&Instruction::I32Store8(BYTE_MEM_ARG));
+    gen_increment(&mut func, cursor_local, 1);
+    gen_increment(&mut func, index_local, 1);
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::LocalGet(out_local));
+    func.instruction(&Instruction::LocalGet(out_len_local));
+    func.instruction(&Instruction::End);
+    func
+}
+
+/// Uses the fallback string if the input is empty
+fn gen_default() -> Function {
+    let fallback_offset_local = 2;
+    let fallback_len_local = 3;
+    let mut func = Function::new(vec![]);
+
+    func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+    func.instruction(&Instruction::LocalGet(fallback_offset_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::Select);
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::LocalGet(fallback_len_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::Select);
+    func.instruction(&Instruction::End);
+    func
+}
+
+/// The HTML special characters and the entities that replace them
+const ESCAPES: [(u8, &str); 5] = [
+    (b'<', "&lt;"),
+    (b'>', "&gt;"),
+    (b'&', "&amp;"),
+    (b'"', "&quot;"),
+    (b'\'', "&#39;"),
+];
+
+/// Replaces HTML special characters with entities,
+/// returning the input unchanged when it contains none of them
+fn gen_escape() -> Function {
+    let out_local = 2;
+    let out_len_local = 3;
+    let index_local = 4;
+    let cursor_local = 5;
+    let char_local = 6;
+    let mut func = Function::new(vec![(5, ValType::I32)]);
+
+    // calculate the escaped length
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::LocalSet(out_len_local));
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::I32GeU);
+    func.instruction(&Instruction::BrIf(1));
+    gen_load_byte(&mut func, INPUT_OFFSET_LOCAL, index_local);
+    func.instruction(&Instruction::LocalSet(char_local));
+    // each special character adds the length of its entity minus itself
+    func.instruction(&Instruction::LocalGet(out_len_local));
+    for (char, entity) in ESCAPES {
+        func.instruction(&Instruction::LocalGet(char_local));
+        func.instruction(&Instruction::I32Const(char as i32));
+        func.instruction(&Instruction::I32Eq);
+        func.instruction(&Instruction::I32Const(entity.len() as i32 - 1));
+        func.instruction(&Instruction::I32Mul);
+        func.instruction(&Instruction::I32Add);
+    }
+    func.instruction(&Instruction::LocalSet(out_len_local));
+    gen_increment(&mut func, index_local, 1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    // return the input if nothing needs escaping
+    func.instruction(&Instruction::LocalGet(out_len_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::I32Eq);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::Return);
+    func.instruction(&Instruction::End);
+
+    // allocate the output
+    gen_alloc(&mut func, out_len_local);
+    func.instruction(&Instruction::LocalTee(out_local));
+    func.instruction(&Instruction::LocalSet(cursor_local));
+
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::LocalSet(index_local));
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::LocalGet(INPUT_LEN_LOCAL));
+    func.instruction(&Instruction::I32GeU);
+    func.instruction(&Instruction::BrIf(1));
+    gen_load_byte(&mut func, INPUT_OFFSET_LOCAL, index_local);
+    func.instruction(&Instruction::LocalSet(char_local));
+    gen_increment(&mut func, index_local, 1);
+    // write the entity for a special character and continue with the next byte
+    for (char, entity) in ESCAPES {
+        func.instruction(&Instruction::LocalGet(char_local));
+        func.instruction(&Instruction::I32Const(char as i32));
+        func.instruction(&Instruction::I32Eq);
+        func.instruction(&Instruction::If(BlockType::Empty));
+        for (offset, byte) in entity.bytes().enumerate() {
+            func.instruction(&Instruction::LocalGet(cursor_local));
+            func.instruction(&Instruction::I32Const(byte as i32));
+            func.instruction(&Instruction::I32Store8(MemArg {
+                offset: offset as u64,
+                ..BYTE_MEM_ARG
+            }));
+        }
+        gen_increment(&mut func, cursor_local, entity.len() as i32);
+        func.instruction(&Instruction::Br(1));
+        func.instruction(&Instruction::End);
+    }
+    // otherwise copy the byte
+    func.instruction(&Instruction::LocalGet(cursor_local));
+    func.instruction(&Instruction::LocalGet(char_local));
+    func.instruction(&Instruction::I32Store8(BYTE_MEM_ARG));
+    gen_increment(&mut func, cursor_local, 1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::LocalGet(out_local));
+    func.instruction(&Instruction::LocalGet(out_len_local));
+    func.instruction(&Instruction::End);
+    func
+}
+
+/// Generates a test of whether a pattern occurs in the input at an index
+struct Matcher {
+    index_local: u32,
+    match_index_local: u32,
+    matched_local: u32,
+    from_offset_local: u32,
+    from_len_local: u32,
+}
+
+impl Matcher {
+    /// Sets the matched local to whether the pattern occurs at the index.
+    /// The pattern must fit in the input after the index.
+    fn gen(&self, func: &mut Function) {
+        func.instruction(&Instruction::I32Const(1));
+        func.instruction(&Instruction::LocalSet(self.matched_local));
+        func.instruction(&Instruction::I32Const(0));
+        func.instruction(&Instruction::LocalSet(self.match_index_local));
+        func.instruction(&Instruction::Block(BlockType::Empty));
+        func.instruction(&Instruction::Loop(BlockType::Empty));
+        func.instruction(&Instruction::LocalGet(self.match_index_local));
+        func.instruction(&Instruction::LocalGet(self.from_len_local));
+        func.instruction(&Instruction::I32GeU);
+        func.instruction(&Instruction::BrIf(1));
+        // compare the input byte to the pattern byte
+        func.instruction(&Instruction::LocalGet(INPUT_OFFSET_LOCAL));
+        func.instruction(&Instruction::LocalGet(self.index_local));
+        func.instruction(&Instruction::I32Add);
+        func.instruction(&Instruction::LocalGet(self.match_index_local));
+        func.instruction(&Instruction::I32Add);
+        func.instruction(&Instruction::I32Load8U(BYTE_MEM_ARG));
+        gen_load_byte(func, self.from_offset_local, self.match_index_local);
+        func.instruction(&Instruction::I32Ne);
+        func.instruction(&Instruction::If(BlockType::Empty));
+        func.instruction(&Instruction::I32Const(0));
+        func.instruction(&Instruction::LocalSet(self.matched_local));
+        func.instruction(&Instruction::Br(2));
+        func.instruction(&Instruction::End);
+        gen_increment(func, self.match_index_local, 1);
+        func.instruction(&Instruction::Br(0));
+        func.instruction(&Instruction::End);
+        func.instruction(&Instruction::End);
+    }
+}
+
+/// Pushes the address of a new allocation with the size in a local
+pub(super) fn gen_alloc(func: &mut Function, size_local: u32) {
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::I32Const(1));
+    func.instruction(&Instruction::LocalGet(size_local));
+    func.instruction(&Instruction::Call(REALLOC_FUNC_INDEX));
+}
+
+/// Pushes the byte at `offset + index`
+pub(super) fn gen_load_byte(func: &mut Function, offset_local: u32, index_local: u32) {
+    func.instruction(&Instruction::LocalGet(offset_local));
+    func.instruction(&Instruction::LocalGet(index_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::I32Load8U(BYTE_MEM_ARG));
+}
+
+/// Pushes whether the byte in a local is ASCII whitespace (space or \t through \r)
+fn gen_is_space(func: &mut Function, char_local: u32) {
+    func.instruction(&Instruction::LocalGet(char_local));
+    func.instruction(&Instruction::I32Const(b' ' as i32));
+    func.instruction(&Instruction::I32Eq);
+    func.instruction(&Instruction::LocalGet(char_local));
+    func.instruction(&Instruction::I32Const(b'\t' as i32));
+    func.instruction(&Instruction::I32Sub);
+    func.instruction(&Instruction::I32Const(5));
+    func.instruction(&Instruction::I32LtU);
+    func.instruction(&Instruction::I32Or);
+}
+
+pub(super) fn gen_increment(func: &mut Function, local: u32, amount: i32) {
+    func.instruction(&Instruction::LocalGet(local));
+    func.instruction(&Instruction::I32Const(amount));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalSet(local));
+}
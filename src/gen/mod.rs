@@ -1,3 +1,8 @@
+mod compare;
 pub mod component;
+mod filters;
 mod module;
+mod numbers;
+pub mod params;
 pub mod template;
+pub mod wit;
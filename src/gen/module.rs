@@ -1,11 +1,16 @@
 use wasm_encoder::{
-    CodeSection, EntityType, ExportKind, ExportSection,
-    FunctionSection, ImportSection, MemoryType, Module, TypeSection, ValType,
+    CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection, GlobalType,
+    ImportSection, Instruction, MemoryType, Module, TypeSection, ValType,
 };
 
 use crate::Config;
 
-use super::template::TemplateGenerator;
+use super::{
+    compare::{gen_compare_strings_function, gen_compare_strings_type},
+    filters::{gen_filter_function, gen_filter_type},
+    numbers::{gen_format_function, gen_format_type},
+    template::TemplateGenerator,
+};
 
 pub fn gen_module(config: &Config, template: &TemplateGenerator) -> Module {
     // Create a type entry for the `apply` function's type
@@ -16,8 +21,30 @@ pub fn gen_module(config: &Config, template: &TemplateGenerator) -> Module {
     let clear_type_index = 1;
     template.gen_core_type(&mut types);
     let template_type_index = 2;
+    types.function(vec![ValType::I32], vec![]);
+    let post_return_type_index = 3;
+    // Create a type entry for each filter function
+    for kind in template.filters() {
+        gen_filter_type(*kind, &mut types);
+    }
+    let first_filter_type_index = 4;
+    // Followed by a type entry for each number format function
+    for kind in template.formats() {
+        gen_format_type(*kind, &mut types);
+    }
+    // Followed by a type entry for the function comparing strings
+    if template.compares_strings() {
+        gen_compare_strings_type(&mut types);
+    }
+    // Followed by a type entry shared by the macro functions
+    let helper_count =
+        template.filters().len() + template.formats().len() + template.compares_strings() as usize;
+    let macro_type_index = first_filter_type_index + helper_count as u32;
+    if template.macro_count() > 0 {
+        template.gen_macro_type(&mut types);
+    }
 
-    // Create imports for the allocator memory, alloc, and clear
+    // Create imports for the allocator memory, alloc, clear, and the end of the last allocation
     let mut imports = ImportSection::new();
     let memory_type = MemoryType {
         minimum: 1,
@@ -36,15 +63,45 @@ pub fn gen_module(config: &Config, template: &TemplateGenerator) -> Module {
     );
     let clear_func_index = 1;
     imports.import("allocator", "clear", EntityType::Function(clear_type_index));
+    // Restoring the end of the last allocation frees everything allocated since it was read
+    let last_global_type = GlobalType {
+        val_type: ValType::I32,
+        mutable: true,
+        shared: false,
+    };
+    imports.import("allocator", "last", EntityType::Global(last_global_type));
 
     // Create a function entry for the `apply` function
     let mut functions = FunctionSection::new();
     functions.function(template_type_index);
     let template_func_index = 2;
+    functions.function(post_return_type_index);
+    let post_return_func_index = 3;
+    for index in 0..helper_count {
+        functions.function(first_filter_type_index + index as u32);
+    }
+    // Each macro has a function calculating its length and one writing it
+    for _ in 0..2 * template.macro_count() {
+        functions.function(macro_type_index);
+    }
 
     // Generate a code section that returns a pointer into the return area
     let mut codes = CodeSection::new();
     codes.function(&template.gen_core_function());
+    codes.function(&gen_post_return(clear_func_index));
+    for kind in template.filters() {
+        codes.function(&gen_filter_function(*kind));
+    }
+    for kind in template.formats() {
+        codes.function(&gen_format_function(*kind));
+    }
+    if template.compares_strings() {
+        codes.function(&gen_compare_strings_function());
+    }
+    for macro_index in 0..template.macro_count() {
+        codes.function(&template.gen_macro_len_function(macro_index));
+        codes.function(&template.gen_macro_write_function(macro_index));
+    }
 
     // Generate a data section with the static data
     let (count, data) = template.gen_data();
@@ -59,6 +116,11 @@ pub fn gen_module(config: &Config, template: &TemplateGenerator) -> Module {
         ExportKind::Func,
         template_func_index,
     );
+    exports.export(
+        &post_return_name(config),
+        ExportKind::Func,
+        post_return_func_index,
+    );
 
     // Construct a module in the required order
     let mut module = Module::new();
@@ -74,4 +136,18 @@ pub fn gen_module(config: &Config, template: &TemplateGenerator) -> Module {
     module
 }
 
+/// The name the post-return function for the template function is exported with
+pub fn post_return_name(config: &Config) -> String {
+    format!("cabi_post_{}", config.export_func_name)
+}
+
+/// Generates a post-return function that frees everything allocated
+/// for a call by resetting the allocator once the result has been read
+fn gen_post_return(clear_func_index: u32) -> Function {
+    let mut func = Function::new(vec![]);
+    func.instruction(&Instruction::Call(clear_func_index));
+    func.instruction(&Instruction::End);
+    func
+}
+
 
@@ -0,0 +1,368 @@
+use wasm_encoder::{BlockType, Function, Instruction, MemArg, TypeSection, ValType};
+
+use super::filters::{gen_alloc, gen_increment, BYTE_MEM_ARG};
+
+// Floats are printed with at most this many fractional digits
+const FRACTION_DIGITS: i32 = 6;
+const FRACTION_SCALE: i64 = 1_000_000;
+
+// Floats at least this large are printed in scientific notation
+const SCIENTIFIC_THRESHOLD: f64 = 1e13;
+
+/// A function that formats numbers as decimal strings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FormatKind {
+    /// Takes an i64 and whether it's signed.
+    /// Smaller integers are extended to an i64 before the call.
+    Integer,
+    /// Takes an f64. f32s are promoted before the call.
+    Float,
+}
+
+/// Adds the core type of the function implementing a format.
+/// Formats take the number and return a newly allocated string.
+pub fn gen_format_type(kind: FormatKind, types: &mut TypeSection) {
+    let params = match kind {
+        FormatKind::Integer => vec![ValType::I64, ValType::I32],
+        FormatKind::Float => vec![ValType::F64],
+    };
+    types.function(params, vec![ValType::I32; 2]);
+}
+
+/// Generates the function implementing a format
+pub fn gen_format_function(kind: FormatKind) -> Function {
+    match kind {
+        FormatKind::Integer => gen_format_integer(),
+        FormatKind::Float => gen_format_float(),
+    }
+}
+
+fn gen_format_integer() -> Function {
+    const VALUE_LOCAL: u32 = 0;
+    const SIGNED_LOCAL: u32 = 1;
+    const NEGATIVE_LOCAL: u32 = 2;
+    const LEN_LOCAL: u32 = 3;
+    const OUTPUT_LOCAL: u32 = 4;
+    const END_LOCAL: u32 = 5;
+    const MAGNITUDE_LOCAL: u32 = 6;
+    const SCRATCH_LOCAL: u32 = 7;
+    let mut func = Function::new([(4, ValType::I32), (2, ValType::I64)]);
+
+    // the value is negative if it's signed and below zero
+    func.instruction(&Instruction::LocalGet(SIGNED_LOCAL));
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::I64Const(0));
+    func.instruction(&Instruction::I64LtS);
+    func.instruction(&Instruction::I32And);
+    func.instruction(&Instruction::LocalSet(NEGATIVE_LOCAL));
+
+    // the digits are those of the magnitude, which fits in a u64 even for i64::MIN
+    func.instruction(&Instruction::I64Const(0));
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::I64Sub);
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::LocalGet(NEGATIVE_LOCAL));
+    func.instruction(&Instruction::Select);
+    func.instruction(&Instruction::LocalSet(MAGNITUDE_LOCAL));
+
+    gen_count_digits(&mut func, MAGNITUDE_LOCAL, SCRATCH_LOCAL, LEN_LOCAL);
+    func.instruction(&Instruction::LocalGet(LEN_LOCAL));
+    func.instruction(&Instruction::LocalGet(NEGATIVE_LOCAL));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalSet(LEN_LOCAL));
+
+    gen_alloc(&mut func, LEN_LOCAL);
+    func.instruction(&Instruction::LocalSet(OUTPUT_LOCAL));
+
+    func.instruction(&Instruction::LocalGet(NEGATIVE_LOCAL));
+    func.instruction(&Instruction::If(BlockType::Empty));
+    gen_store_char(&mut func, OUTPUT_LOCAL, b'-');
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::LocalGet(OUTPUT_LOCAL));
+    func.instruction(&Instruction::LocalGet(LEN_LOCAL));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalSet(END_LOCAL));
+    gen_write_digits(&mut func, MAGNITUDE_LOCAL, END_LOCAL, None);
+
+    func.instruction(&Instruction::LocalGet(OUTPUT_LOCAL));
+    func.instruction(&Instruction::LocalGet(LEN_LOCAL));
+    func.instruction(&Instruction::End);
+    func
+}
+
+/// Formats a float with up to six fractional digits and no trailing zeros.
+/// Large floats are printed as a mantissa and exponent, like `1.5e20`.
+fn gen_format_float() -> Function {
+    const VALUE_LOCAL: u32 = 0;
+    const NEGATIVE_LOCAL: u32 = 1;
+    const EXPONENT_LOCAL: u32 = 2;
+    const LEN_LOCAL: u32 = 3;
+    const OUTPUT_LOCAL: u32 = 4;
+    const CURSOR_LOCAL: u32 = 5;
+    const FRACTION_DIGITS_LOCAL: u32 = 6;
+    const INTEGER_DIGITS_LOCAL: u32 = 7;
+    const EXPONENT_DIGITS_LOCAL: u32 = 8;
+    const END_LOCAL: u32 = 9;
+    const INTEGER_LOCAL: u32 = 10;
+    const FRACTION_LOCAL: u32 = 11;
+    const SCRATCH_LOCAL: u32 = 12;
+    const EXPONENT_WIDE_LOCAL: u32 = 13;
+    let mut func = Function::new([(9, ValType::I32), (4, ValType::I64)]);
+
+    // NaN is the only value that isn't equal to itself
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::F64Ne);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    gen_return_text(&mut func, "NaN", LEN_LOCAL, OUTPUT_LOCAL);
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::F64Const(0.0));
+    func.instruction(&Instruction::F64Lt);
+    func.instruction(&Instruction::LocalSet(NEGATIVE_LOCAL));
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::F64Abs);
+    func.instruction(&Instruction::LocalSet(VALUE_LOCAL));
+
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::F64Const(f64::INFINITY));
+    func.instruction(&Instruction::F64Eq);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(NEGATIVE_LOCAL));
+    func.instruction(&Instruction::If(BlockType::Empty));
+    gen_return_text(&mut func, "-inf", LEN_LOCAL, OUTPUT_LOCAL);
+    func.instruction(&Instruction::End);
+    gen_return_text(&mut func, "inf", LEN_LOCAL, OUTPUT_LOCAL);
+    func.instruction(&Instruction::End);
+
+    // divide large values down to a single integer digit, counting the exponent
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::F64Const(SCIENTIFIC_THRESHOLD));
+    func.instruction(&Instruction::F64Ge);
+    func.instruction(&Instruction::If(BlockType::Empty));
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::F64Const(10.0));
+    func.instruction(&Instruction::F64Lt);
+    func.instruction(&Instruction::BrIf(1));
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::F64Const(10.0));
+    func.instruction(&Instruction::F64Div);
+    func.instruction(&Instruction::LocalSet(VALUE_LOCAL));
+    gen_increment(&mut func, EXPONENT_LOCAL, 1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    // round to a fixed point number and split it into integer and fractional parts
+    func.instruction(&Instruction::LocalGet(VALUE_LOCAL));
+    func.instruction(&Instruction::F64Const(FRACTION_SCALE as f64));
+    func.instruction(&Instruction::F64Mul);
+    func.instruction(&Instruction::F64Nearest);
+    func.instruction(&Instruction::I64TruncSatF64U);
+    func.instruction(&Instruction::LocalTee(SCRATCH_LOCAL));
+    func.instruction(&Instruction::I64Const(FRACTION_SCALE));
+    func.instruction(&Instruction::I64DivU);
+    func.instruction(&Instruction::LocalSet(INTEGER_LOCAL));
+    func.instruction(&Instruction::LocalGet(SCRATCH_LOCAL));
+    func.instruction(&Instruction::I64Const(FRACTION_SCALE));
+    func.instruction(&Instruction::I64RemU);
+    func.instruction(&Instruction::LocalSet(FRACTION_LOCAL));
+
+    // drop the trailing zeros of the fractional part
+    func.instruction(&Instruction::I32Const(FRACTION_DIGITS));
+    func.instruction(&Instruction::LocalSet(FRACTION_DIGITS_LOCAL));
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(FRACTION_DIGITS_LOCAL));
+    func.instruction(&Instruction::I32Eqz);
+    func.instruction(&Instruction::BrIf(1));
+    func.instruction(&Instruction::LocalGet(FRACTION_LOCAL));
+    func.instruction(&Instruction::I64Const(10));
+    func.instruction(&Instruction::I64RemU);
+    func.instruction(&Instruction::I64Const(0));
+    func.instruction(&Instruction::I64Ne);
+    func.instruction(&Instruction::BrIf(1));
+    func.instruction(&Instruction::LocalGet(FRACTION_LOCAL));
+    func.instruction(&Instruction::I64Const(10));
+    func.instruction(&Instruction::I64DivU);
+    func.instruction(&Instruction::LocalSet(FRACTION_LOCAL));
+    gen_increment(&mut func, FRACTION_DIGITS_LOCAL, -1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+
+    gen_count_digits(
+        &mut func,
+        INTEGER_LOCAL,
+        SCRATCH_LOCAL,
+        INTEGER_DIGITS_LOCAL,
+    );
+    func.instruction(&Instruction::LocalGet(EXPONENT_LOCAL));
+    func.instruction(&Instruction::I64ExtendI32U);
+    func.instruction(&Instruction::LocalSet(EXPONENT_WIDE_LOCAL));
+    gen_count_digits(
+        &mut func,
+        EXPONENT_WIDE_LOCAL,
+        SCRATCH_LOCAL,
+        EXPONENT_DIGITS_LOCAL,
+    );
+
+    // len = sign + integer digits + optional ".fraction" + optional "e<exponent>"
+    func.instruction(&Instruction::LocalGet(NEGATIVE_LOCAL));
+    func.instruction(&Instruction::LocalGet(INTEGER_DIGITS_LOCAL));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalGet(FRACTION_DIGITS_LOCAL));
+    func.instruction(&Instruction::I32Const(1));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::LocalGet(FRACTION_DIGITS_LOCAL));
+    func.instruction(&Instruction::Select);
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalGet(EXPONENT_DIGITS_LOCAL));
+    func.instruction(&Instruction::I32Const(1));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::I32Const(0));
+    func.instruction(&Instruction::LocalGet(EXPONENT_LOCAL));
+    func.instruction(&Instruction::Select);
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalSet(LEN_LOCAL));
+
+    gen_alloc(&mut func, LEN_LOCAL);
+    func.instruction(&Instruction::LocalTee(OUTPUT_LOCAL));
+    func.instruction(&Instruction::LocalSet(CURSOR_LOCAL));
+
+    func.instruction(&Instruction::LocalGet(NEGATIVE_LOCAL));
+    func.instruction(&Instruction::If(BlockType::Empty));
+    gen_store_char(&mut func, CURSOR_LOCAL, b'-');
+    gen_increment(&mut func, CURSOR_LOCAL, 1);
+    func.instruction(&Instruction::End);
+
+    gen_advance_cursor(&mut func, CURSOR_LOCAL, INTEGER_DIGITS_LOCAL, END_LOCAL);
+    gen_write_digits(&mut func, INTEGER_LOCAL, END_LOCAL, None);
+
+    func.instruction(&Instruction::LocalGet(FRACTION_DIGITS_LOCAL));
+    func.instruction(&Instruction::If(BlockType::Empty));
+    gen_store_char(&mut func, CURSOR_LOCAL, b'.');
+    gen_increment(&mut func, CURSOR_LOCAL, 1);
+    gen_advance_cursor(&mut func, CURSOR_LOCAL, FRACTION_DIGITS_LOCAL, END_LOCAL);
+    // the fraction keeps its leading zeros, so exactly its digit count is written
+    gen_write_digits(
+        &mut func,
+        FRACTION_LOCAL,
+        END_LOCAL,
+        Some(FRACTION_DIGITS_LOCAL),
+    );
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::LocalGet(EXPONENT_LOCAL));
+    func.instruction(&Instruction::If(BlockType::Empty));
+    gen_store_char(&mut func, CURSOR_LOCAL, b'e');
+    gen_increment(&mut func, CURSOR_LOCAL, 1);
+    gen_advance_cursor(&mut func, CURSOR_LOCAL, EXPONENT_DIGITS_LOCAL, END_LOCAL);
+    gen_write_digits(&mut func, EXPONENT_WIDE_LOCAL, END_LOCAL, None);
+    func.instruction(&Instruction::End);
+
+    func.instruction(&Instruction::LocalGet(OUTPUT_LOCAL));
+    func.instruction(&Instruction::LocalGet(LEN_LOCAL));
+    func.instruction(&Instruction::End);
+    func
+}
+
+/// Sets `out` to the number of decimal digits in the u64 in `value`
+fn gen_count_digits(func: &mut Function, value_local: u32, scratch_local: u32, out_local: u32) {
+    func.instruction(&Instruction::I32Const(1));
+    func.instruction(&Instruction::LocalSet(out_local));
+    func.instruction(&Instruction::LocalGet(value_local));
+    func.instruction(&Instruction::LocalSet(scratch_local));
+    func.instruction(&Instruction::Block(BlockType::Empty));
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    func.instruction(&Instruction::LocalGet(scratch_local));
+    func.instruction(&Instruction::I64Const(10));
+    func.instruction(&Instruction::I64LtU);
+    func.instruction(&Instruction::BrIf(1));
+    func.instruction(&Instruction::LocalGet(scratch_local));
+    func.instruction(&Instruction::I64Const(10));
+    func.instruction(&Instruction::I64DivU);
+    func.instruction(&Instruction::LocalSet(scratch_local));
+    gen_increment(func, out_local, 1);
+    func.instruction(&Instruction::Br(0));
+    func.instruction(&Instruction::End);
+    func.instruction(&Instruction::End);
+}
+
+/// Writes the digits of the u64 in `value` backwards, ending just before `end`.
+/// Writes `count` digits if given and otherwise stops after the last non-zero digit.
+/// Clobbers `value`, `end`, and `count`.
+fn gen_write_digits(
+    func: &mut Function,
+    value_local: u32,
+    end_local: u32,
+    count_local: Option<u32>,
+) {
+    func.instruction(&Instruction::Loop(BlockType::Empty));
+    gen_increment(func, end_local, -1);
+    func.instruction(&Instruction::LocalGet(end_local));
+    func.instruction(&Instruction::LocalGet(value_local));
+    func.instruction(&Instruction::I64Const(10));
+    func.instruction(&Instruction::I64RemU);
+    func.instruction(&Instruction::I32WrapI64);
+    func.instruction(&Instruction::I32Const(b'0' as i32));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::I32Store8(BYTE_MEM_ARG));
+    func.instruction(&Instruction::LocalGet(value_local));
+    func.instruction(&Instruction::I64Const(10));
+    func.instruction(&Instruction::I64DivU);
+    func.instruction(&Instruction::LocalSet(value_local));
+    match count_local {
+        Some(count_local) => {
+            gen_increment(func, count_local, -1);
+            func.instruction(&Instruction::LocalGet(count_local));
+        }
+        None => {
+            func.instruction(&Instruction::LocalGet(value_local));
+            func.instruction(&Instruction::I64Const(0));
+            func.instruction(&Instruction::I64Ne);
+        }
+    }
+    func.instruction(&Instruction::BrIf(0));
+    func.instruction(&Instruction::End);
+}
+
+/// Moves the cursor past `len` bytes, leaving the new position in `end` as well
+fn gen_advance_cursor(func: &mut Function, cursor_local: u32, len_local: u32, end_local: u32) {
+    func.instruction(&Instruction::LocalGet(cursor_local));
+    func.instruction(&Instruction::LocalGet(len_local));
+    func.instruction(&Instruction::I32Add);
+    func.instruction(&Instruction::LocalTee(cursor_local));
+    func.instruction(&Instruction::LocalSet(end_local));
+}
+
+fn gen_store_char(func: &mut Function, address_local: u32, char: u8) {
+    func.instruction(&Instruction::LocalGet(address_local));
+    func.instruction(&Instruction::I32Const(char as i32));
+    func.instruction(&Instruction::I32Store8(BYTE_MEM_ARG));
+}
+
+/// Returns a newly allocated copy of a short ASCII text
+fn gen_return_text(func: &mut Function, text: &str, len_local: u32, output_local: u32) {
+    func.instruction(&Instruction::I32Const(text.len() as i32));
+    func.instruction(&Instruction::LocalSet(len_local));
+    gen_alloc(func, len_local);
+    func.instruction(&Instruction::LocalSet(output_local));
+    for (index, byte) in text.bytes().enumerate() {
+        func.instruction(&Instruction::LocalGet(output_local));
+        func.instruction(&Instruction::I32Const(byte as i32));
+        func.instruction(&Instruction::I32Store8(MemArg {
+            offset: index as u64,
+            ..BYTE_MEM_ARG
+        }));
+    }
+    func.instruction(&Instruction::LocalGet(output_local));
+    func.instruction(&Instruction::LocalGet(len_local));
+    func.instruction(&Instruction::Return);
+}
@@ -0,0 +1,1546 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, LazyLock},
+};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+use wasm_encoder::{
+    Component, ComponentExportKind, ComponentExportSection, ComponentTypeSection, ComponentValType,
+    PrimitiveValType, ValType,
+};
+
+use crate::parse::{BinaryOp, Expr, FileData, Literal, Node, Path, M};
+
+const MAX_FLAT_PARAMS: u32 = 16;
+
+/// The type of a template parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamType<'source> {
+    String,
+    Bool,
+    Number(NumberType),
+    Enum(Enum<'source>),
+    Variant(Variant<'source>),
+    Option(Box<ParamType<'source>>),
+    List(Box<ParamType<'source>>),
+    Record(Record<'source>),
+}
+
+/// A numeric parameter type, which is formatted in decimal when interpolated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberType {
+    U8,
+    U16,
+    U32,
+    U64,
+    S8,
+    S16,
+    S32,
+    S64,
+    F32,
+    F64,
+}
+
+impl NumberType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "u8" => Some(NumberType::U8),
+            "u16" => Some(NumberType::U16),
+            "u32" => Some(NumberType::U32),
+            "u64" => Some(NumberType::U64),
+            "s8" => Some(NumberType::S8),
+            "s16" => Some(NumberType::S16),
+            "s32" => Some(NumberType::S32),
+            "s64" => Some(NumberType::S64),
+            "f32" => Some(NumberType::F32),
+            "f64" => Some(NumberType::F64),
+            _ => None,
+        }
+    }
+
+    /// The name of the type in WIT
+    pub fn name(&self) -> &'static str {
+        match self {
+            NumberType::U8 => "u8",
+            NumberType::U16 => "u16",
+            NumberType::U32 => "u32",
+            NumberType::U64 => "u64",
+            NumberType::S8 => "s8",
+            NumberType::S16 => "s16",
+            NumberType::S32 => "s32",
+            NumberType::S64 => "s64",
+            NumberType::F32 => "f32",
+            NumberType::F64 => "f64",
+        }
+    }
+
+    /// The size (and alignment) of the type when stored in memory
+    pub fn size(&self) -> u32 {
+        match self {
+            NumberType::U8 | NumberType::S8 => 1,
+            NumberType::U16 | NumberType::S16 => 2,
+            NumberType::U32 | NumberType::S32 | NumberType::F32 => 4,
+            NumberType::U64 | NumberType::S64 | NumberType::F64 => 8,
+        }
+    }
+
+    /// The core type the number is flattened into
+    pub fn core_type(&self) -> ValType {
+        match self {
+            NumberType::U64 | NumberType::S64 => ValType::I64,
+            NumberType::F32 => ValType::F32,
+            NumberType::F64 => ValType::F64,
+            _ => ValType::I32,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            NumberType::S8 | NumberType::S16 | NumberType::S32 | NumberType::S64
+        )
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, NumberType::F32 | NumberType::F64)
+    }
+
+    /// Whether an integer literal can be converted to this type without changing its value,
+    /// which floats can't do once it needs more digits than they have
+    pub fn fits(&self, value: i64) -> bool {
+        match self {
+            NumberType::U8 => u8::try_from(value).is_ok(),
+            NumberType::U16 => u16::try_from(value).is_ok(),
+            NumberType::U32 => u32::try_from(value).is_ok(),
+            NumberType::U64 => value >= 0,
+            NumberType::S8 => i8::try_from(value).is_ok(),
+            NumberType::S16 => i16::try_from(value).is_ok(),
+            NumberType::S32 => i32::try_from(value).is_ok(),
+            NumberType::S64 => true,
+            NumberType::F32 => value as f32 as i128 == value as i128,
+            NumberType::F64 => value as f64 as i128 == value as i128,
+        }
+    }
+
+    fn primitive(&self) -> PrimitiveValType {
+        match self {
+            NumberType::U8 => PrimitiveValType::U8,
+            NumberType::U16 => PrimitiveValType::U16,
+            NumberType::U32 => PrimitiveValType::U32,
+            NumberType::U64 => PrimitiveValType::U64,
+            NumberType::S8 => PrimitiveValType::S8,
+            NumberType::S16 => PrimitiveValType::S16,
+            NumberType::S32 => PrimitiveValType::S32,
+            NumberType::S64 => PrimitiveValType::S64,
+            NumberType::F32 => PrimitiveValType::F32,
+            NumberType::F64 => PrimitiveValType::F64,
+        }
+    }
+}
+
+/// A use of a parameter that doesn't fit its type
+#[derive(Error, Debug, Diagnostic)]
+#[error("{message}")]
+#[diagnostic()]
+pub struct TypeError {
+    #[source_code]
+    pub(super) src: Arc<NamedSource>,
+    #[label("Here")]
+    pub(super) span: SourceSpan,
+    pub(super) message: String,
+}
+
+/// A record type and the name it is exported with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<'source> {
+    pub name: String,
+    pub fields: Vec<(&'source str, ParamType<'source>)>,
+}
+
+/// An enum type and the name it is exported with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enum<'source> {
+    pub name: String,
+    pub cases: Vec<&'source str>,
+}
+
+/// A variant type and the name it is exported with,
+/// where each case may carry a payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant<'source> {
+    pub name: String,
+    pub cases: Vec<(&'source str, Option<ParamType<'source>>)>,
+}
+
+impl<'source> ParamType<'source> {
+    /// The number of core values this type is flattened into
+    pub fn flat_len(&self) -> u32 {
+        match self {
+            ParamType::String | ParamType::List(_) => 2,
+            ParamType::Bool | ParamType::Number(_) | ParamType::Enum(_) => 1,
+            ParamType::Option(payload) => 1 + payload.flat_len(),
+            ParamType::Variant(variant) => {
+                1 + variant
+                    .payloads()
+                    .map(|payload| payload.flat_len())
+                    .max()
+                    .unwrap_or(0)
+            }
+            ParamType::Record(record) => record.fields.iter().map(|(_, ty)| ty.flat_len()).sum(),
+        }
+    }
+
+    /// The size of this type when stored in memory
+    pub fn size(&self) -> u32 {
+        match self {
+            ParamType::String | ParamType::List(_) => 8,
+            ParamType::Bool => 1,
+            ParamType::Number(number) => number.size(),
+            ParamType::Enum(enum_) => enum_.discriminant_size(),
+            ParamType::Option(payload) => {
+                align_to(self.payload_offset() + payload.size(), self.align())
+            }
+            ParamType::Variant(variant) => {
+                let payload_size = variant
+                    .payloads()
+                    .map(|payload| payload.size())
+                    .max()
+                    .unwrap_or(0);
+                align_to(self.payload_offset() + payload_size, self.align())
+            }
+            ParamType::Record(record) => align_to(record.end_offset(), self.align()),
+        }
+    }
+
+    /// The alignment of this type when stored in memory
+    pub fn align(&self) -> u32 {
+        match self {
+            ParamType::String | ParamType::List(_) => 4,
+            ParamType::Bool => 1,
+            ParamType::Number(number) => number.size(),
+            ParamType::Enum(enum_) => enum_.discriminant_size(),
+            ParamType::Option(payload) => payload.align(),
+            ParamType::Variant(variant) => variant.discriminant_size().max(variant.payload_align()),
+            ParamType::Record(record) => record
+                .fields
+                .iter()
+                .map(|(_, ty)| ty.align())
+                .max()
+                .unwrap_or(1),
+        }
+    }
+
+    /// Adds the core types this type is flattened into
+    pub fn flat_types(&self, types: &mut Vec<ValType>) {
+        match self {
+            ParamType::String | ParamType::List(_) => types.extend([ValType::I32; 2]),
+            ParamType::Bool | ParamType::Enum(_) => types.push(ValType::I32),
+            ParamType::Number(number) => types.push(number.core_type()),
+            ParamType::Option(payload) => {
+                // only payloads made of i32s are supported, so no types need to be joined
+                types.push(ValType::I32);
+                payload.flat_types(types);
+            }
+            ParamType::Variant(_) => {
+                // payloads are also made of i32s, so joining them just takes the longest
+                types.extend(std::iter::repeat_n(ValType::I32, self.flat_len() as usize));
+            }
+            ParamType::Record(record) => {
+                for (_, ty) in record.fields.iter() {
+                    ty.flat_types(types);
+                }
+            }
+        }
+    }
+
+    /// Whether the type is or contains a number type matching the predicate
+    pub fn contains_number(&self, predicate: impl Fn(NumberType) -> bool + Copy) -> bool {
+        match self {
+            ParamType::String | ParamType::Bool | ParamType::Enum(_) => false,
+            ParamType::Number(number) => predicate(*number),
+            ParamType::Option(inner) | ParamType::List(inner) => inner.contains_number(predicate),
+            ParamType::Variant(variant) => variant
+                .payloads()
+                .any(|payload| payload.contains_number(predicate)),
+            ParamType::Record(record) => record
+                .fields
+                .iter()
+                .any(|(_, ty)| ty.contains_number(predicate)),
+        }
+    }
+
+    /// The offset of the payload of an option or variant from its discriminant when stored in memory
+    pub fn payload_offset(&self) -> u32 {
+        match self {
+            ParamType::Option(payload) => align_to(1, payload.align()),
+            ParamType::Variant(variant) => {
+                align_to(variant.discriminant_size(), variant.payload_align())
+            }
+            _ => unreachable!("only options and variants have payloads"),
+        }
+    }
+
+    /// Describes the type for use in diagnostics
+    pub fn describe(&self) -> String {
+        match self {
+            ParamType::String => "a string".into(),
+            ParamType::Bool => "a bool".into(),
+            ParamType::Number(number) => format!("`{}`", number.name()),
+            ParamType::Enum(enum_) => format!("enum `{}`", enum_.name),
+            ParamType::Variant(variant) => format!("variant `{}`", variant.name),
+            ParamType::Option(_) => "an optional string".into(),
+            ParamType::List(_) => "a list".into(),
+            ParamType::Record(record) => format!("record `{}`", record.name),
+        }
+    }
+
+    // Used to order record fields by kind and then by name
+    fn rank(&self) -> u32 {
+        match self {
+            ParamType::String => 0,
+            ParamType::Bool => 1,
+            ParamType::Number(_) => 2,
+            ParamType::Enum(_) => 3,
+            ParamType::Variant(_) => 4,
+            ParamType::Option(_) => 5,
+            ParamType::List(_) => 6,
+            ParamType::Record(_) => 7,
+        }
+    }
+}
+
+impl<'source> Record<'source> {
+    /// The index of the field with the given name,
+    /// where `snake_case` names match `kebab-case` fields
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        let name = snake_to_kebab(name);
+        self.fields
+            .iter()
+            .position(|(field, _)| snake_to_kebab(field) == name)
+    }
+
+    /// The index of the first core value of a field when flattened
+    pub fn field_flat_index(&self, index: usize) -> u32 {
+        self.fields[..index]
+            .iter()
+            .map(|(_, ty)| ty.flat_len())
+            .sum()
+    }
+
+    /// The offset of a field from the start of the record when stored in memory
+    pub fn field_offset(&self, index: usize) -> u32 {
+        let mut offset = 0;
+        for (_, ty) in &self.fields[..index] {
+            offset = align_to(offset, ty.align()) + ty.size();
+        }
+        align_to(offset, self.fields[index].1.align())
+    }
+
+    // The offset just past the last field
+    fn end_offset(&self) -> u32 {
+        let mut offset = 0;
+        for (_, ty) in &self.fields {
+            offset = align_to(offset, ty.align()) + ty.size();
+        }
+        offset
+    }
+}
+
+impl<'source> Enum<'source> {
+    /// The index of the case with the given name,
+    /// where `snake_case` names match `kebab-case` cases
+    pub fn case_index(&self, name: &str) -> Option<usize> {
+        let name = snake_to_kebab(name);
+        self.cases
+            .iter()
+            .position(|case| snake_to_kebab(case) == name)
+    }
+
+    /// The size of the discriminant, which is just big enough to hold every case
+    pub fn discriminant_size(&self) -> u32 {
+        discriminant_size(self.cases.len())
+    }
+}
+
+impl<'source> Variant<'source> {
+    /// The index of the case with the given name,
+    /// where `snake_case` names match `kebab-case` cases
+    pub fn case_index(&self, name: &str) -> Option<usize> {
+        let name = snake_to_kebab(name);
+        self.cases
+            .iter()
+            .position(|(case, _)| snake_to_kebab(case) == name)
+    }
+
+    /// The size of the discriminant, which is just big enough to hold every case
+    pub fn discriminant_size(&self) -> u32 {
+        discriminant_size(self.cases.len())
+    }
+
+    /// The payloads of the cases that have one
+    pub fn payloads(&self) -> impl Iterator<Item = &ParamType<'source>> {
+        self.cases
+            .iter()
+            .filter_map(|(_, payload)| payload.as_ref())
+    }
+
+    // The alignment of the payloads, which all start at the same offset
+    fn payload_align(&self) -> u32 {
+        self.payloads()
+            .map(|payload| payload.align())
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+/// One side of a comparison
+#[derive(Clone, Copy)]
+pub(super) enum Operand<'a, 'source> {
+    Value(&'a ParamType<'source>),
+    StringLiteral,
+    IntegerLiteral(i64),
+    /// A nested condition like `(a and b)`
+    Condition,
+}
+
+/// How the two sides of a comparison are compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Comparison {
+    /// By their bytes, where a `none` optional string is empty
+    Strings,
+    /// As numbers of the same type, which integer literals are converted to
+    Numbers(NumberType),
+    /// By whether they hold
+    Bools,
+}
+
+impl<'a, 'source> Operand<'a, 'source> {
+    /// The operand an expression compares
+    pub fn of(
+        expr: &Expr<'source>,
+        resolve: impl FnOnce(&Path<'source>) -> &'a ParamType<'source>,
+    ) -> Self {
+        match expr {
+            Expr::Path(path) => Operand::Value(resolve(path)),
+            Expr::Literal(literal) => match literal.value {
+                Literal::String(_) => Operand::StringLiteral,
+                Literal::Integer(value) => Operand::IntegerLiteral(value),
+            },
+            Expr::Not { .. } | Expr::Binary { .. } => Operand::Condition,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Operand::Value(ty) => ty.describe(),
+            Operand::StringLiteral => "a string literal".into(),
+            Operand::IntegerLiteral(_) => "an integer literal".into(),
+            Operand::Condition => "a condition".into(),
+        }
+    }
+}
+
+/// How a comparison compares its operands,
+/// or why the operands can't be compared with the operator
+pub(super) fn compare_as(
+    op: BinaryOp,
+    left: Operand,
+    right: Operand,
+) -> Result<Comparison, String> {
+    use Operand::*;
+    let comparison = match (left, right) {
+        (
+            Value(ParamType::String | ParamType::Option(_)) | StringLiteral,
+            Value(ParamType::String | ParamType::Option(_)) | StringLiteral,
+        ) => Comparison::Strings,
+        (Value(ParamType::Number(left)), Value(ParamType::Number(right))) if left == right => {
+            Comparison::Numbers(*left)
+        }
+        (Value(ParamType::Number(number)), IntegerLiteral(value))
+        | (IntegerLiteral(value), Value(ParamType::Number(number))) => {
+            if !number.fits(value) {
+                return Err(format!("`{}` doesn't fit in `{}`", value, number.name()));
+            }
+            Comparison::Numbers(*number)
+        }
+        (IntegerLiteral(_), IntegerLiteral(_)) => Comparison::Numbers(NumberType::S64),
+        (Value(ParamType::Bool) | Condition, Value(ParamType::Bool) | Condition) => {
+            Comparison::Bools
+        }
+        _ => {
+            return Err(format!(
+                "Can't compare {} with {}",
+                left.describe(),
+                right.describe()
+            ))
+        }
+    };
+    if op.is_ordering() && !matches!(comparison, Comparison::Numbers(_)) {
+        return Err(format!(
+            "Can't order {} and {}, only numbers",
+            left.describe(),
+            right.describe()
+        ));
+    }
+    Ok(comparison)
+}
+
+fn discriminant_size(case_count: usize) -> u32 {
+    match case_count {
+        0..=0x100 => 1,
+        0x101..=0x10000 => 2,
+        _ => 4,
+    }
+}
+
+fn align_to(offset: u32, align: u32) -> u32 {
+    (offset + align - 1) & !(align - 1)
+}
+
+pub struct Params<'source> {
+    ty: ParamType<'source>,
+}
+
+/// A parameter type as it is being inferred from its uses
+#[derive(Default)]
+enum Inferred<'source> {
+    #[default]
+    Unknown,
+    String,
+    Bool,
+    Number(NumberType),
+    // A string that is also tested for presence
+    OptionalString,
+    // A number that is compared without being annotated, which defaults to `s32`
+    Integer,
+    // The cases of an enum in the order they're first matched,
+    // which make it a variant once any of them binds a payload
+    Variant(Vec<(&'source str, Option<Inferred<'source>>)>),
+    List {
+        item_name: &'source str,
+        item: Box<Inferred<'source>>,
+    },
+    Record(BTreeMap<&'source str, Inferred<'source>>),
+}
+
+/// A step from a value to one of its parts
+#[derive(Clone, Copy)]
+enum Step<'source> {
+    Field(&'source str),
+    Item,
+    Payload(&'source str),
+}
+
+/// What a name bound by the template refers to
+#[derive(Clone)]
+enum Bound<'source> {
+    /// A value bound by an enclosing loop or case, and the steps to reach it from the params record
+    Param(Vec<Step<'source>>),
+    /// An argument of an enclosing macro
+    Argument,
+    /// A variable set earlier
+    Variable,
+    /// The variables of an enclosing loop
+    Loop,
+}
+
+/// The names bound by enclosing loops, cases, and macros, and by variables set before
+type Scope<'source> = Vec<(&'source str, Bound<'source>)>;
+
+/// The type of macro arguments and variables, which are always strings
+pub(super) static STRING_BINDING: ParamType<'static> = ParamType::String;
+
+/// The type of `loop` within a loop, whose fields are computed from the loop counter
+pub(super) static LOOP_VARIABLES: LazyLock<ParamType<'static>> = LazyLock::new(|| {
+    ParamType::Record(Record {
+        name: "loop".into(),
+        fields: vec![
+            ("index", ParamType::Number(NumberType::U32)),
+            ("first", ParamType::Bool),
+            ("last", ParamType::Bool),
+            ("length", ParamType::Number(NumberType::U32)),
+        ],
+    })
+});
+
+/// How a parameter is used by the template
+#[derive(Clone, Copy)]
+enum Use {
+    Interpolated,
+    /// Interpolated with a type annotation like `{{ count: u32 }}`
+    Annotated(NumberType),
+    Condition,
+    /// Ordered or compared to an integer
+    Numeric,
+    Loop,
+    Match,
+}
+
+impl<'source> Inferred<'source> {
+    /// Describes the type for use in diagnostics
+    fn describe(&self) -> String {
+        match self {
+            Inferred::Unknown => "unknown".into(),
+            Inferred::String => "a string".into(),
+            Inferred::Bool => "a condition".into(),
+            Inferred::Number(number) => format!("`{}`", number.name()),
+            Inferred::OptionalString => "an optional string".into(),
+            Inferred::Integer => "a number".into(),
+            Inferred::Variant(cases) if cases.iter().any(|(_, payload)| payload.is_some()) => {
+                "a variant".into()
+            }
+            Inferred::Variant(_) => "an enum".into(),
+            Inferred::List { .. } => "a list".into(),
+            Inferred::Record(_) => "a record".into(),
+        }
+    }
+}
+
+impl<'source> Params<'source> {
+    /// Infers the params record from how the template uses each parameter
+    pub fn new(file_data: &'source FileData<'source>) -> Result<Self, TypeError> {
+        let mut root = Inferred::Record(BTreeMap::new());
+        Self::collect_sequence(
+            &file_data.source,
+            &file_data.contents,
+            &mut Vec::new(),
+            &mut root,
+        )?;
+
+        let mut named_types = Vec::new();
+        Self::collect_named_paths(&root, &mut Vec::new(), &mut named_types);
+        let type_names = Self::type_names(&named_types);
+
+        let ty = Self::finish(root, &mut Vec::new(), &type_names);
+        let params = Params { ty };
+
+        // comparisons can only be checked once the types of both sides are known
+        params.check_sequence(&file_data.source, &file_data.contents, &mut Vec::new())?;
+        Ok(params)
+    }
+
+    /// Uses a params record type that was declared rather than inferred
+    pub(super) fn from_type(ty: ParamType<'source>) -> Self {
+        Params { ty }
+    }
+
+    /// Infers the parts of the params record used by a sequence of nodes.
+    /// The variables set in the sequence are only in scope until its end.
+    fn collect_sequence(
+        src: &'source Arc<NamedSource>,
+        nodes: &'source [Node<'source>],
+        scope: &mut Scope<'source>,
+        root: &mut Inferred<'source>,
+    ) -> Result<(), TypeError> {
+        let scope_len = scope.len();
+        for node in nodes {
+            Self::collect_params(src, node, scope, root)?;
+        }
+        scope.truncate(scope_len);
+        Ok(())
+    }
+
+    /// Infers the parts of the params record used by a node
+    fn collect_params(
+        src: &'source Arc<NamedSource>,
+        node: &'source Node<'source>,
+        scope: &mut Scope<'source>,
+        root: &mut Inferred<'source>,
+    ) -> Result<(), TypeError> {
+        match node {
+            Node::Text { .. } => {}
+            Node::Parameter {
+                name, annotation, ..
+            } => {
+                let use_ = match annotation {
+                    Some(annotation) => Use::Annotated(annotation_type(src, annotation)?),
+                    None => Use::Interpolated,
+                };
+                Self::infer(src, root, scope, name, use_)?;
+            }
+            Node::Conditional {
+                if_kwd: _,
+                cond,
+                contents,
+                elif_branches,
+                else_branch,
+                endif_kwd: _,
+            } => {
+                let branches = std::iter::once((cond, contents)).chain(
+                    elif_branches
+                        .iter()
+                        .map(|branch| (&branch.cond, &branch.contents)),
+                );
+                for (cond, contents) in branches {
+                    Self::infer_cond(src, root, scope, cond)?;
+                    Self::collect_sequence(src, contents, scope, root)?;
+                }
+                if let Some(branch) = else_branch {
+                    Self::collect_sequence(src, &branch.contents, scope, root)?;
+                }
+            }
+            Node::Loop {
+                for_kwd: _,
+                item_ident,
+                in_kwd: _,
+                list_path,
+                contents,
+                endfor_kwd: _,
+            } => {
+                let Some(inferred) = Self::infer(src, root, scope, list_path, Use::Loop)? else {
+                    unreachable!("only params are lists")
+                };
+                if matches!(inferred, Inferred::Unknown) {
+                    *inferred = Inferred::List {
+                        item_name: item_ident.value,
+                        item: Box::default(),
+                    };
+                }
+                let mut steps = Self::steps(scope, list_path).expect("only params are lists");
+                steps.push(Step::Item);
+                scope.push(("loop", Bound::Loop));
+                scope.push((item_ident.value, Bound::Param(steps)));
+                Self::collect_sequence(src, contents, scope, root)?;
+                scope.truncate(scope.len() - 2);
+            }
+            Node::Match {
+                match_kwd: _,
+                path,
+                cases,
+                endmatch_kwd: _,
+            } => {
+                let Some(Inferred::Variant(known)) =
+                    Self::infer(src, root, scope, path, Use::Match)?
+                else {
+                    unreachable!("matched values are inferred as variants")
+                };
+                for case in cases {
+                    let index = match known.iter().position(|(name, _)| *name == case.name.value) {
+                        Some(index) => index,
+                        None => {
+                            known.push((case.name.value, None));
+                            known.len() - 1
+                        }
+                    };
+                    if case.binding.is_some() {
+                        known[index].1.get_or_insert_with(Inferred::default);
+                    }
+                }
+                for case in cases {
+                    if let Some(binding) = &case.binding {
+                        let mut steps = Self::steps(scope, path).expect("only params are variants");
+                        steps.push(Step::Payload(case.name.value));
+                        scope.push((binding.value, Bound::Param(steps)));
+                    }
+                    Self::collect_sequence(src, &case.contents, scope, root)?;
+                    if case.binding.is_some() {
+                        scope.pop();
+                    }
+                }
+            }
+            Node::Super { .. } => unreachable!("super() is resolved along with the blocks"),
+            Node::Include { .. } | Node::Block { .. } => {
+                for (children, src) in node.children(src) {
+                    Self::collect_sequence(src, children, scope, root)?;
+                }
+            }
+            Node::Macro { args, contents, .. } => {
+                // the contents can't use the names bound where the macro is defined
+                let mut scope = args
+                    .iter()
+                    .map(|arg| (arg.value, Bound::Argument))
+                    .collect();
+                Self::collect_sequence(&contents.source, &contents.contents, &mut scope, root)?;
+            }
+            Node::Call { args, .. } => {
+                for arg in args {
+                    if let Expr::Path(path) = arg {
+                        Self::infer(src, root, scope, path, Use::Interpolated)?;
+                    }
+                }
+            }
+            Node::Set { name, terms, .. } => {
+                // the value can use a parameter or an earlier variable with the same name
+                for term in terms {
+                    if let Expr::Path(path) = &term.value {
+                        Self::infer(src, root, scope, path, Use::Interpolated)?;
+                    }
+                }
+                scope.push((name.value, Bound::Variable));
+            }
+        }
+        Ok(())
+    }
+
+    /// Infers the parts of the params record used by a condition.
+    /// Parameters compared to strings or each other are strings unless they're known to be numbers,
+    /// and those compared to integers or ordered are numbers.
+    fn infer_cond(
+        src: &Arc<NamedSource>,
+        root: &mut Inferred<'source>,
+        scope: &Scope<'source>,
+        expr: &Expr<'source>,
+    ) -> Result<(), TypeError> {
+        match expr {
+            Expr::Path(path) => {
+                Self::infer(src, root, scope, path, Use::Condition)?;
+            }
+            Expr::Literal(_) => {}
+            Expr::Not { operand, .. } => Self::infer_cond(src, root, scope, operand)?,
+            Expr::Binary { op, left, right } if op.value.is_comparison() => {
+                for (operand, other) in [(left, right), (right, left)] {
+                    let Expr::Path(path) = &**operand else {
+                        Self::infer_cond(src, root, scope, operand)?;
+                        continue;
+                    };
+                    let numeric = op.value.is_ordering()
+                        || matches!(
+                            **other,
+                            Expr::Literal(M {
+                                value: Literal::Integer(_),
+                                ..
+                            })
+                        );
+                    let use_ = if numeric {
+                        Use::Numeric
+                    } else {
+                        Use::Interpolated
+                    };
+                    Self::infer(src, root, scope, path, use_)?;
+                }
+            }
+            Expr::Binary { left, right, .. } => {
+                Self::infer_cond(src, root, scope, left)?;
+                Self::infer_cond(src, root, scope, right)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Refines the type of the value named by a path to fit a use of it.
+    /// Strings and lists are tested for emptiness in conditions,
+    /// but a string that is also used as a condition on its own becomes optional.
+    /// Numbers are inferred from annotations and comparisons and can be used anywhere a string can,
+    /// but payloads are flattened into i32s so they can't hold wider numbers.
+    /// Macro arguments, variables, and loop variables have known types, so there's nothing to infer for them.
+    fn infer<'a>(
+        src: &Arc<NamedSource>,
+        root: &'a mut Inferred<'source>,
+        scope: &Scope<'source>,
+        path: &Path<'source>,
+        use_: Use,
+    ) -> Result<Option<&'a mut Inferred<'source>>, TypeError> {
+        let Some(steps) = Self::steps(scope, path) else {
+            if let Some(Bound::Loop) = Self::bound(scope, path) {
+                return Self::check_loop_variable(src, path, use_).map(|_| None);
+            }
+            return Self::check_string(src, scope, path, use_).map(|_| None);
+        };
+        // the number of steps taken before reaching the first segment of the path
+        let prefix_len = steps.len() + 1 - path.segments.len();
+        let conflict = |segment: usize, used_as: &str, found: String| {
+            let name: Vec<&str> = path.segments[..=segment]
+                .iter()
+                .map(|segment| segment.value)
+                .collect();
+            TypeError {
+                src: src.clone(),
+                span: path.prefix_span(segment + 1),
+                message: format!(
+                    "`{}` is used as {} here but as {} elsewhere",
+                    name.join("."),
+                    used_as,
+                    found
+                ),
+            }
+        };
+
+        if let Use::Annotated(number) = use_ {
+            // a list stores its items in memory, so only the steps after the last item matter
+            let in_payload = steps
+                .iter()
+                .rev()
+                .take_while(|step| !matches!(step, Step::Item))
+                .any(|step| matches!(step, Step::Payload(_)));
+            if in_payload && number.core_type() != ValType::I32 {
+                let name: Vec<&str> = path.segments.iter().map(|segment| segment.value).collect();
+                return Err(TypeError {
+                    src: src.clone(),
+                    span: path.span(),
+                    message: format!(
+                        "`{}` is in a variant payload, which can't hold `{}`",
+                        name.join("."),
+                        number.name()
+                    ),
+                });
+            }
+        }
+
+        let inferred = Self::lookup(root, &steps).map_err(|(failed_step, found)| {
+            conflict(failed_step.saturating_sub(prefix_len), "a record", found)
+        })?;
+        let last = path.segments.len() - 1;
+        match (use_, &*inferred) {
+            (Use::Interpolated, Inferred::Unknown) => *inferred = Inferred::String,
+            (Use::Interpolated, Inferred::Bool) => *inferred = Inferred::OptionalString,
+            (
+                Use::Interpolated,
+                Inferred::String
+                | Inferred::OptionalString
+                | Inferred::Number(_)
+                | Inferred::Integer,
+            ) => {}
+            (Use::Interpolated, found) => return Err(conflict(last, "a string", found.describe())),
+            (Use::Annotated(number), Inferred::Unknown | Inferred::String | Inferred::Integer) => {
+                *inferred = Inferred::Number(number)
+            }
+            (Use::Annotated(number), Inferred::Number(found)) if number == *found => {}
+            (Use::Annotated(number), found) => {
+                let used_as = format!("`{}`", number.name());
+                return Err(conflict(last, &used_as, found.describe()));
+            }
+            (Use::Condition, Inferred::Unknown) => *inferred = Inferred::Bool,
+            (Use::Condition, Inferred::String) => *inferred = Inferred::OptionalString,
+            (
+                Use::Condition,
+                Inferred::Bool
+                | Inferred::Number(_)
+                | Inferred::Integer
+                | Inferred::OptionalString
+                | Inferred::List { .. },
+            ) => {}
+            (Use::Condition, found) => return Err(conflict(last, "a condition", found.describe())),
+            (Use::Numeric, Inferred::Unknown | Inferred::String | Inferred::Bool) => {
+                *inferred = Inferred::Integer
+            }
+            (Use::Numeric, Inferred::Integer | Inferred::Number(_)) => {}
+            (Use::Numeric, found) => return Err(conflict(last, "a number", found.describe())),
+            (Use::Loop, Inferred::Unknown | Inferred::List { .. }) => {}
+            (Use::Loop, found) => return Err(conflict(last, "a list", found.describe())),
+            (Use::Match, Inferred::Unknown) => *inferred = Inferred::Variant(Vec::new()),
+            (Use::Match, Inferred::Variant(_)) => {}
+            (Use::Match, found) => return Err(conflict(last, "an enum", found.describe())),
+        }
+        Ok(Some(inferred))
+    }
+
+    /// Checks that a macro argument or variable is used as a string
+    fn check_string(
+        src: &Arc<NamedSource>,
+        scope: &Scope<'source>,
+        path: &Path<'source>,
+        use_: Use,
+    ) -> Result<(), TypeError> {
+        let used_as = match use_ {
+            _ if path.segments.len() > 1 => "a record".into(),
+            Use::Interpolated | Use::Condition => return Ok(()),
+            Use::Annotated(number) => format!("`{}`", number.name()),
+            Use::Numeric => "a number".into(),
+            Use::Loop => "a list".into(),
+            Use::Match => "an enum".into(),
+        };
+        Err(TypeError {
+            src: src.clone(),
+            span: path.prefix_span(1),
+            message: format!(
+                "`{}` is used as {} here but {} are strings",
+                path.root().value,
+                used_as,
+                match Self::bound(scope, path) {
+                    Some(Bound::Variable) => "variables",
+                    _ => "macro arguments",
+                }
+            ),
+        })
+    }
+
+    /// Checks that a loop variable isn't looped over or matched.
+    /// Its other uses are checked along with the rest of the template once the params are inferred.
+    fn check_loop_variable(
+        src: &Arc<NamedSource>,
+        path: &Path<'source>,
+        use_: Use,
+    ) -> Result<(), TypeError> {
+        let used_as = match use_ {
+            Use::Loop => "a list",
+            Use::Match => "an enum",
+            _ => return Ok(()),
+        };
+        let name: Vec<&str> = path.segments.iter().map(|segment| segment.value).collect();
+        Err(TypeError {
+            src: src.clone(),
+            span: path.span(),
+            message: format!(
+                "`{}` is used as {} here but it's a loop variable",
+                name.join("."),
+                used_as
+            ),
+        })
+    }
+
+    /// What the first name in a path is bound to, if it isn't a field of the params record
+    fn bound<'a>(scope: &'a Scope<'source>, path: &Path<'source>) -> Option<&'a Bound<'source>> {
+        let root = path.root().value;
+        scope
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == root)
+            .map(|(_, bound)| bound)
+    }
+
+    /// The steps from the params record to the value named by a path,
+    /// or nothing if it's a macro argument, variable, or loop variable
+    fn steps(scope: &Scope<'source>, path: &Path<'source>) -> Option<Vec<Step<'source>>> {
+        let mut steps = match Self::bound(scope, path) {
+            Some(Bound::Param(steps)) => steps.clone(),
+            Some(Bound::Argument | Bound::Variable | Bound::Loop) => return None,
+            None => vec![Step::Field(path.root().value)],
+        };
+        steps.extend(
+            path.segments[1..]
+                .iter()
+                .map(|segment| Step::Field(segment.value)),
+        );
+        Some(steps)
+    }
+
+    /// Finds the inferred type at the end of the steps,
+    /// creating records along the way where nothing is known yet.
+    /// Fails with the index of the step and the type found
+    /// if a step doesn't fit what was already inferred.
+    fn lookup<'a>(
+        mut inferred: &'a mut Inferred<'source>,
+        steps: &[Step<'source>],
+    ) -> Result<&'a mut Inferred<'source>, (usize, String)> {
+        for (index, step) in steps.iter().enumerate() {
+            if matches!(inferred, Inferred::Unknown) {
+                *inferred = Inferred::Record(BTreeMap::new());
+            }
+            let found = inferred.describe();
+            inferred = match (step, inferred) {
+                (Step::Field(name), Inferred::Record(fields)) => fields.entry(name).or_default(),
+                (Step::Item, Inferred::List { item, .. }) => item,
+                (Step::Payload(name), Inferred::Variant(cases)) => cases
+                    .iter_mut()
+                    .find(|(case, _)| case == name)
+                    .and_then(|(_, payload)| payload.as_mut())
+                    .expect("payloads are bound when their case is matched"),
+                _ => return Err((index, found)),
+            };
+        }
+        Ok(inferred)
+    }
+
+    // Collects the names of the path to each record, enum, and variant, starting with the params record
+    fn collect_named_paths(
+        inferred: &Inferred<'source>,
+        path: &mut Vec<&'source str>,
+        named_types: &mut Vec<Vec<&'source str>>,
+    ) {
+        match inferred {
+            Inferred::List { item_name, item } => {
+                path.push(item_name);
+                Self::collect_named_paths(item, path, named_types);
+                path.pop();
+            }
+            Inferred::Record(fields) => {
+                named_types.push(path.clone());
+                for (name, field) in fields {
+                    path.push(name);
+                    Self::collect_named_paths(field, path, named_types);
+                    path.pop();
+                }
+            }
+            Inferred::Variant(cases) => {
+                named_types.push(path.clone());
+                for (name, payload) in cases {
+                    if let Some(payload) = payload {
+                        path.push(name);
+                        Self::collect_named_paths(payload, path, named_types);
+                        path.pop();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Names the records, enums, and variants at the end of each path.
+    /// They're named after the field, loop item, or case they're used through,
+    /// or the whole path to them if that name is ambiguous,
+    /// which is numbered if another type already has that name.
+    fn type_names(named_types: &[Vec<&'source str>]) -> BTreeMap<Vec<&'source str>, String> {
+        let mut short_names: BTreeMap<&str, usize> = BTreeMap::new();
+        short_names.insert("params", 1);
+        for path in named_types.iter().skip(1) {
+            *short_names.entry(path.last().unwrap()).or_default() += 1;
+        }
+
+        let mut type_names = BTreeMap::new();
+        type_names.insert(Vec::new(), "params".to_owned());
+        // short names are given out first, since they're unique
+        let (short, full): (Vec<_>, Vec<_>) = named_types
+            .iter()
+            .skip(1)
+            .partition(|path| short_names[path.last().unwrap()] == 1);
+        for path in short {
+            type_names.insert(path.clone(), snake_to_kebab(path.last().unwrap()));
+        }
+        for path in full {
+            let full_name = snake_to_kebab(&path.join("-"));
+            let mut name = full_name.clone();
+            let mut number = 2;
+            while type_names.values().any(|taken| *taken == name) {
+                name = format!("{}{}", full_name, number);
+                number += 1;
+            }
+            type_names.insert(path.clone(), name);
+        }
+        type_names
+    }
+
+    /// Converts an inferred type into a parameter type,
+    /// where records, enums, and variants are named by the path to them
+    fn finish(
+        inferred: Inferred<'source>,
+        path: &mut Vec<&'source str>,
+        type_names: &BTreeMap<Vec<&'source str>, String>,
+    ) -> ParamType<'source> {
+        match inferred {
+            Inferred::Unknown | Inferred::String => ParamType::String,
+            Inferred::Bool => ParamType::Bool,
+            Inferred::Number(number) => ParamType::Number(number),
+            Inferred::Integer => ParamType::Number(NumberType::S32),
+            Inferred::OptionalString => ParamType::Option(Box::new(ParamType::String)),
+            Inferred::Variant(cases) if cases.iter().all(|(_, payload)| payload.is_none()) => {
+                ParamType::Enum(Enum {
+                    name: type_names[path.as_slice()].clone(),
+                    cases: cases.into_iter().map(|(name, _)| name).collect(),
+                })
+            }
+            Inferred::Variant(cases) => {
+                let name = type_names[path.as_slice()].clone();
+                let cases = cases
+                    .into_iter()
+                    .map(|(case, payload)| {
+                        path.push(case);
+                        let payload =
+                            payload.map(|payload| Self::finish(payload, path, type_names));
+                        path.pop();
+                        (case, payload)
+                    })
+                    .collect();
+                ParamType::Variant(Variant { name, cases })
+            }
+            Inferred::List { item_name, item } => {
+                path.push(item_name);
+                let item = Self::finish(*item, path, type_names);
+                path.pop();
+                ParamType::List(Box::new(item))
+            }
+            Inferred::Record(fields) => {
+                let name = type_names[path.as_slice()].clone();
+                let mut fields: Vec<(&str, ParamType)> = fields
+                    .into_iter()
+                    .map(|(field, inferred)| {
+                        path.push(field);
+                        let ty = Self::finish(inferred, path, type_names);
+                        path.pop();
+                        (field, ty)
+                    })
+                    .collect();
+                fields.sort_by_key(|(_, ty)| ty.rank());
+                ParamType::Record(Record { name, fields })
+            }
+        }
+    }
+
+    /// The type of the params record
+    pub fn param_type(&self) -> &ParamType<'source> {
+        &self.ty
+    }
+
+    pub fn stack_len(&self) -> u32 {
+        self.ty.flat_len()
+    }
+
+    pub fn must_spill(&self) -> bool {
+        self.stack_len() > MAX_FLAT_PARAMS
+    }
+
+    /// Defines and exports the params record type and the named types it uses.
+    /// Returns the index of the exported params record type.
+    pub fn record_type(&self, component: &mut Component) -> u32 {
+        let mut type_count = 0;
+        match Self::gen_type(component, &mut type_count, &mut Vec::new(), &self.ty) {
+            ComponentValType::Type(index) => index,
+            ComponentValType::Primitive(_) => unreachable!("params are always a record"),
+        }
+    }
+
+    /// Defines a type, or reuses the type exported for an identical record, enum, or variant,
+    /// since a declared named type can be used more than once
+    fn gen_type<'a>(
+        component: &mut Component,
+        type_count: &mut u32,
+        exported: &mut Vec<(&'a ParamType<'source>, u32)>,
+        ty: &'a ParamType<'source>,
+    ) -> ComponentValType {
+        if let Some((_, index)) = exported.iter().find(|(exported, _)| *exported == ty) {
+            return ComponentValType::Type(*index);
+        }
+        match ty {
+            ParamType::String => ComponentValType::Primitive(PrimitiveValType::String),
+            ParamType::Bool => ComponentValType::Primitive(PrimitiveValType::Bool),
+            ParamType::Number(number) => ComponentValType::Primitive(number.primitive()),
+            ParamType::Enum(enum_) => {
+                let cases: Vec<String> = enum_
+                    .cases
+                    .iter()
+                    .map(|case| snake_to_kebab(case))
+                    .collect();
+                let mut types = ComponentTypeSection::new();
+                types
+                    .defined_type()
+                    .enum_type(cases.iter().map(|case| case.as_str()));
+                component.section(&types);
+                Self::export_type(component, type_count, exported, ty, &enum_.name)
+            }
+            ParamType::Variant(variant) => {
+                let converted_names: Vec<String> = variant
+                    .cases
+                    .iter()
+                    .map(|(case, _)| snake_to_kebab(case))
+                    .collect();
+                let payload_types: Vec<Option<ComponentValType>> = variant
+                    .cases
+                    .iter()
+                    .map(|(_, payload)| {
+                        payload
+                            .as_ref()
+                            .map(|payload| Self::gen_type(component, type_count, exported, payload))
+                    })
+                    .collect();
+                let cases = converted_names
+                    .iter()
+                    .zip(payload_types)
+                    .map(|(name, payload)| (name.as_str(), payload, None));
+                let mut types = ComponentTypeSection::new();
+                types.defined_type().variant(cases);
+                component.section(&types);
+                Self::export_type(component, type_count, exported, ty, &variant.name)
+            }
+            ParamType::Option(payload) => {
+                let payload = Self::gen_type(component, type_count, exported, payload);
+                let mut types = ComponentTypeSection::new();
+                types.defined_type().option(payload);
+                component.section(&types);
+                *type_count += 1;
+                ComponentValType::Type(*type_count - 1)
+            }
+            ParamType::List(item) => {
+                let item = Self::gen_type(component, type_count, exported, item);
+                let mut types = ComponentTypeSection::new();
+                types.defined_type().list(item);
+                component.section(&types);
+                *type_count += 1;
+                ComponentValType::Type(*type_count - 1)
+            }
+            ParamType::Record(record) => {
+                let converted_names: Vec<String> = record
+                    .fields
+                    .iter()
+                    .map(|(field, _)| snake_to_kebab(field))
+                    .collect();
+                let field_types: Vec<ComponentValType> = record
+                    .fields
+                    .iter()
+                    .map(|(_, ty)| Self::gen_type(component, type_count, exported, ty))
+                    .collect();
+                let fields = converted_names
+                    .iter()
+                    .map(|name| name.as_str())
+                    .zip(field_types);
+                let mut types = ComponentTypeSection::new();
+                types.defined_type().record(fields);
+                component.section(&types);
+                Self::export_type(component, type_count, exported, ty, &record.name)
+            }
+        }
+    }
+
+    /// Exports the type that was just defined,
+    /// since records, enums, and variants must be exported to be used by the exported function
+    fn export_type<'a>(
+        component: &mut Component,
+        type_count: &mut u32,
+        exported: &mut Vec<(&'a ParamType<'source>, u32)>,
+        ty: &'a ParamType<'source>,
+        name: &str,
+    ) -> ComponentValType {
+        let type_index = *type_count;
+        *type_count += 1;
+
+        let mut exports = ComponentExportSection::new();
+        exports.export(name, ComponentExportKind::Type, type_index, None);
+        component.section(&exports);
+        *type_count += 1;
+        exported.push((ty, *type_count - 1));
+        ComponentValType::Type(*type_count - 1)
+    }
+}
+
+/// The number type named by an annotation like `u32` in `{{ count: u32 }}`
+pub(super) fn annotation_type(
+    src: &Arc<NamedSource>,
+    annotation: &M<&str>,
+) -> Result<NumberType, TypeError> {
+    NumberType::from_name(annotation.value).ok_or_else(|| TypeError {
+        src: src.clone(),
+        span: annotation.span,
+        message: format!("Unknown number type `{}`", annotation.value),
+    })
+}
+
+pub(crate) fn snake_to_kebab(ident: &str) -> String {
+    ident.replace('_', "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use miette::NamedSource;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{parse::parse_file, Config};
+
+    #[test]
+    fn nested_record_test() {
+        let text = "{{ user.name }}{% if user.admin %}{% endif %}{% for post in posts %}{{ post.title }}{% endfor %}";
+        let source = Arc::new(NamedSource::new("nested_record_test", text));
+        let file_data = parse_file(&Config::default(), source, text).unwrap();
+        let params = Params::new(&file_data).unwrap();
+
+        let post = ParamType::Record(Record {
+            name: "post".into(),
+            fields: vec![("title", ParamType::String)],
+        });
+        let user = ParamType::Record(Record {
+            name: "user".into(),
+            fields: vec![("name", ParamType::String), ("admin", ParamType::Bool)],
+        });
+        let expected = ParamType::Record(Record {
+            name: "params".into(),
+            fields: vec![("posts", ParamType::List(Box::new(post))), ("user", user)],
+        });
+        assert_eq!(params.param_type(), &expected);
+
+        // posts: 2 values at offset 0, user.name: 2 values at offset 8, user.admin: 1 value at offset 16
+        let ParamType::Record(record) = params.param_type() else {
+            unreachable!()
+        };
+        let ParamType::Record(user) = &record.fields[1].1 else {
+            unreachable!()
+        };
+        assert_eq!(record.field_flat_index(1) + user.field_flat_index(1), 4);
+        assert_eq!(record.field_offset(1) + user.field_offset(1), 16);
+        assert_eq!(params.param_type().size(), 20);
+    }
+
+    #[test]
+    fn optional_string_test() {
+        let text =
+            "{% if title %}{{ title }}{% endif %}{{ subtitle }}{% if subtitle %}!{% endif %}";
+        let source = Arc::new(NamedSource::new("optional_string_test", text));
+        let file_data = parse_file(&Config::default(), source, text).unwrap();
+        let params = Params::new(&file_data).unwrap();
+
+        let optional_string = ParamType::Option(Box::new(ParamType::String));
+        let expected = ParamType::Record(Record {
+            name: "params".into(),
+            fields: vec![
+                ("subtitle", optional_string.clone()),
+                ("title", optional_string),
+            ],
+        });
+        assert_eq!(params.param_type(), &expected);
+
+        // each option is a discriminant followed by a string at offset 4
+        assert_eq!(params.stack_len(), 6);
+        assert_eq!(params.param_type().size(), 24);
+    }
+
+    #[test]
+    fn variant_test() {
+        let text =
+            "{% match visitor %}{% case user(name) %}{{ name }}{% case guest %}{% endmatch %}\
+            {% match visitor %}{% case bot %}{% endmatch %}";
+        let source = Arc::new(NamedSource::new("variant_test", text));
+        let file_data = parse_file(&Config::default(), source, text).unwrap();
+        let params = Params::new(&file_data).unwrap();
+
+        let visitor = ParamType::Variant(Variant {
+            name: "visitor".into(),
+            cases: vec![
+                ("user", Some(ParamType::String)),
+                ("guest", None),
+                ("bot", None),
+            ],
+        });
+        let expected = ParamType::Record(Record {
+            name: "params".into(),
+            fields: vec![("visitor", visitor)],
+        });
+        assert_eq!(params.param_type(), &expected);
+
+        // the discriminant is followed by the longest payload at offset 4
+        assert_eq!(params.stack_len(), 3);
+        assert_eq!(params.param_type().size(), 12);
+
+        assert_eq!(
+            type_error("{% match price %}{% case exact(amount) %}{{ amount: f64 }}{% endmatch %}"),
+            (
+                "`amount` is in a variant payload, which can't hold `f64`".into(),
+                SourceSpan::from((44, 6))
+            )
+        );
+    }
+
+    /// Infers the params of the text and returns the message and span of the error
+    fn type_error(text: &str) -> (String, SourceSpan) {
+        let source = Arc::new(NamedSource::new("type_error_test", text.to_owned()));
+        let file_data = parse_file(&Config::default(), source, text).unwrap();
+        let error = Params::new(&file_data).err().unwrap();
+        (error.message, error.span)
+    }
+
+    #[test]
+    fn conflict_test() {
+        assert_eq!(
+            type_error("{% for x in xs %}{% endfor %}{{ xs }}"),
+            (
+                "`xs` is used as a string here but as a list elsewhere".into(),
+                SourceSpan::from((32, 2))
+            )
+        );
+        assert_eq!(
+            type_error("{{ user }}{{ user.name }}"),
+            (
+                "`user` is used as a record here but as a string elsewhere".into(),
+                SourceSpan::from((13, 4))
+            )
+        );
+        assert_eq!(
+            type_error("{{ user.name }}{% if user %}{% endif %}"),
+            (
+                "`user` is used as a condition here but as a record elsewhere".into(),
+                SourceSpan::from((21, 4))
+            )
+        );
+        assert_eq!(
+            type_error("{% if tags %}{% endif %}{% for tag in tags %}{% endfor %}"),
+            (
+                "`tags` is used as a list here but as a condition elsewhere".into(),
+                SourceSpan::from((38, 4))
+            )
+        );
+        assert_eq!(
+            type_error("{% for post in posts %}{{ post }}{{ post.title }}{% endfor %}"),
+            (
+                "`post` is used as a record here but as a string elsewhere".into(),
+                SourceSpan::from((36, 4))
+            )
+        );
+        assert_eq!(
+            type_error("{% match status %}{% endmatch %}{{ status }}"),
+            (
+                "`status` is used as a string here but as an enum elsewhere".into(),
+                SourceSpan::from((35, 6))
+            )
+        );
+    }
+
+    #[test]
+    fn number_test() {
+        let text = "{{ count: u32 }}{% if count %}{{ count }}{% endif %}{{ price: f64 }}";
+        let source = Arc::new(NamedSource::new("number_test", text));
+        let file_data = parse_file(&Config::default(), source, text).unwrap();
+        let params = Params::new(&file_data).unwrap();
+        let expected = ParamType::Record(Record {
+            name: "params".into(),
+            fields: vec![
+                ("count", ParamType::Number(NumberType::U32)),
+                ("price", ParamType::Number(NumberType::F64)),
+            ],
+        });
+        assert_eq!(params.param_type(), &expected);
+
+        assert_eq!(
+            type_error("{{ count: u32 }}{{ count: s8 }}"),
+            (
+                "`count` is used as `s8` here but as `u32` elsewhere".into(),
+                SourceSpan::from((19, 5))
+            )
+        );
+        assert_eq!(
+            type_error("{{ count: int }}"),
+            (
+                "Unknown number type `int`".into(),
+                SourceSpan::from((10, 3))
+            )
+        );
+    }
+
+    #[test]
+    fn comparison_test() {
+        let text =
+            "{% if count > 3 %}{% endif %}{% if name == 'Sam' or role != title %}{% endif %}\
+            {{ price: f64 }}{% if not price <= 10 %}{% endif %}";
+        let source = Arc::new(NamedSource::new("comparison_test", text));
+        let file_data = parse_file(&Config::default(), source, text).unwrap();
+        let params = Params::new(&file_data).unwrap();
+        let expected = ParamType::Record(Record {
+            name: "params".into(),
+            fields: vec![
+                ("name", ParamType::String),
+                ("role", ParamType::String),
+                ("title", ParamType::String),
+                ("count", ParamType::Number(NumberType::S32)),
+                ("price", ParamType::Number(NumberType::F64)),
+            ],
+        });
+        assert_eq!(params.param_type(), &expected);
+
+        assert_eq!(
+            type_error("{{ level: u8 }}{% if level >= 300 %}{% endif %}"),
+            (
+                "`300` doesn't fit in `u8`".into(),
+                SourceSpan::from((21, 12))
+            )
+        );
+        assert_eq!(
+            type_error("{{ ratio: f32 }}{% if ratio == 16777217 %}{% endif %}"),
+            (
+                "`16777217` doesn't fit in `f32`".into(),
+                SourceSpan::from((22, 17))
+            )
+        );
+        assert_eq!(
+            type_error("{% if 'a' < 'b' %}{% endif %}"),
+            (
+                "Can't order a string literal and a string literal, only numbers".into(),
+                SourceSpan::from((6, 9))
+            )
+        );
+        assert_eq!(
+            type_error("{% if 'x' %}{% endif %}"),
+            (
+                "Literals can only be compared".into(),
+                SourceSpan::from((6, 3))
+            )
+        );
+    }
+}
@@ -1,179 +1,529 @@
-use std::collections::HashSet;
+use std::{collections::BTreeMap, sync::Arc};
 
+use miette::NamedSource;
 use wasm_encoder::{
-    DataCountSection, DataSection, Function, Instruction, MemArg, TypeSection, ValType, ComponentTypeSection, PrimitiveValType, ComponentValType, BlockType,
+    BlockType, DataCountSection, DataSection, Function, Instruction, MemArg, TypeSection, ValType,
 };
 
-use crate::{parse::Node, FileData};
+use crate::{
+    parse::{BinaryOp, CaseBranch, Expr, Filter, FilterKind, Literal, Node, Path, Term, M},
+    Config, FileData,
+};
+
+use super::{
+    numbers::FormatKind,
+    params::{
+        compare_as, Comparison, NumberType, Operand, ParamType, Params, LOOP_VARIABLES,
+        STRING_BINDING,
+    },
+};
+
+pub(super) const REALLOC_FUNC_INDEX: u32 = 0;
+pub(super) const MEMORY_INDEX: u32 = 0;
+// The end of the allocator's last allocation
+pub(super) const LAST_ALLOCATION_GLOBAL_INDEX: u32 = 0;
 
-const REALLOC_FUNC_INDEX: u32 = 0;
-const MEMORY_INDEX: u32 = 0;
+// Filter functions follow the imports, template function, and post-return function,
+// the functions formatting numbers follow the filter functions,
+// followed by the function comparing strings and then the functions of each macro
+const FIRST_FILTER_FUNC_INDEX: u32 = 4;
 
-const MAX_FLAT_PARAMS: u32 = 16;
+/// A condition and the contents rendered when it holds
+type Branch<'a, 'source> = (&'a Expr<'source>, &'a [Node<'source>]);
 
 pub struct TemplateGenerator<'source> {
     params: Params<'source>,
     file_data: &'source FileData<'source>,
+    loop_depth: u32,
+    autoescape: bool,
+    // The filters used by the template, each implemented by a function
+    filters: Vec<FilterKind>,
+    // The number formats needed by the params, each implemented by a function
+    formats: Vec<FormatKind>,
+    // The string literals used by the template, each stored in a data segment
+    literals: Vec<&'source str>,
+    text_count: u32,
+    // Whether any condition compares strings, which needs a function to do it
+    compares_strings: bool,
+    // The macros defined by the template, each implemented by a pair of functions
+    macros: Vec<Macro<'source>>,
+    // The most arguments any macro takes
+    macro_args_len: u32,
+    // The number of variables set by the template, each stored in a pair of locals
+    variable_count: u32,
+    // The variables set outside of loops and macros, which keep the value they're set to
+    // while calculating the length until the template is written
+    reused_variables: Vec<u32>,
+    // The most terms any variable is set to, each stored in a pair of locals while the value is built
+    set_terms_len: u32,
 }
 
-pub struct Params<'source> {
-    text_params: Vec<&'source str>,
-    cond_params: Vec<&'source str>,
+/// A macro, which is compiled into a function calculating the length of its contents
+/// followed by one writing them
+struct Macro<'source> {
+    name: &'source str,
+    args: &'source [M<&'source str>],
+    contents: &'source [Node<'source>],
 }
 
-impl<'source> Params<'source> {
-    pub fn new(contents: &'source Vec<Node<'source>>) -> Self {
-        let mut text_params = HashSet::new();
-        let mut cond_params = HashSet::new();
-        for node in contents {
-            Self::collect_params(node, &mut text_params, &mut cond_params);
+/// Where the flattened parts of a value are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Location {
+    /// In consecutive locals starting at the given index
+    Local(u32),
+    /// In memory at an offset from the address stored in the `base` local
+    Memory { base: u32, offset: u32 },
+    /// Computed from the counter of the loop at the given depth,
+    /// which is the `loop` record when there's no variable
+    Loop {
+        depth: u32,
+        variable: Option<LoopVariable>,
+    },
+}
+
+/// A field of `loop`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LoopVariable {
+    /// The number of the current item, starting from one
+    Index,
+    First,
+    Last,
+    /// The number of items
+    Length,
+}
+
+impl LoopVariable {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "index" => Some(LoopVariable::Index),
+            "first" => Some(LoopVariable::First),
+            "last" => Some(LoopVariable::Last),
+            "length" => Some(LoopVariable::Length),
+            _ => None,
         }
-        let mut text_params: Vec<&str> = text_params.into_iter().collect();
-        let mut cond_params: Vec<&str> = cond_params.into_iter().collect();
-        text_params.sort();
-        cond_params.sort();
-        Params {
-            text_params,
-            cond_params,
+    }
+}
+
+/// A typed value that is available to the template
+#[derive(Clone, Copy)]
+struct Value<'a, 'source> {
+    ty: &'a ParamType<'source>,
+    location: Location,
+}
+
+impl<'a, 'source> Value<'a, 'source> {
+    /// The value of a field of a record
+    fn field(&self, name: &str) -> Option<Self> {
+        let ParamType::Record(record) = self.ty else {
+            return None;
+        };
+        let index = record.field_index(name)?;
+        let location = match self.location {
+            Location::Local(start) => Location::Local(start + record.field_flat_index(index)),
+            Location::Memory { base, offset } => Location::Memory {
+                base,
+                offset: offset + record.field_offset(index),
+            },
+            Location::Loop { depth, .. } => Location::Loop {
+                depth,
+                variable: LoopVariable::from_name(name),
+            },
+        };
+        Some(Value {
+            ty: &record.fields[index].1,
+            location,
+        })
+    }
+
+    /// The payload of an option, which is only valid when the option is `some`
+    fn payload(&self) -> Self {
+        let ParamType::Option(payload) = self.ty else {
+            unreachable!("only options have payloads")
+        };
+        let location = match self.location {
+            Location::Local(start) => Location::Local(start + 1),
+            Location::Memory { base, offset } => Location::Memory {
+                base,
+                offset: offset + self.ty.payload_offset(),
+            },
+            Location::Loop { .. } => unreachable!("loop variables are numbers and bools"),
+        };
+        Value {
+            ty: payload,
+            location,
         }
     }
 
-    fn collect_params(
-        node: &'source Node<'source>,
-        text_params: &mut HashSet<&'source str>,
-        cond_params: &mut HashSet<&'source str>,
-    ) {
+    /// The payload of a variant case, which is only valid when the variant holds that case
+    fn case_payload(&self, name: &str) -> Option<Self> {
+        let ParamType::Variant(variant) = self.ty else {
+            return None;
+        };
+        let payload = variant.cases[variant.case_index(name)?].1.as_ref()?;
+        let location = match self.location {
+            Location::Local(start) => Location::Local(start + 1),
+            Location::Memory { base, offset } => Location::Memory {
+                base,
+                offset: offset + self.ty.payload_offset(),
+            },
+            Location::Loop { .. } => unreachable!("loop variables are numbers and bools"),
+        };
+        Some(Value {
+            ty: payload,
+            location,
+        })
+    }
+}
+
+/// A name bound by an enclosing loop, case, or macro, or by a variable, and the value it's bound to
+#[derive(Clone, Copy)]
+struct Binding<'a, 'source> {
+    name: &'source str,
+    value: Value<'a, 'source>,
+    // Whether the name is a loop item, which uses the loop locals for its depth
+    is_item: bool,
+}
+
+/// The number of loops enclosing the bindings in scope
+fn loop_depth(scope: &[Binding]) -> u32 {
+    scope.iter().filter(|binding| binding.is_item).count() as u32
+}
+
+impl<'source> TemplateGenerator<'source> {
+    pub fn new(
+        config: &Config,
+        params: Params<'source>,
+        file_data: &'source FileData<'source>,
+    ) -> Self {
+        let loop_depth = file_data
+            .contents
+            .iter()
+            .map(|node| Self::node_loop_depth(node, &file_data.source))
+            .max()
+            .unwrap_or(0);
+        let variable_count = file_data
+            .contents
+            .iter()
+            .map(|node| Self::node_variable_count(node, &file_data.source))
+            .sum();
+        let mut reused_variables = Vec::new();
+        for node in file_data.contents.iter() {
+            Self::collect_reused_variables(node, &file_data.source, &mut reused_variables);
+        }
+        let mut template = Self {
+            params,
+            file_data,
+            loop_depth,
+            autoescape: config.autoescape,
+            filters: Vec::new(),
+            formats: Vec::new(),
+            literals: Vec::new(),
+            text_count: 0,
+            compares_strings: false,
+            macros: Vec::new(),
+            macro_args_len: 0,
+            variable_count,
+            reused_variables,
+            set_terms_len: 0,
+        };
+        for node in file_data.contents.iter() {
+            template.collect_filters(node, &file_data.source);
+        }
+        template.macro_args_len = template
+            .macros
+            .iter()
+            .map(|macro_| macro_.args.len() as u32)
+            .max()
+            .unwrap_or(0);
+        template.filters.sort();
+        template.filters.dedup();
+        if template
+            .params
+            .param_type()
+            .contains_number(|number| !number.is_float())
+            || file_data
+                .contents
+                .iter()
+                .any(|node| Self::node_formats_loop_number(node, &file_data.source))
+        {
+            template.formats.push(FormatKind::Integer);
+        }
+        if template
+            .params
+            .param_type()
+            .contains_number(|number| number.is_float())
+        {
+            template.formats.push(FormatKind::Float);
+        }
+        template
+    }
+
+    /// Collects the filters and distinct string literals used by a node,
+    /// counts its text segments, notes whether it might compare strings, collects the macros it defines,
+    /// and notes the most terms its variables are set to
+    fn collect_filters(&mut self, node: &'source Node<'source>, source: &'source Arc<NamedSource>) {
         match node {
-            Node::Text { .. } => {}
-            Node::Parameter { name } => {
-                text_params.insert(name.value);
+            Node::Text { .. } => self.text_count += 1,
+            Node::Parameter { filters, .. } => {
+                if self.must_escape(filters) {
+                    self.filters.push(FilterKind::Escape);
+                }
+                Self::collect_chain(filters, &mut self.filters, &mut self.literals);
             }
             Node::Conditional {
-                if_kwd: _,
-                cond_ident,
+                cond,
+                elif_branches,
+                ..
+            } => {
+                let conds =
+                    std::iter::once(cond).chain(elif_branches.iter().map(|branch| &branch.cond));
+                for cond in conds {
+                    Self::collect_cond_literals(
+                        cond,
+                        &mut self.literals,
+                        &mut self.compares_strings,
+                    );
+                }
+            }
+            Node::Macro {
+                name,
+                args,
                 contents,
-                endif_kwd: _,
+                ..
             } => {
-                cond_params.insert(cond_ident.value);
-                for node in contents {
-                    Self::collect_params(node, text_params, cond_params);
+                self.macros.push(Macro {
+                    name: name.value,
+                    args,
+                    contents: &contents.contents,
+                });
+            }
+            Node::Call { args, .. } => {
+                for arg in args {
+                    Self::collect_cond_literals(
+                        arg,
+                        &mut self.literals,
+                        &mut self.compares_strings,
+                    );
+                }
+            }
+            Node::Set { terms, .. } => {
+                self.set_terms_len = self.set_terms_len.max(terms.len() as u32);
+                // variables are escaped where they're interpolated rather than when they're set
+                for term in terms {
+                    Self::collect_cond_literals(
+                        &term.value,
+                        &mut self.literals,
+                        &mut self.compares_strings,
+                    );
+                    Self::collect_chain(&term.filters, &mut self.filters, &mut self.literals);
+                }
+            }
+            Node::Loop { .. } | Node::Match { .. } | Node::Include { .. } | Node::Block { .. } => {}
+            Node::Super { .. } => unreachable!("super() is resolved along with the blocks"),
+        }
+        for (children, source) in node.children(source) {
+            for child in children {
+                self.collect_filters(child, source);
+            }
+        }
+    }
+
+    /// Collects the filters of a chain and their string arguments
+    fn collect_chain(
+        chain: &[Filter<'source>],
+        filters: &mut Vec<FilterKind>,
+        literals: &mut Vec<&'source str>,
+    ) {
+        for filter in chain {
+            if filter.kind.value != FilterKind::Safe {
+                filters.push(filter.kind.value);
+            }
+            for arg in filter.args.iter() {
+                if let Literal::String(value) = arg.value {
+                    Self::collect_literal(value, literals);
                 }
             }
         }
     }
 
-    pub fn stack_len(&self) -> u32 {
-        self.text_stack_len() + (self.cond_params.len() as u32)
+    /// Collects the string literals compared by a condition
+    /// and notes whether it might compare strings, which is when anything but integers are tested for equality
+    fn collect_cond_literals(
+        expr: &Expr<'source>,
+        literals: &mut Vec<&'source str>,
+        compares_strings: &mut bool,
+    ) {
+        match expr {
+            Expr::Path(_) => {}
+            Expr::Literal(literal) => {
+                if let Literal::String(value) = literal.value {
+                    Self::collect_literal(value, literals);
+                }
+            }
+            Expr::Not { operand, .. } => {
+                Self::collect_cond_literals(operand, literals, compares_strings)
+            }
+            Expr::Binary { op, left, right } => {
+                let is_integer = |expr: &Expr| {
+                    matches!(
+                        expr,
+                        Expr::Literal(literal) if matches!(literal.value, Literal::Integer(_))
+                    )
+                };
+                if matches!(op.value, BinaryOp::Eq | BinaryOp::Ne)
+                    && !is_integer(left)
+                    && !is_integer(right)
+                {
+                    *compares_strings = true;
+                }
+                Self::collect_cond_literals(left, literals, compares_strings);
+                Self::collect_cond_literals(right, literals, compares_strings);
+            }
+        }
     }
 
-    fn text_stack_len(&self) -> u32 {
-        2 * (self.text_params.len() as u32)
+    fn collect_literal(value: &'source str, literals: &mut Vec<&'source str>) {
+        if !literals.contains(&value) {
+            literals.push(value);
+        }
     }
 
-    fn text_mem_len(&self) -> u32 {
-        8 * (self.text_params.len() as u32)
+    /// Whether a filter chain marks its output as safe or escapes it itself
+    fn marks_escaped(filters: &[Filter<'source>]) -> bool {
+        filters
+            .iter()
+            .any(|filter| matches!(filter.kind.value, FilterKind::Safe | FilterKind::Escape))
     }
 
-    pub fn must_spill(&self) -> bool {
-        self.stack_len() > MAX_FLAT_PARAMS
+    /// Whether the parameter must be escaped after applying its filters
+    fn must_escape(&self, filters: &[Filter<'source>]) -> bool {
+        self.autoescape && !Self::marks_escaped(filters)
     }
 
-    // The number of text parameters
-    pub fn text_params_len(&self) -> usize {
-        self.text_params.len()
+    /// Whether any functions must be applied to the parameter before it's written
+    fn is_filtered(&self, filters: &[Filter<'source>]) -> bool {
+        self.must_escape(filters)
+            || filters
+                .iter()
+                .any(|filter| filter.kind.value != FilterKind::Safe)
     }
 
-    // The index in the parameters of a given text parameter name
-    pub fn text_param_index(&self, param: &str) -> usize {
-        self.text_params.binary_search(&param).unwrap()
+    /// The maximum number of loops nested within a node (including itself)
+    fn node_loop_depth(node: &Node<'source>, source: &Arc<NamedSource>) -> u32 {
+        let own = matches!(node, Node::Loop { .. }) as u32;
+        let nested = node
+            .children(source)
+            .into_iter()
+            .flat_map(|(children, source)| {
+                children
+                    .iter()
+                    .map(move |child| Self::node_loop_depth(child, source))
+            })
+            .max()
+            .unwrap_or(0);
+        own + nested
     }
 
-    // The index in the parameters of a given condition parameter name
-    pub fn cond_param_index(&self, param: &str) -> usize {
-        self.cond_params.binary_search(&param).unwrap()
+    /// Whether a node (or one within it) might format `loop.index` or `loop.length` as a string
+    fn node_formats_loop_number(node: &Node<'source>, source: &Arc<NamedSource>) -> bool {
+        let is_loop_number = |path: &Path| {
+            let names: Vec<&str> = path.segments.iter().map(|segment| segment.value).collect();
+            matches!(names.as_slice(), ["loop", "index" | "length"])
+        };
+        let is_loop_number_arg =
+            |expr: &Expr| matches!(expr, Expr::Path(path) if is_loop_number(path));
+        let formats = match node {
+            Node::Parameter { name, .. } => is_loop_number(name),
+            Node::Call { args, .. } => args.iter().any(is_loop_number_arg),
+            Node::Set { terms, .. } => terms.iter().any(|term| is_loop_number_arg(&term.value)),
+            _ => false,
+        };
+        formats
+            || node.children(source).into_iter().any(|(children, source)| {
+                children
+                    .iter()
+                    .any(|child| Self::node_formats_loop_number(child, source))
+            })
     }
 
-    pub fn record_type(&self) -> ComponentTypeSection {
-        let mut types = ComponentTypeSection::new();
-        let converted_names: Vec<String> = self.text_params.iter().map(|param: &&str| snake_to_kebab(param)).collect();
-        let text_fields = converted_names.iter().map(|param| {
-            (
-                param.as_str(),
-                ComponentValType::Primitive(PrimitiveValType::String),
-            )
-        });
-        let converted_names: Vec<String> = self.cond_params.iter().map(|param: &&str| snake_to_kebab(param)).collect();
-        let cond_fields = converted_names.iter().map(|param| {
-            (
-                param.as_str(),
-                ComponentValType::Primitive(PrimitiveValType::Bool),
-            )
-        });
-        let fields: Vec<(&str, ComponentValType)> = text_fields.chain(cond_fields).collect();
-        types.defined_type().record(fields);
-        types
+    /// Collects the variables set within a node (including itself) outside of loops and macros,
+    /// which are set to the same value when calculating the length and when writing the template
+    fn collect_reused_variables(
+        node: &Node<'source>,
+        source: &Arc<NamedSource>,
+        variables: &mut Vec<u32>,
+    ) {
+        match node {
+            Node::Set { index, .. } => variables.push(*index as u32),
+            Node::Loop { .. } | Node::Macro { .. } => return,
+            _ => {}
+        }
+        for (children, source) in node.children(source) {
+            for child in children {
+                Self::collect_reused_variables(child, source, variables);
+            }
+        }
     }
 
-    fn gen_push_text_offset(&self, func: &mut Function, text_index: u32) {
-        self.gen_push_text_field(func, text_index, 0)
+    /// The number of variables set within a node (including itself)
+    fn node_variable_count(node: &Node<'source>, source: &Arc<NamedSource>) -> u32 {
+        let own = matches!(node, Node::Set { .. }) as u32;
+        let nested: u32 = node
+            .children(source)
+            .into_iter()
+            .flat_map(|(children, source)| {
+                children
+                    .iter()
+                    .map(move |child| Self::node_variable_count(child, source))
+            })
+            .sum();
+        own + nested
     }
 
-    fn gen_push_text_len(&self, func: &mut Function, text_index: u32) {
-        self.gen_push_text_field(func, text_index, 1)
+    pub fn params(&self) -> &Params<'source> {
+        &self.params
     }
-    
-    fn gen_push_text_field(&self, func: &mut Function, text_index: u32, field: u32) {
-        if self.must_spill() {
-            // push params offset
-            func.instruction(&Instruction::LocalGet(0));
-            // push param index shift
-            let shift = (text_index * 8) + (field * 4);
-            let shift = shift.try_into().unwrap();
-            func.instruction(&Instruction::I32Const(shift));
-            // compute the final param index
-            func.instruction(&Instruction::I32Add);
-            // load the param string offset
-            func.instruction(&Instruction::I32Load(MemArg {
-                offset: 0,
-                align: 4,
-                memory_index: 0,
-            }));
-        } else {
-            let local_index = 2 * text_index + field;
-            func.instruction(&Instruction::LocalGet(local_index));
-        }
+
+    /// The filters used by the template in the order their functions are defined
+    pub fn filters(&self) -> &[FilterKind] {
+        &self.filters
     }
 
-    fn gen_push_cond(&self, func: &mut Function, cond_index: u32) {
-        if self.must_spill() {
-            // push params offset
-            func.instruction(&Instruction::LocalGet(0));
-            // push param index shift
-            let shift = self.text_mem_len() + cond_index;
-            let shift = shift.try_into().unwrap();
-            func.instruction(&Instruction::I32Const(shift));
-            // compute the final param index
-            func.instruction(&Instruction::I32Add);
-            // load the param string offset
-            func.instruction(&Instruction::I32Load8U(MemArg {
-                offset: 0,
-                align: 1,
-                memory_index: 0,
-            }));
-        } else {
-            let local_index = self.text_stack_len() + cond_index;
-            func.instruction(&Instruction::LocalGet(local_index));
-        }
+    fn filter_func_index(&self, kind: FilterKind) -> u32 {
+        let position = self.filters.binary_search(&kind).unwrap();
+        FIRST_FILTER_FUNC_INDEX + position as u32
     }
-}
 
-impl<'source> TemplateGenerator<'source> {
-    pub fn new(params: Params<'source>, file_data: &'source FileData<'source>) -> Self {
-        Self { params, file_data }
+    /// The number formats used by the template in the order their functions are defined
+    pub fn formats(&self) -> &[FormatKind] {
+        &self.formats
     }
 
-    pub fn params(&self) -> &Params<'source> {
-        &self.params
+    fn format_func_index(&self, kind: FormatKind) -> u32 {
+        let position = self.formats.binary_search(&kind).unwrap();
+        FIRST_FILTER_FUNC_INDEX + self.filters.len() as u32 + position as u32
+    }
+
+    /// Whether the template needs the function comparing strings
+    pub fn compares_strings(&self) -> bool {
+        self.compares_strings
+    }
+
+    fn compare_func_index(&self) -> u32 {
+        FIRST_FILTER_FUNC_INDEX + self.filters.len() as u32 + self.formats.len() as u32
+    }
+
+    /// The number of macros, which each need a function calculating their length and one writing them
+    pub fn macro_count(&self) -> usize {
+        self.macros.len()
+    }
+
+    fn macro_len_func_index(&self, macro_index: usize) -> u32 {
+        self.compare_func_index() + self.compares_strings as u32 + 2 * macro_index as u32
+    }
+
+    fn macro_write_func_index(&self, macro_index: usize) -> u32 {
+        self.macro_len_func_index(macro_index) + 1
     }
 
     fn arguments_len(&self) -> u32 {
@@ -184,69 +534,159 @@ impl<'source> TemplateGenerator<'source> {
         }
     }
 
+    // The address of a string literal copied out of its data segment
+    fn literal_local(&self, literal_index: u32) -> u32 {
+        self.arguments_len() + literal_index
+    }
+
+    // The address a macro function starts writing at
+    fn macro_cursor_local(&self) -> u32 {
+        self.arguments_len() + (self.literals.len() as u32)
+    }
+
+    // The address of an argument of a macro function, followed by its length
+    fn macro_arg_local(&self, arg_index: u32) -> u32 {
+        self.macro_cursor_local() + 1 + (2 * arg_index)
+    }
+
+    /// The number of locals shared by the template function and the macro functions,
+    /// which are the arguments of the template function and the rest of the parameters of the macro functions
+    fn frame_len(&self) -> u32 {
+        if self.macros.is_empty() {
+            self.macro_cursor_local()
+        } else {
+            self.macro_arg_local(self.macro_args_len)
+        }
+    }
+
     fn result_len_local(&self) -> u32 {
-        self.arguments_len() + 0
+        self.frame_len()
     }
 
     fn result_addr_local(&self) -> u32 {
-        self.arguments_len() + 1
+        self.frame_len() + 1
     }
 
     fn return_area_local(&self) -> u32 {
-        self.arguments_len() + 2
+        self.frame_len() + 2
     }
 
     fn result_cursor_local(&self) -> u32 {
-        self.arguments_len() + 3
+        self.frame_len() + 3
+    }
+
+    // The length of the most recent filtered parameter
+    fn filtered_len_local(&self) -> u32 {
+        self.frame_len() + 4
+    }
+
+    // The loop counter of the loop at the given nesting depth
+    fn loop_index_local(&self, depth: u32) -> u32 {
+        self.frame_len() + 5 + (4 * depth)
+    }
+
+    // The address of the current item of the loop at the given nesting depth
+    fn loop_item_local(&self, depth: u32) -> u32 {
+        self.frame_len() + 6 + (4 * depth)
+    }
+
+    // The accumulated length of the loop at the given nesting depth
+    fn loop_len_local(&self, depth: u32) -> u32 {
+        self.frame_len() + 7 + (4 * depth)
+    }
+
+    // The number of items in the list of the loop at the given nesting depth
+    fn loop_count_local(&self, depth: u32) -> u32 {
+        self.frame_len() + 8 + (4 * depth)
     }
 
+    // The length of the contents rendered by the most recent match
+    fn match_len_local(&self) -> u32 {
+        self.frame_len() + 5 + (4 * self.loop_depth)
+    }
+
+    // The address of the value of a variable, followed by its length
+    fn variable_local(&self, variable_index: u32) -> u32 {
+        self.frame_len() + 6 + (4 * self.loop_depth) + (2 * variable_index)
+    }
+
+    // The address of a term of the variable being set, followed by its length
+    fn term_local(&self, term_index: u32) -> u32 {
+        self.variable_local(self.variable_count) + (2 * term_index)
+    }
+
+    /// The number of locals declared by each function, after the ones shared by them all
     fn locals_len(&self) -> u32 {
-        4
+        6 + (4 * self.loop_depth) + (2 * self.variable_count) + (2 * self.set_terms_len)
+    }
+
+    /// The core types of the arguments of the template function
+    fn argument_types(&self) -> Vec<ValType> {
+        let mut params = Vec::new();
+        if self.params.must_spill() {
+            params.push(ValType::I32);
+        } else {
+            self.params.param_type().flat_types(&mut params);
+        }
+        params
     }
 
     pub fn gen_core_type(&self, types: &mut TypeSection) {
-        let params = vec![ValType::I32; self.arguments_len() as usize];
+        let params = self.argument_types();
         let results = vec![ValType::I32];
         types.function(params, results);
     }
 
+    /// Adds the core type of the macro functions, which take every local shared by the functions
+    /// and return the length of the contents they calculate or write
+    pub fn gen_macro_type(&self, types: &mut TypeSection) {
+        let mut params = self.argument_types();
+        params.extend((self.arguments_len()..self.frame_len()).map(|_| ValType::I32));
+        types.function(params, vec![ValType::I32]);
+    }
+
     pub fn gen_data(&self) -> (DataCountSection, DataSection) {
         let mut count = 0;
         let mut data = DataSection::new();
 
         for node in self.file_data.contents.iter() {
-            Self::collect_data(node, &mut count, &mut data);
+            Self::collect_data(node, &self.file_data.source, &mut count, &mut data);
+        }
+        for literal in self.literals.iter() {
+            data.passive(literal.bytes());
+            count += 1;
         }
 
         let count = DataCountSection { count };
         (count, data)
     }
 
-    fn collect_data(node: &Node<'source>, count: &mut u32, data: &mut DataSection) {
-        match node {
-            Node::Text { index: _, text } => {
-                data.passive(text.value.bytes());
-                *count += 1;
-            }
-            Node::Parameter { name: _ } => {}
-            Node::Conditional {
-                if_kwd: _,
-                cond_ident: _,
-                contents,
-                endif_kwd: _,
-            } => {
-                for node in contents {
-                    Self::collect_data(node, count, data);
-                }
+    fn collect_data(
+        node: &Node<'source>,
+        source: &Arc<NamedSource>,
+        count: &mut u32,
+        data: &mut DataSection,
+    ) {
+        if let Node::Text { index: _, text } = node {
+            data.passive(text.value.bytes());
+            *count += 1;
+        }
+        for (children, source) in node.children(source) {
+            for child in children {
+                Self::collect_data(child, source, count, data);
             }
         }
     }
 
     pub fn gen_core_function(&self) -> Function {
-        // Local variables
-        let locals = vec![(self.locals_len(), ValType::I32)];
+        // Local variables, starting with the shared ones that aren't arguments
+        let locals = vec![(
+            self.frame_len() - self.arguments_len() + self.locals_len(),
+            ValType::I32,
+        )];
         let mut func = Function::new(locals);
 
+        self.gen_init_literals(&mut func);
         self.gen_calculate_len(&mut func);
         self.gen_allocate_results(&mut func);
         self.gen_init_cursor(&mut func);
@@ -257,39 +697,171 @@ impl<'source> TemplateGenerator<'source> {
         func
     }
 
+    /// Generates the function calculating the length of the contents of a macro
+    pub fn gen_macro_len_function(&self, macro_index: usize) -> Function {
+        let locals = vec![(self.locals_len(), ValType::I32)];
+        let mut func = Function::new(locals);
+
+        let mut scope = self.macro_scope(macro_index);
+        self.gen_calculate_sequence_len(&mut func, &mut scope, self.macros[macro_index].contents);
+
+        func.instruction(&Instruction::End);
+        func
+    }
+
+    /// Generates the function writing the contents of a macro, which returns their length
+    pub fn gen_macro_write_function(&self, macro_index: usize) -> Function {
+        let locals = vec![(self.locals_len(), ValType::I32)];
+        let mut func = Function::new(locals);
+
+        func.instruction(&Instruction::LocalGet(self.macro_cursor_local()));
+        func.instruction(&Instruction::LocalSet(self.result_cursor_local()));
+        let mut scope = self.macro_scope(macro_index);
+        self.gen_write_sequence_template(&mut func, &mut scope, self.macros[macro_index].contents);
+
+        // push how far the cursor moved
+        func.instruction(&Instruction::LocalGet(self.result_cursor_local()));
+        func.instruction(&Instruction::LocalGet(self.macro_cursor_local()));
+        func.instruction(&Instruction::I32Sub);
+        func.instruction(&Instruction::End);
+        func
+    }
+
+    /// Binds the arguments of a macro to the locals they're passed in
+    fn macro_scope(&self, macro_index: usize) -> Vec<Binding<'_, 'source>> {
+        self.macros[macro_index]
+            .args
+            .iter()
+            .enumerate()
+            .map(|(arg_index, arg)| Binding {
+                name: arg.value,
+                value: Value {
+                    ty: &STRING_BINDING,
+                    location: Location::Local(self.macro_arg_local(arg_index as u32)),
+                },
+                is_item: false,
+            })
+            .collect()
+    }
+
+    /// Copies each string literal into memory so it can be passed to filters
+    fn gen_init_literals(&self, func: &mut Function) {
+        for (literal_index, literal) in self.literals.iter().enumerate() {
+            let literal_index = literal_index as u32;
+            let length = literal.len() as i32;
+            // allocate the literal
+            func.instruction(&Instruction::I32Const(0));
+            func.instruction(&Instruction::I32Const(0));
+            func.instruction(&Instruction::I32Const(1));
+            func.instruction(&Instruction::I32Const(length));
+            func.instruction(&Instruction::Call(REALLOC_FUNC_INDEX));
+            func.instruction(&Instruction::LocalSet(self.literal_local(literal_index)));
+            // copy the literal data segment into it
+            func.instruction(&Instruction::LocalGet(self.literal_local(literal_index)));
+            func.instruction(&Instruction::I32Const(0));
+            func.instruction(&Instruction::I32Const(length));
+            func.instruction(&Instruction::MemoryInit {
+                mem: 0,
+                data_index: self.text_count + literal_index,
+            });
+        }
+    }
+
     fn gen_calculate_len(&self, func: &mut Function) {
-        self.gen_calculate_sequence_len(func, self.file_data.contents.as_slice());
+        let mut scope = Vec::new();
+        self.gen_calculate_sequence_len(func, &mut scope, self.file_data.contents.as_slice());
         // Store the calculated length
         func.instruction(&Instruction::LocalSet(self.result_len_local()));
     }
 
-    fn gen_calculate_sequence_len(&self, func: &mut Function, sequence: &[Node<'source>]) {
+    fn gen_calculate_sequence_len<'a>(
+        &'a self,
+        func: &mut Function,
+        scope: &mut Vec<Binding<'a, 'source>>,
+        sequence: &[Node<'source>],
+    ) {
+        let scope_len = scope.len();
         let mut base_length = 0;
-        let mut param_counts = vec![0; self.params.text_params_len()];
+        let mut param_counts: BTreeMap<Location, i32> = BTreeMap::new();
         let mut prior_exists = false;
         for node in sequence.iter() {
             match node {
                 Node::Text { index: _, text } => {
                     base_length += text.value.len() as i32;
                 }
-                Node::Parameter { name } => {
-                    let index = self.params.text_param_index(&name.value);
-                    param_counts[index] += 1;
+                Node::Parameter { name, filters, .. } => {
+                    let value = self.resolve(scope, name);
+                    if !self.is_filtered(filters) && matches!(value.ty, ParamType::String) {
+                        *param_counts.entry(value.location).or_default() += 1;
+                    } else {
+                        // keep only the length of the filtered string,
+                        // freeing what the filters allocated since it's filtered again when it's written
+                        func.instruction(&Instruction::GlobalGet(LAST_ALLOCATION_GLOBAL_INDEX));
+                        self.gen_push_filtered(func, value, filters);
+                        func.instruction(&Instruction::LocalSet(self.filtered_len_local()));
+                        func.instruction(&Instruction::Drop);
+                        func.instruction(&Instruction::GlobalSet(LAST_ALLOCATION_GLOBAL_INDEX));
+                        func.instruction(&Instruction::LocalGet(self.filtered_len_local()));
+
+                        if prior_exists {
+                            func.instruction(&Instruction::I32Add);
+                        }
+
+                        prior_exists = true;
+                    }
                 }
                 Node::Conditional {
                     if_kwd: _,
-                    cond_ident,
+                    cond,
                     contents,
+                    elif_branches,
+                    else_branch,
                     endif_kwd: _,
                 } => {
-                    let cond_index = self.params.cond_param_index(cond_ident.value) as u32;
+                    let branches: Vec<Branch> = std::iter::once((cond, contents.as_slice()))
+                        .chain(
+                            elif_branches
+                                .iter()
+                                .map(|branch| (&branch.cond, branch.contents.as_slice())),
+                        )
+                        .collect();
+                    let else_contents = else_branch
+                        .as_ref()
+                        .map(|branch| branch.contents.as_slice());
+                    self.gen_calculate_branches_len(func, scope, &branches, else_contents);
+
+                    if prior_exists {
+                        func.instruction(&Instruction::I32Add);
+                    }
+
+                    prior_exists = true;
+                }
+                Node::Loop {
+                    for_kwd: _,
+                    item_ident,
+                    in_kwd: _,
+                    list_path,
+                    contents,
+                    endfor_kwd: _,
+                } => {
+                    let depth = loop_depth(scope);
+                    let list = self.resolve(scope, list_path);
 
-                    self.params.gen_push_cond(func, cond_index);
-                    func.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
-                    self.gen_calculate_sequence_len(func, &contents);
-                    func.instruction(&Instruction::Else);
+                    // reset the accumulated length
                     func.instruction(&Instruction::I32Const(0));
-                    func.instruction(&Instruction::End);
+                    func.instruction(&Instruction::LocalSet(self.loop_len_local(depth)));
+
+                    let item = self.gen_loop_start(func, list, depth);
+                    self.push_loop_bindings(scope, item_ident.value, item, depth);
+                    self.gen_calculate_sequence_len(func, scope, contents);
+                    scope.truncate(scope.len() - 2);
+                    // add the length of this iteration to the accumulated length
+                    func.instruction(&Instruction::LocalGet(self.loop_len_local(depth)));
+                    func.instruction(&Instruction::I32Add);
+                    func.instruction(&Instruction::LocalSet(self.loop_len_local(depth)));
+                    self.gen_loop_end(func, depth);
+
+                    func.instruction(&Instruction::LocalGet(self.loop_len_local(depth)));
 
                     if prior_exists {
                         func.instruction(&Instruction::I32Add);
@@ -297,8 +869,59 @@ impl<'source> TemplateGenerator<'source> {
 
                     prior_exists = true;
                 }
+                Node::Match { path, cases, .. } => {
+                    // nothing is rendered if no case matches
+                    func.instruction(&Instruction::I32Const(0));
+                    func.instruction(&Instruction::LocalSet(self.match_len_local()));
+
+                    let value = self.resolve(scope, path);
+                    self.gen_match_start(func, value, cases);
+                    for (index, case) in cases.iter().enumerate() {
+                        self.push_case_binding(scope, value, case);
+                        self.gen_calculate_sequence_len(func, scope, &case.contents);
+                        if case.binding.is_some() {
+                            scope.pop();
+                        }
+                        func.instruction(&Instruction::LocalSet(self.match_len_local()));
+                        self.gen_match_case_end(func, index, cases.len());
+                    }
+
+                    func.instruction(&Instruction::LocalGet(self.match_len_local()));
+
+                    if prior_exists {
+                        func.instruction(&Instruction::I32Add);
+                    }
+
+                    prior_exists = true;
+                }
+                Node::Super { .. } => unreachable!("super() is resolved along with the blocks"),
+                Node::Include { file, .. } | Node::Block { contents: file, .. } => {
+                    self.gen_calculate_sequence_len(func, scope, &file.contents);
+
+                    if prior_exists {
+                        func.instruction(&Instruction::I32Add);
+                    }
+
+                    prior_exists = true;
+                }
+                Node::Macro { .. } => {}
+                Node::Call { name, args } => {
+                    let macro_index = self.gen_push_call(func, scope, name.value, args);
+                    func.instruction(&Instruction::Call(self.macro_len_func_index(macro_index)));
+
+                    if prior_exists {
+                        func.instruction(&Instruction::I32Add);
+                    }
+
+                    prior_exists = true;
+                }
+                Node::Set {
+                    name, index, terms, ..
+                } => self.gen_set(func, scope, name.value, *index as u32, terms),
             }
         }
+        // the variables set in the sequence go out of scope
+        scope.truncate(scope_len);
 
         // push the base length
         func.instruction(&Instruction::I32Const(base_length));
@@ -308,20 +931,718 @@ impl<'source> TemplateGenerator<'source> {
         }
 
         // accumulate the dynamic part of the length
-        for (index, count) in param_counts.iter().enumerate() {
-            if *count > 0 {
-                // load the length of the parameter
-                self.params.gen_push_text_len(func, index as u32);
-                // push the count of parameter occurrences
-                func.instruction(&Instruction::I32Const(*count));
-                // multiple the length by the occurrences
-                func.instruction(&Instruction::I32Mul);
-                // add this length addition to the total length
+        for (location, count) in param_counts {
+            // load the length of the parameter
+            self.gen_load_i32(func, location, 1);
+            // push the count of parameter occurrences
+            func.instruction(&Instruction::I32Const(count));
+            // multiple the length by the occurrences
+            func.instruction(&Instruction::I32Mul);
+            // add this length addition to the total length
+            func.instruction(&Instruction::I32Add);
+        }
+    }
+
+    /// Pushes the length of the first branch whose condition holds,
+    /// falling back to the else contents (or zero) when none do.
+    fn gen_calculate_branches_len<'a>(
+        &'a self,
+        func: &mut Function,
+        scope: &mut Vec<Binding<'a, 'source>>,
+        branches: &[Branch<'_, 'source>],
+        else_contents: Option<&[Node<'source>]>,
+    ) {
+        match branches.split_first() {
+            Some(((cond, contents), rest)) => {
+                self.gen_push_cond(func, scope, cond);
+                func.instruction(&Instruction::If(BlockType::Result(ValType::I32)));
+                self.gen_calculate_sequence_len(func, scope, contents);
+                func.instruction(&Instruction::Else);
+                self.gen_calculate_branches_len(func, scope, rest, else_contents);
+                func.instruction(&Instruction::End);
+            }
+            None => match else_contents {
+                Some(contents) => self.gen_calculate_sequence_len(func, scope, contents),
+                None => {
+                    func.instruction(&Instruction::I32Const(0));
+                }
+            },
+        }
+    }
+
+    /// Finds the value a path refers to,
+    /// starting from either a name bound in scope, like a loop item or a variable, or the params record
+    fn resolve<'a>(
+        &'a self,
+        scope: &[Binding<'a, 'source>],
+        path: &Path<'source>,
+    ) -> Value<'a, 'source> {
+        let root = path.root().value;
+        let mut value = match scope.iter().rev().find(|binding| binding.name == root) {
+            Some(binding) => binding.value,
+            None => {
+                let params = Value {
+                    ty: self.params.param_type(),
+                    location: if self.params.must_spill() {
+                        Location::Memory { base: 0, offset: 0 }
+                    } else {
+                        Location::Local(0)
+                    },
+                };
+                params
+                    .field(root)
+                    .expect("params are inferred from their uses")
+            }
+        };
+        for segment in &path.segments[1..] {
+            value = value
+                .field(segment.value)
+                .expect("params are inferred from their uses");
+        }
+        value
+    }
+
+    /// Pushes the offset and length of a string parameter after applying filters to it
+    /// and escaping it if required
+    fn gen_push_filtered(
+        &self,
+        func: &mut Function,
+        value: Value<'_, 'source>,
+        filters: &[Filter<'source>],
+    ) {
+        self.gen_push_string(func, value);
+        self.gen_apply_filters(func, filters);
+        if self.must_escape(filters) {
+            func.instruction(&Instruction::Call(
+                self.filter_func_index(FilterKind::Escape),
+            ));
+        }
+    }
+
+    /// Applies filters to the string whose offset and length are on the stack
+    fn gen_apply_filters(&self, func: &mut Function, filters: &[Filter<'source>]) {
+        for filter in filters {
+            if filter.kind.value == FilterKind::Safe {
+                continue;
+            }
+            for arg in filter.args.iter() {
+                match arg.value {
+                    Literal::String(value) => self.gen_push_literal(func, value),
+                    Literal::Integer(value) => {
+                        // filters validate that integer arguments fit in a u32
+                        func.instruction(&Instruction::I32Const(value as u32 as i32));
+                    }
+                }
+            }
+            func.instruction(&Instruction::Call(
+                self.filter_func_index(filter.kind.value),
+            ));
+        }
+    }
+
+    /// Stores the value of a variable in its locals and binds its name to them for the rest of the sequence.
+    /// The terms are each kept in their own locals until their lengths are known,
+    /// so they can be copied into a single allocation of the whole value.
+    fn gen_set<'a>(
+        &'a self,
+        func: &mut Function,
+        scope: &mut Vec<Binding<'a, 'source>>,
+        name: &'source str,
+        variable_index: u32,
+        terms: &[Term<'source>],
+    ) {
+        let addr_local = self.variable_local(variable_index);
+        let len_local = addr_local + 1;
+        for (term_index, term) in terms.iter().enumerate() {
+            match &term.value {
+                Expr::Path(path) => self.gen_push_string(func, self.resolve(scope, path)),
+                Expr::Literal(literal) => {
+                    let Literal::String(value) = literal.value else {
+                        unreachable!("terms are parameters or string literals")
+                    };
+                    self.gen_push_literal(func, value);
+                }
+                _ => unreachable!("terms are parameters or string literals"),
+            }
+            self.gen_apply_filters(func, &term.filters);
+            // a single term is the value itself
+            let term_local = if terms.len() == 1 {
+                addr_local
+            } else {
+                self.term_local(term_index as u32)
+            };
+            func.instruction(&Instruction::LocalSet(term_local + 1));
+            func.instruction(&Instruction::LocalSet(term_local));
+        }
+        if terms.len() > 1 {
+            // allocate the whole value
+            func.instruction(&Instruction::I32Const(0));
+            func.instruction(&Instruction::I32Const(0));
+            func.instruction(&Instruction::I32Const(1));
+            func.instruction(&Instruction::LocalGet(self.term_local(0) + 1));
+            for term_index in 1..terms.len() as u32 {
+                func.instruction(&Instruction::LocalGet(self.term_local(term_index) + 1));
+                func.instruction(&Instruction::I32Add);
+            }
+            func.instruction(&Instruction::Call(REALLOC_FUNC_INDEX));
+            func.instruction(&Instruction::LocalSet(addr_local));
+
+            // copy each term after the ones before it, counting the length copied so far
+            func.instruction(&Instruction::I32Const(0));
+            func.instruction(&Instruction::LocalSet(len_local));
+            for term_index in 0..terms.len() as u32 {
+                let term_local = self.term_local(term_index);
+                func.instruction(&Instruction::LocalGet(addr_local));
+                func.instruction(&Instruction::LocalGet(len_local));
+                func.instruction(&Instruction::I32Add);
+                func.instruction(&Instruction::LocalGet(term_local));
+                func.instruction(&Instruction::LocalGet(term_local + 1));
+                func.instruction(&Instruction::MemoryCopy {
+                    src_mem: MEMORY_INDEX,
+                    dst_mem: MEMORY_INDEX,
+                });
+
+                func.instruction(&Instruction::LocalGet(len_local));
+                func.instruction(&Instruction::LocalGet(term_local + 1));
+                func.instruction(&Instruction::I32Add);
+                func.instruction(&Instruction::LocalSet(len_local));
+            }
+        }
+        self.bind_variable(scope, name, variable_index);
+    }
+
+    /// Binds the name of a variable to its locals for the rest of the sequence
+    fn bind_variable<'a>(
+        &'a self,
+        scope: &mut Vec<Binding<'a, 'source>>,
+        name: &'source str,
+        variable_index: u32,
+    ) {
+        scope.push(Binding {
+            name,
+            value: Value {
+                ty: &STRING_BINDING,
+                location: Location::Local(self.variable_local(variable_index)),
+            },
+            is_item: false,
+        });
+    }
+
+    /// Pushes the offset and length of a string literal, which was copied into memory up front
+    fn gen_push_literal(&self, func: &mut Function, value: &str) {
+        let literal_index = self.literals.iter().position(|l| *l == value).unwrap();
+        func.instruction(&Instruction::LocalGet(
+            self.literal_local(literal_index as u32),
+        ));
+        func.instruction(&Instruction::I32Const(value.len() as i32));
+    }
+
+    /// Pushes the offset and length of a string,
+    /// or of an optional string where `none` is treated as empty
+    fn gen_push_string(&self, func: &mut Function, value: Value<'_, 'source>) {
+        match value.ty {
+            ParamType::String => {
+                self.gen_load_i32(func, value.location, 0);
+                self.gen_load_i32(func, value.location, 1);
+            }
+            ParamType::Option(_) => {
+                let payload = value.payload();
+                for index in 0..2 {
+                    self.gen_load_i32(func, payload.location, index);
+                    func.instruction(&Instruction::I32Const(0));
+                    self.gen_load_u8(func, value.location);
+                    func.instruction(&Instruction::Select);
+                }
+            }
+            ParamType::Number(number) => {
+                self.gen_load_number(func, *number, value.location);
+                let kind = if number.is_float() {
+                    if *number == NumberType::F32 {
+                        func.instruction(&Instruction::F64PromoteF32);
+                    }
+                    FormatKind::Float
+                } else {
+                    match number {
+                        NumberType::U64 | NumberType::S64 => {}
+                        _ if number.is_signed() => {
+                            func.instruction(&Instruction::I64ExtendI32S);
+                        }
+                        _ => {
+                            func.instruction(&Instruction::I64ExtendI32U);
+                        }
+                    }
+                    func.instruction(&Instruction::I32Const(number.is_signed() as i32));
+                    FormatKind::Integer
+                };
+                func.instruction(&Instruction::Call(self.format_func_index(kind)));
+            }
+            _ => unreachable!("params are inferred from their uses"),
+        }
+    }
+
+    /// Pushes a number as its flattened core type
+    fn gen_load_number(&self, func: &mut Function, number: NumberType, location: Location) {
+        let (base, offset) = match location {
+            Location::Local(index) => {
+                func.instruction(&Instruction::LocalGet(index));
+                return;
+            }
+            Location::Memory { base, offset } => (base, offset),
+            Location::Loop { depth, variable } => {
+                self.gen_load_loop_variable(func, depth, variable);
+                return;
+            }
+        };
+        let mem_arg = MemArg {
+            offset: offset.into(),
+            align: number.size().trailing_zeros(),
+            memory_index: MEMORY_INDEX,
+        };
+        func.instruction(&Instruction::LocalGet(base));
+        func.instruction(&match number {
+            NumberType::U8 => Instruction::I32Load8U(mem_arg),
+            NumberType::S8 => Instruction::I32Load8S(mem_arg),
+            NumberType::U16 => Instruction::I32Load16U(mem_arg),
+            NumberType::S16 => Instruction::I32Load16S(mem_arg),
+            NumberType::U32 | NumberType::S32 => Instruction::I32Load(mem_arg),
+            NumberType::U64 | NumberType::S64 => Instruction::I64Load(mem_arg),
+            NumberType::F32 => Instruction::F32Load(mem_arg),
+            NumberType::F64 => Instruction::F64Load(mem_arg),
+        });
+    }
+
+    /// Pushes the value of a loop variable, computed from the loop counter and list length
+    fn gen_load_loop_variable(
+        &self,
+        func: &mut Function,
+        depth: u32,
+        variable: Option<LoopVariable>,
+    ) {
+        let counter = self.loop_index_local(depth);
+        match variable.expect("loop variables are checked with the params") {
+            LoopVariable::Index => {
+                func.instruction(&Instruction::LocalGet(counter));
+                func.instruction(&Instruction::I32Const(1));
                 func.instruction(&Instruction::I32Add);
             }
+            LoopVariable::First => {
+                func.instruction(&Instruction::LocalGet(counter));
+                func.instruction(&Instruction::I32Eqz);
+            }
+            LoopVariable::Last => {
+                func.instruction(&Instruction::LocalGet(counter));
+                func.instruction(&Instruction::I32Const(1));
+                func.instruction(&Instruction::I32Add);
+                func.instruction(&Instruction::LocalGet(self.loop_count_local(depth)));
+                func.instruction(&Instruction::I32Eq);
+            }
+            LoopVariable::Length => {
+                func.instruction(&Instruction::LocalGet(self.loop_count_local(depth)));
+            }
+        }
+    }
+
+    /// Pushes the flattened value at `index` of an i32 pair (string or list)
+    fn gen_load_i32(&self, func: &mut Function, location: Location, index: u32) {
+        match location {
+            Location::Local(start) => {
+                func.instruction(&Instruction::LocalGet(start + index));
+            }
+            Location::Memory { base, offset } => {
+                func.instruction(&Instruction::LocalGet(base));
+                func.instruction(&Instruction::I32Load(MemArg {
+                    offset: (offset + 4 * index).into(),
+                    align: 2,
+                    memory_index: MEMORY_INDEX,
+                }));
+            }
+            Location::Loop { .. } => unreachable!("loop variables are numbers and bools"),
+        }
+    }
+
+    /// Pushes a value that is flattened into one i32 but stored as a byte (bool or discriminant)
+    fn gen_load_u8(&self, func: &mut Function, location: Location) {
+        match location {
+            Location::Local(index) => {
+                func.instruction(&Instruction::LocalGet(index));
+            }
+            Location::Memory { base, offset } => {
+                func.instruction(&Instruction::LocalGet(base));
+                func.instruction(&Instruction::I32Load8U(MemArg {
+                    offset: offset.into(),
+                    align: 0,
+                    memory_index: MEMORY_INDEX,
+                }));
+            }
+            Location::Loop { depth, variable } => {
+                self.gen_load_loop_variable(func, depth, variable)
+            }
+        }
+    }
+
+    /// Pushes whether a value is truthy.
+    /// Strings and lists are truthy when they aren't empty, options when they're `some`,
+    /// and numbers when they aren't zero.
+    fn gen_push_truthy(&self, func: &mut Function, value: Value<'_, 'source>) {
+        match value.ty {
+            ParamType::Number(number) => {
+                self.gen_load_number(func, *number, value.location);
+                match number {
+                    NumberType::U64 | NumberType::S64 => {
+                        func.instruction(&Instruction::I64Const(0));
+                        func.instruction(&Instruction::I64Ne);
+                    }
+                    NumberType::F32 => {
+                        func.instruction(&Instruction::F32Const(0.0));
+                        func.instruction(&Instruction::F32Ne);
+                    }
+                    NumberType::F64 => {
+                        func.instruction(&Instruction::F64Const(0.0));
+                        func.instruction(&Instruction::F64Ne);
+                    }
+                    _ => {}
+                }
+            }
+            ParamType::Bool | ParamType::Option(_) => {
+                self.gen_load_u8(func, value.location);
+            }
+            ParamType::String | ParamType::List(_) => {
+                self.gen_load_i32(func, value.location, 1);
+            }
+            ParamType::Record(_) => {
+                func.instruction(&Instruction::I32Const(1));
+            }
+            ParamType::Enum(_) | ParamType::Variant(_) => {
+                unreachable!("enums and variants can only be matched")
+            }
+        }
+    }
+
+    /// Pushes whether a condition holds, which is nonzero when it does
+    fn gen_push_cond<'a>(
+        &'a self,
+        func: &mut Function,
+        scope: &[Binding<'a, 'source>],
+        expr: &Expr<'source>,
+    ) {
+        match expr {
+            Expr::Path(path) => {
+                let value = self.resolve(scope, path);
+                self.gen_push_truthy(func, value);
+            }
+            Expr::Literal(_) => unreachable!("literals are only compared"),
+            Expr::Not { operand, .. } => {
+                self.gen_push_cond(func, scope, operand);
+                func.instruction(&Instruction::I32Eqz);
+            }
+            Expr::Binary { op, left, right } => match op.value {
+                BinaryOp::Or => {
+                    self.gen_push_cond(func, scope, left);
+                    self.gen_push_cond(func, scope, right);
+                    func.instruction(&Instruction::I32Or);
+                }
+                BinaryOp::And => {
+                    // neither side is false
+                    self.gen_push_cond(func, scope, left);
+                    func.instruction(&Instruction::I32Eqz);
+                    self.gen_push_cond(func, scope, right);
+                    func.instruction(&Instruction::I32Eqz);
+                    func.instruction(&Instruction::I32Or);
+                    func.instruction(&Instruction::I32Eqz);
+                }
+                op => self.gen_push_comparison(func, scope, op, left, right),
+            },
+        }
+    }
+
+    /// Pushes whether a comparison holds, which is one when it does
+    fn gen_push_comparison<'a>(
+        &'a self,
+        func: &mut Function,
+        scope: &[Binding<'a, 'source>],
+        op: BinaryOp,
+        left: &Expr<'source>,
+        right: &Expr<'source>,
+    ) {
+        let operand = |expr| Operand::of(expr, |path| self.resolve(scope, path).ty);
+        let comparison = compare_as(op, operand(left), operand(right))
+            .expect("comparisons are checked with the params");
+        match comparison {
+            Comparison::Strings => {
+                for expr in [left, right] {
+                    match expr {
+                        Expr::Path(path) => self.gen_push_string(func, self.resolve(scope, path)),
+                        Expr::Literal(literal) => {
+                            let Literal::String(value) = literal.value else {
+                                unreachable!("only string literals are compared with strings")
+                            };
+                            self.gen_push_literal(func, value);
+                        }
+                        _ => unreachable!("only strings are compared with strings"),
+                    }
+                }
+                func.instruction(&Instruction::Call(self.compare_func_index()));
+                if op == BinaryOp::Ne {
+                    func.instruction(&Instruction::I32Eqz);
+                }
+            }
+            Comparison::Numbers(number) => {
+                for expr in [left, right] {
+                    match expr {
+                        Expr::Path(path) => {
+                            let value = self.resolve(scope, path);
+                            self.gen_load_number(func, number, value.location);
+                        }
+                        Expr::Literal(literal) => {
+                            let Literal::Integer(value) = literal.value else {
+                                unreachable!("only integer literals are compared with numbers")
+                            };
+                            func.instruction(&match number {
+                                NumberType::U64 | NumberType::S64 => Instruction::I64Const(value),
+                                NumberType::F32 => Instruction::F32Const(value as f32),
+                                NumberType::F64 => Instruction::F64Const(value as f64),
+                                _ => Instruction::I32Const(value as i32),
+                            });
+                        }
+                        _ => unreachable!("only numbers are compared with numbers"),
+                    }
+                }
+                func.instruction(&Self::number_comparison(op, number));
+            }
+            Comparison::Bools => {
+                // conditions may be any nonzero value when they hold, so compare whether each is zero
+                self.gen_push_cond(func, scope, left);
+                func.instruction(&Instruction::I32Eqz);
+                self.gen_push_cond(func, scope, right);
+                func.instruction(&Instruction::I32Eqz);
+                func.instruction(&match op {
+                    BinaryOp::Eq => Instruction::I32Eq,
+                    _ => Instruction::I32Ne,
+                });
+            }
+        }
+    }
+
+    /// The instruction comparing two numbers of the same type
+    fn number_comparison(op: BinaryOp, number: NumberType) -> Instruction<'static> {
+        use Instruction::*;
+        let signed = number.is_signed();
+        match number {
+            NumberType::F32 => match op {
+                BinaryOp::Eq => F32Eq,
+                BinaryOp::Ne => F32Ne,
+                BinaryOp::Lt => F32Lt,
+                BinaryOp::Le => F32Le,
+                BinaryOp::Gt => F32Gt,
+                _ => F32Ge,
+            },
+            NumberType::F64 => match op {
+                BinaryOp::Eq => F64Eq,
+                BinaryOp::Ne => F64Ne,
+                BinaryOp::Lt => F64Lt,
+                BinaryOp::Le => F64Le,
+                BinaryOp::Gt => F64Gt,
+                _ => F64Ge,
+            },
+            NumberType::U64 | NumberType::S64 => match op {
+                BinaryOp::Eq => I64Eq,
+                BinaryOp::Ne => I64Ne,
+                BinaryOp::Lt if signed => I64LtS,
+                BinaryOp::Lt => I64LtU,
+                BinaryOp::Le if signed => I64LeS,
+                BinaryOp::Le => I64LeU,
+                BinaryOp::Gt if signed => I64GtS,
+                BinaryOp::Gt => I64GtU,
+                _ if signed => I64GeS,
+                _ => I64GeU,
+            },
+            _ => match op {
+                BinaryOp::Eq => I32Eq,
+                BinaryOp::Ne => I32Ne,
+                BinaryOp::Lt if signed => I32LtS,
+                BinaryOp::Lt => I32LtU,
+                BinaryOp::Le if signed => I32LeS,
+                BinaryOp::Le => I32LeU,
+                BinaryOp::Gt if signed => I32GtS,
+                BinaryOp::Gt => I32GtU,
+                _ if signed => I32GeS,
+                _ => I32GeU,
+            },
+        }
+    }
+
+    /// Starts a match on an enum or variant, branching to the contents of the case it holds.
+    /// Opens a block for the whole match and one for each case, and uses `br_table`
+    /// to exit the block before the contents of the case, or the whole match
+    /// when no case matches. Each case must be followed by `gen_match_case_end`.
+    fn gen_match_start(
+        &self,
+        func: &mut Function,
+        value: Value<'_, 'source>,
+        cases: &[CaseBranch<'source>],
+    ) {
+        let (case_count, discriminant_size) = match value.ty {
+            ParamType::Enum(enum_) => (enum_.cases.len(), enum_.discriminant_size()),
+            ParamType::Variant(variant) => (variant.cases.len(), variant.discriminant_size()),
+            _ => unreachable!("params are inferred from their uses"),
+        };
+
+        if cases.is_empty() {
+            return;
+        }
+        func.instruction(&Instruction::Block(BlockType::Empty));
+        for _ in cases {
+            func.instruction(&Instruction::Block(BlockType::Empty));
+        }
+
+        // the block of the nth case is n blocks out, and the whole match is one further
+        let no_match = cases.len() as u32;
+        let mut targets = vec![no_match; case_count];
+        for (index, case) in cases.iter().enumerate() {
+            let case_index = match value.ty {
+                ParamType::Enum(enum_) => enum_.case_index(case.name.value),
+                ParamType::Variant(variant) => variant.case_index(case.name.value),
+                _ => None,
+            };
+            targets[case_index.expect("params are inferred from their uses")] = index as u32;
+        }
+        self.gen_load_discriminant(func, discriminant_size, value.location);
+        func.instruction(&Instruction::BrTable(targets.into(), no_match));
+        func.instruction(&Instruction::End);
+    }
+
+    /// Binds `loop` and the item of a loop while its contents are generated
+    fn push_loop_bindings<'a>(
+        &self,
+        scope: &mut Vec<Binding<'a, 'source>>,
+        item_name: &'source str,
+        item: Value<'a, 'source>,
+        depth: u32,
+    ) {
+        scope.push(Binding {
+            name: "loop",
+            value: Value {
+                ty: &LOOP_VARIABLES,
+                location: Location::Loop {
+                    depth,
+                    variable: None,
+                },
+            },
+            is_item: false,
+        });
+        scope.push(Binding {
+            name: item_name,
+            value: item,
+            is_item: true,
+        });
+    }
+
+    /// Binds the payload of a case to its name while its contents are generated
+    fn push_case_binding<'a>(
+        &self,
+        scope: &mut Vec<Binding<'a, 'source>>,
+        value: Value<'a, 'source>,
+        case: &CaseBranch<'source>,
+    ) {
+        if let Some(binding) = &case.binding {
+            scope.push(Binding {
+                name: binding.value,
+                value: value
+                    .case_payload(case.name.value)
+                    .expect("params are inferred from their uses"),
+                is_item: false,
+            });
+        }
+    }
+
+    /// Ends the contents of a case by exiting the whole match
+    fn gen_match_case_end(&self, func: &mut Function, index: usize, case_count: usize) {
+        let remaining = (case_count - 1 - index) as u32;
+        if remaining > 0 {
+            func.instruction(&Instruction::Br(remaining));
+        }
+        func.instruction(&Instruction::End);
+    }
+
+    /// Pushes the discriminant of an enum or variant
+    fn gen_load_discriminant(&self, func: &mut Function, size: u32, location: Location) {
+        let (base, offset) = match location {
+            Location::Local(index) => {
+                func.instruction(&Instruction::LocalGet(index));
+                return;
+            }
+            Location::Memory { base, offset } => (base, offset),
+            Location::Loop { .. } => unreachable!("loop variables are numbers and bools"),
+        };
+        let mem_arg = MemArg {
+            offset: offset.into(),
+            align: size.trailing_zeros(),
+            memory_index: MEMORY_INDEX,
+        };
+        func.instruction(&Instruction::LocalGet(base));
+        func.instruction(&match size {
+            1 => Instruction::I32Load8U(mem_arg),
+            2 => Instruction::I32Load16U(mem_arg),
+            _ => Instruction::I32Load(mem_arg),
+        });
+    }
+
+    /// Starts a loop over the given list, leaving the address of the current item
+    /// in the item local for the depth. Must be followed by `gen_loop_end`.
+    fn gen_loop_start<'a>(
+        &'a self,
+        func: &mut Function,
+        list: Value<'a, 'source>,
+        depth: u32,
+    ) -> Value<'a, 'source> {
+        let ParamType::List(item_type) = list.ty else {
+            unreachable!("params are inferred from their uses")
+        };
+
+        // reset the loop counter and keep the list length for the loop variables
+        func.instruction(&Instruction::I32Const(0));
+        func.instruction(&Instruction::LocalSet(self.loop_index_local(depth)));
+        self.gen_load_i32(func, list.location, 1);
+        func.instruction(&Instruction::LocalSet(self.loop_count_local(depth)));
+
+        func.instruction(&Instruction::Block(BlockType::Empty));
+        func.instruction(&Instruction::Loop(BlockType::Empty));
+
+        // exit once the counter reaches the list length
+        func.instruction(&Instruction::LocalGet(self.loop_index_local(depth)));
+        func.instruction(&Instruction::LocalGet(self.loop_count_local(depth)));
+        func.instruction(&Instruction::I32GeU);
+        func.instruction(&Instruction::BrIf(1));
+
+        // compute the address of the current item
+        self.gen_load_i32(func, list.location, 0);
+        func.instruction(&Instruction::LocalGet(self.loop_index_local(depth)));
+        func.instruction(&Instruction::I32Const(item_type.size() as i32));
+        func.instruction(&Instruction::I32Mul);
+        func.instruction(&Instruction::I32Add);
+        func.instruction(&Instruction::LocalSet(self.loop_item_local(depth)));
+
+        Value {
+            ty: item_type,
+            location: Location::Memory {
+                base: self.loop_item_local(depth),
+                offset: 0,
+            },
         }
     }
 
+    fn gen_loop_end(&self, func: &mut Function, depth: u32) {
+        // increment the loop counter
+        func.instruction(&Instruction::LocalGet(self.loop_index_local(depth)));
+        func.instruction(&Instruction::I32Const(1));
+        func.instruction(&Instruction::I32Add);
+        func.instruction(&Instruction::LocalSet(self.loop_index_local(depth)));
+
+        func.instruction(&Instruction::Br(0));
+        func.instruction(&Instruction::End);
+        func.instruction(&Instruction::End);
+    }
+
     fn gen_allocate_results(&self, func: &mut Function) {
         // allocate result string
         func.instruction(&Instruction::I32Const(0));
@@ -366,43 +1687,177 @@ impl<'source> TemplateGenerator<'source> {
     }
 
     fn gen_write_template(&self, func: &mut Function) {
-        self.gen_write_sequence_template(func, &self.file_data.contents);
+        let mut scope = Vec::new();
+        self.gen_write_sequence_template(func, &mut scope, &self.file_data.contents);
     }
 
-    fn gen_write_sequence_template(&self, func: &mut Function, sequence: &[Node<'source>]) {
+    fn gen_write_sequence_template<'a>(
+        &'a self,
+        func: &mut Function,
+        scope: &mut Vec<Binding<'a, 'source>>,
+        sequence: &[Node<'source>],
+    ) {
+        let scope_len = scope.len();
         for node in sequence {
             // note both branches end by pushing the cursor shift
             match node {
                 Node::Text { index, text } => {
                     self.gen_write_segment(func, *index as u32, text.value.len() as i32);
                 }
-                Node::Parameter { name } => {
-                    let index = self.params.text_param_index(&name.value);
-                    self.gen_write_param(func, index as u32);
+                Node::Parameter { name, filters, .. } => {
+                    let value = self.resolve(scope, name);
+                    if !self.is_filtered(filters) && matches!(value.ty, ParamType::String) {
+                        self.gen_write_param(func, value.location);
+                    } else {
+                        self.gen_write_filtered(func, value, filters);
+                    }
                 }
                 Node::Conditional {
                     if_kwd: _,
-                    cond_ident,
+                    cond,
                     contents,
+                    elif_branches,
+                    else_branch,
                     endif_kwd: _,
                 } => {
-                    let cond_index = self.params.cond_param_index(cond_ident.value) as u32;
+                    let branches: Vec<Branch> = std::iter::once((cond, contents.as_slice()))
+                        .chain(
+                            elif_branches
+                                .iter()
+                                .map(|branch| (&branch.cond, branch.contents.as_slice())),
+                        )
+                        .collect();
+                    let else_contents = else_branch
+                        .as_ref()
+                        .map(|branch| branch.contents.as_slice());
+                    self.gen_write_branches_template(func, scope, &branches, else_contents);
+                }
+                Node::Loop {
+                    for_kwd: _,
+                    item_ident,
+                    in_kwd: _,
+                    list_path,
+                    contents,
+                    endfor_kwd: _,
+                } => {
+                    let depth = loop_depth(scope);
+                    let list = self.resolve(scope, list_path);
 
-                    self.params.gen_push_cond(func, cond_index);
-                    func.instruction(&Instruction::If(BlockType::Empty));
-                    self.gen_write_sequence_template(func, contents);
-                    func.instruction(&Instruction::Else);
-                    func.instruction(&Instruction::End);
+                    let item = self.gen_loop_start(func, list, depth);
+                    self.push_loop_bindings(scope, item_ident.value, item, depth);
+                    self.gen_write_sequence_template(func, scope, contents);
+                    scope.truncate(scope.len() - 2);
+                    self.gen_loop_end(func, depth);
+                }
+                Node::Match { path, cases, .. } => {
+                    let value = self.resolve(scope, path);
+                    self.gen_match_start(func, value, cases);
+                    for (index, case) in cases.iter().enumerate() {
+                        self.push_case_binding(scope, value, case);
+                        self.gen_write_sequence_template(func, scope, &case.contents);
+                        if case.binding.is_some() {
+                            scope.pop();
+                        }
+                        self.gen_match_case_end(func, index, cases.len());
+                    }
+                }
+                Node::Super { .. } => unreachable!("super() is resolved along with the blocks"),
+                Node::Include { file, .. } | Node::Block { contents: file, .. } => {
+                    self.gen_write_sequence_template(func, scope, &file.contents);
+                }
+                Node::Macro { .. } => {}
+                Node::Call { name, args } => {
+                    let macro_index = self.gen_push_call(func, scope, name.value, args);
+                    func.instruction(&Instruction::Call(self.macro_write_func_index(macro_index)));
+                }
+                Node::Set {
+                    name, index, terms, ..
+                } => {
+                    let variable_index = *index as u32;
+                    if self.reused_variables.contains(&variable_index) {
+                        // the value was already set while calculating the length
+                        self.bind_variable(scope, name.value, variable_index);
+                    } else {
+                        self.gen_set(func, scope, name.value, variable_index, terms);
+                    }
                 }
             }
-            
-            if matches!(node, Node::Text { .. }) || matches!(node, Node::Parameter { .. }) {
+
+            if matches!(
+                node,
+                Node::Text { .. } | Node::Parameter { .. } | Node::Call { .. }
+            ) {
                 // push cursor and add to shift
                 func.instruction(&Instruction::LocalGet(self.result_cursor_local()));
                 func.instruction(&Instruction::I32Add);
                 func.instruction(&Instruction::LocalSet(self.result_cursor_local()));
             }
         }
+        scope.truncate(scope_len);
+    }
+
+    /// Pushes the shared locals and the arguments of a call to a macro, whose index is returned.
+    /// Arguments the macro doesn't take are left empty.
+    fn gen_push_call<'a>(
+        &'a self,
+        func: &mut Function,
+        scope: &[Binding<'a, 'source>],
+        name: &str,
+        args: &[Expr<'source>],
+    ) -> usize {
+        let macro_index = self
+            .macros
+            .iter()
+            .position(|macro_| macro_.name == name)
+            .expect("calls are checked with the macros");
+        for local in 0..self.macro_cursor_local() {
+            func.instruction(&Instruction::LocalGet(local));
+        }
+        // the macro writes from the cursor, which only matters once the length is known
+        func.instruction(&Instruction::LocalGet(self.result_cursor_local()));
+        for arg in args {
+            match arg {
+                Expr::Path(path) => self.gen_push_string(func, self.resolve(scope, path)),
+                Expr::Literal(literal) => {
+                    let Literal::String(value) = literal.value else {
+                        unreachable!("macro arguments and variables are strings")
+                    };
+                    self.gen_push_literal(func, value);
+                }
+                _ => unreachable!("macro arguments and variables are strings"),
+            }
+        }
+        for _ in args.len() as u32..self.macro_args_len {
+            func.instruction(&Instruction::I32Const(0));
+            func.instruction(&Instruction::I32Const(0));
+        }
+        macro_index
+    }
+
+    /// Writes the first branch whose condition holds,
+    /// falling back to the else contents when none do.
+    fn gen_write_branches_template<'a>(
+        &'a self,
+        func: &mut Function,
+        scope: &mut Vec<Binding<'a, 'source>>,
+        branches: &[Branch<'_, 'source>],
+        else_contents: Option<&[Node<'source>]>,
+    ) {
+        match branches.split_first() {
+            Some(((cond, contents), rest)) => {
+                self.gen_push_cond(func, scope, cond);
+                func.instruction(&Instruction::If(BlockType::Empty));
+                self.gen_write_sequence_template(func, scope, contents);
+                func.instruction(&Instruction::Else);
+                self.gen_write_branches_template(func, scope, rest, else_contents);
+                func.instruction(&Instruction::End);
+            }
+            None => {
+                if let Some(contents) = else_contents {
+                    self.gen_write_sequence_template(func, scope, contents);
+                }
+            }
+        }
     }
 
     fn gen_write_segment(&self, func: &mut Function, data_index: u32, length: i32) {
@@ -419,13 +1874,34 @@ impl<'source> TemplateGenerator<'source> {
         func.instruction(&Instruction::I32Const(length));
     }
 
-    fn gen_write_param(&self, func: &mut Function, param_index: u32) {
+    fn gen_write_filtered(
+        &self,
+        func: &mut Function,
+        value: Value<'_, 'source>,
+        filters: &[Filter<'source>],
+    ) {
+        // push destination
+        func.instruction(&Instruction::LocalGet(self.result_cursor_local()));
+        // push source and length
+        self.gen_push_filtered(func, value, filters);
+        func.instruction(&Instruction::LocalTee(self.filtered_len_local()));
+        // copy the filtered data
+        func.instruction(&Instruction::MemoryCopy {
+            src_mem: MEMORY_INDEX,
+            dst_mem: MEMORY_INDEX,
+        });
+
+        // push length
+        func.instruction(&Instruction::LocalGet(self.filtered_len_local()));
+    }
+
+    fn gen_write_param(&self, func: &mut Function, location: Location) {
         // push destination
         func.instruction(&Instruction::LocalGet(self.result_cursor_local()));
         // push source
-        self.params.gen_push_text_offset(func, param_index);
+        self.gen_load_i32(func, location, 0);
         // push length
-        self.params.gen_push_text_len(func, param_index);
+        self.gen_load_i32(func, location, 1);
         // copy the argument data
         func.instruction(&Instruction::MemoryCopy {
             src_mem: MEMORY_INDEX,
@@ -433,10 +1909,6 @@ impl<'source> TemplateGenerator<'source> {
         });
 
         // push length
-        self.params.gen_push_text_len(func, param_index);
+        self.gen_load_i32(func, location, 1);
     }
 }
-
-fn snake_to_kebab(ident: &str) -> String {
-    ident.replace("_", "-")
-}
\ No newline at end of file
@@ -0,0 +1,622 @@
+use miette::{Diagnostic, NamedSource};
+use std::{fmt::Write, sync::Arc};
+use thiserror::Error;
+use wasm_encoder::ValType;
+use wit_parser::{Resolve, Results, Type, TypeDefKind, WorldId, WorldItem, WorldKey};
+
+use crate::{
+    parse::{Expr, FileData, Node, Path},
+    Config,
+};
+
+use super::params::{
+    annotation_type, compare_as, snake_to_kebab, Enum, NumberType, Operand, ParamType, Params,
+    Record, TypeError, Variant, LOOP_VARIABLES, STRING_BINDING,
+};
+
+// Identifiers that must be escaped with `%` to be used as names in WIT
+const WIT_KEYWORDS: &[&str] = &[
+    "as",
+    "bool",
+    "borrow",
+    "char",
+    "constructor",
+    "enum",
+    "export",
+    "f32",
+    "f64",
+    "flags",
+    "float32",
+    "float64",
+    "func",
+    "future",
+    "import",
+    "include",
+    "interface",
+    "list",
+    "option",
+    "own",
+    "package",
+    "record",
+    "resource",
+    "result",
+    "s16",
+    "s32",
+    "s64",
+    "s8",
+    "static",
+    "stream",
+    "string",
+    "tuple",
+    "type",
+    "u16",
+    "u32",
+    "u64",
+    "u8",
+    "use",
+    "variant",
+    "with",
+    "world",
+];
+
+/// A problem with the WIT world a template is compiled against
+#[derive(Error, Debug, Diagnostic)]
+#[error("{message}")]
+#[diagnostic()]
+pub struct WitError {
+    message: String,
+}
+
+/// Renders a WIT world with the params record and export function of a template
+pub fn gen_wit(config: &Config, params: &Params) -> String {
+    let mut named_types = Vec::new();
+    collect_named_types(params.param_type(), &mut named_types);
+
+    let mut wit = String::from("package template:generated;\n\nworld template {\n");
+    for ty in named_types {
+        match ty {
+            ParamType::Record(record) => {
+                writeln!(wit, "    record {} {{", wit_ident(&record.name)).unwrap();
+                for (field, ty) in record.fields.iter() {
+                    writeln!(
+                        wit,
+                        "        {}: {},",
+                        wit_ident(&snake_to_kebab(field)),
+                        wit_type(ty)
+                    )
+                    .unwrap();
+                }
+            }
+            ParamType::Enum(enum_) => {
+                writeln!(wit, "    enum {} {{", wit_ident(&enum_.name)).unwrap();
+                for case in enum_.cases.iter() {
+                    writeln!(wit, "        {},", wit_ident(&snake_to_kebab(case))).unwrap();
+                }
+            }
+            ParamType::Variant(variant) => {
+                writeln!(wit, "    variant {} {{", wit_ident(&variant.name)).unwrap();
+                for (case, payload) in variant.cases.iter() {
+                    let case = wit_ident(&snake_to_kebab(case));
+                    match payload {
+                        Some(payload) => {
+                            writeln!(wit, "        {}({}),", case, wit_type(payload)).unwrap()
+                        }
+                        None => writeln!(wit, "        {},", case).unwrap(),
+                    }
+                }
+            }
+            _ => unreachable!("only records, enums, and variants are named"),
+        }
+        wit.push_str("    }\n\n");
+    }
+    writeln!(
+        wit,
+        "    export {}: func(param: params) -> string;",
+        wit_ident(&config.export_func_name)
+    )
+    .unwrap();
+    wit.push_str("}\n");
+    wit
+}
+
+/// Collects the records, enums, and variants used by a type, each after the types it uses
+fn collect_named_types<'a, 'source>(
+    ty: &'a ParamType<'source>,
+    named_types: &mut Vec<&'a ParamType<'source>>,
+) {
+    match ty {
+        ParamType::String | ParamType::Bool | ParamType::Number(_) => return,
+        ParamType::Option(payload) => return collect_named_types(payload, named_types),
+        ParamType::List(item) => return collect_named_types(item, named_types),
+        ParamType::Enum(_) => {}
+        ParamType::Variant(variant) => {
+            for payload in variant.payloads() {
+                collect_named_types(payload, named_types);
+            }
+        }
+        ParamType::Record(record) => {
+            for (_, ty) in record.fields.iter() {
+                collect_named_types(ty, named_types);
+            }
+        }
+    }
+    // a declared type can be used more than once
+    if !named_types.contains(&ty) {
+        named_types.push(ty);
+    }
+}
+
+/// The WIT syntax for a type
+fn wit_type(ty: &ParamType) -> String {
+    match ty {
+        ParamType::String => "string".into(),
+        ParamType::Bool => "bool".into(),
+        ParamType::Number(number) => number.name().into(),
+        ParamType::Enum(enum_) => wit_ident(&enum_.name),
+        ParamType::Variant(variant) => wit_ident(&variant.name),
+        ParamType::Option(payload) => format!("option<{}>", wit_type(payload)),
+        ParamType::List(item) => format!("list<{}>", wit_type(item)),
+        ParamType::Record(record) => wit_ident(&record.name),
+    }
+}
+
+fn wit_ident(name: &str) -> String {
+    if WIT_KEYWORDS.contains(&name) {
+        format!("%{}", name)
+    } else {
+        name.into()
+    }
+}
+
+/// The names bound by enclosing loops, cases, and macros, and by variables set before, and their types.
+/// Each loop binds `loop` along with its item.
+type Scope<'a, 'source> = Vec<(&'source str, &'a ParamType<'source>)>;
+
+impl<'source> Params<'source> {
+    /// Takes the params record from the export function of a WIT world
+    /// and checks that the template uses it correctly
+    pub fn from_wit(
+        resolve: &'source Resolve,
+        world: WorldId,
+        export_name: &str,
+        file_data: &'source FileData<'source>,
+    ) -> miette::Result<Self> {
+        let world = &resolve.worlds[world];
+        let error = |message: String| WitError { message };
+        let func = match world.exports.get(&WorldKey::Name(export_name.to_owned())) {
+            Some(WorldItem::Function(func)) => func,
+            _ => {
+                return Err(error(format!(
+                    "World `{}` has no exported function `{}`",
+                    world.name, export_name
+                ))
+                .into())
+            }
+        };
+        if !matches!(func.results, Results::Anon(Type::String)) {
+            return Err(error(format!("Function `{}` must return a string", export_name)).into());
+        }
+        let [(_, param)] = func.params.as_slice() else {
+            return Err(error(format!(
+                "Function `{}` must take a single record parameter",
+                export_name
+            ))
+            .into());
+        };
+        let ty = Self::convert(resolve, param).map_err(error)?;
+        if !matches!(ty, ParamType::Record(_)) {
+            return Err(error(format!(
+                "Function `{}` must take a single record parameter",
+                export_name
+            ))
+            .into());
+        }
+
+        let params = Params::from_type(ty);
+        params.check_sequence(&file_data.source, &file_data.contents, &mut Vec::new())?;
+        Ok(params)
+    }
+
+    /// Converts a WIT type into a parameter type
+    fn convert(resolve: &'source Resolve, ty: &Type) -> Result<ParamType<'source>, String> {
+        let id = match ty {
+            Type::String => return Ok(ParamType::String),
+            Type::Bool => return Ok(ParamType::Bool),
+            Type::Id(id) => *id,
+            _ => {
+                return wit_number_type(ty)
+                    .map(ParamType::Number)
+                    .ok_or_else(|| format!("Unsupported type `{}`", wit_type_name(resolve, ty)))
+            }
+        };
+        let def = &resolve.types[id];
+        match &def.kind {
+            TypeDefKind::Type(ty) => Self::convert(resolve, ty),
+            TypeDefKind::List(item) => Ok(ParamType::List(Box::new(Self::convert(resolve, item)?))),
+            // only optional strings are supported, since other payloads would need their
+            // flattened types joined with the discriminant
+            TypeDefKind::Option(Type::String) => Ok(ParamType::Option(Box::new(ParamType::String))),
+            TypeDefKind::Record(record) => {
+                let fields = record
+                    .fields
+                    .iter()
+                    .map(|field| Ok((field.name.as_str(), Self::convert(resolve, &field.ty)?)))
+                    .collect::<Result<_, String>>()?;
+                Ok(ParamType::Record(Record {
+                    name: def.name.clone().unwrap_or_default(),
+                    fields,
+                }))
+            }
+            TypeDefKind::Enum(enum_) => Ok(ParamType::Enum(Enum {
+                name: def.name.clone().unwrap_or_default(),
+                cases: enum_.cases.iter().map(|case| case.name.as_str()).collect(),
+            })),
+            TypeDefKind::Variant(variant) => {
+                let cases: Vec<_> = variant
+                    .cases
+                    .iter()
+                    .map(|case| {
+                        let payload = case
+                            .ty
+                            .as_ref()
+                            .map(|ty| Self::convert(resolve, ty))
+                            .transpose()?;
+                        Ok((case.name.as_str(), payload))
+                    })
+                    .collect::<Result<_, String>>()?;
+                // payloads are joined by just taking the longest, which only works when they're all i32s
+                let mut payload_types = Vec::new();
+                for (_, payload) in cases.iter() {
+                    if let Some(payload) = payload {
+                        payload.flat_types(&mut payload_types);
+                    }
+                }
+                if payload_types.iter().any(|ty| *ty != ValType::I32) {
+                    return Err(format!("Unsupported type `{}`", wit_type_name(resolve, ty)));
+                }
+                Ok(ParamType::Variant(Variant {
+                    name: def.name.clone().unwrap_or_default(),
+                    cases,
+                }))
+            }
+            _ => Err(format!("Unsupported type `{}`", wit_type_name(resolve, ty))),
+        }
+    }
+
+    /// Checks the parameters used by a sequence of nodes.
+    /// The variables set in the sequence are only in scope until its end.
+    pub(super) fn check_sequence<'a>(
+        &'a self,
+        src: &Arc<NamedSource>,
+        nodes: &'source [Node<'source>],
+        scope: &mut Scope<'a, 'source>,
+    ) -> Result<(), TypeError> {
+        let scope_len = scope.len();
+        for node in nodes {
+            self.check(src, node, scope)?;
+        }
+        scope.truncate(scope_len);
+        Ok(())
+    }
+
+    /// Checks that each parameter used by a node has a type that fits the use
+    fn check<'a>(
+        &'a self,
+        src: &Arc<NamedSource>,
+        node: &'source Node<'source>,
+        scope: &mut Scope<'a, 'source>,
+    ) -> Result<(), TypeError> {
+        match node {
+            Node::Text { .. } => {}
+            Node::Parameter {
+                name, annotation, ..
+            } => {
+                let ty = self.check_path(src, scope, name)?;
+                if let Some(annotation) = annotation {
+                    let number = annotation_type(src, annotation)?;
+                    if ty != &ParamType::Number(number) {
+                        let expected = format!("`{}`", number.name());
+                        return Err(mismatch(src, name, ty, &expected));
+                    }
+                }
+                // a `none` option is rendered as an empty string
+                if !matches!(
+                    ty,
+                    ParamType::String | ParamType::Number(_) | ParamType::Option(_)
+                ) {
+                    return Err(mismatch(src, name, ty, "a string"));
+                }
+            }
+            Node::Conditional {
+                if_kwd: _,
+                cond,
+                contents,
+                elif_branches,
+                else_branch,
+                endif_kwd: _,
+            } => {
+                let branches = std::iter::once((cond, contents)).chain(
+                    elif_branches
+                        .iter()
+                        .map(|branch| (&branch.cond, &branch.contents)),
+                );
+                for (cond, contents) in branches {
+                    self.check_cond(src, scope, cond)?;
+                    self.check_sequence(src, contents, scope)?;
+                }
+                if let Some(branch) = else_branch {
+                    self.check_sequence(src, &branch.contents, scope)?;
+                }
+            }
+            Node::Loop {
+                item_ident,
+                list_path,
+                contents,
+                ..
+            } => {
+                let ty = self.check_path(src, scope, list_path)?;
+                let ParamType::List(item) = ty else {
+                    return Err(mismatch(src, list_path, ty, "a list"));
+                };
+                scope.push(("loop", &LOOP_VARIABLES));
+                scope.push((item_ident.value, item));
+                self.check_sequence(src, contents, scope)?;
+                scope.truncate(scope.len() - 2);
+            }
+            Node::Match {
+                match_kwd: _,
+                path,
+                cases,
+                endmatch_kwd: _,
+            } => {
+                let ty = self.check_path(src, scope, path)?;
+                if !matches!(ty, ParamType::Enum(_) | ParamType::Variant(_)) {
+                    return Err(mismatch(src, path, ty, "an enum or variant"));
+                }
+                for case in cases {
+                    let error = |message: String| TypeError {
+                        src: src.clone(),
+                        span: case.name.span,
+                        message,
+                    };
+                    let payload = match ty {
+                        ParamType::Enum(enum_) => match enum_.case_index(case.name.value) {
+                            Some(_) => None,
+                            None => {
+                                let message = format!(
+                                    "Enum `{}` has no case `{}`",
+                                    enum_.name, case.name.value
+                                );
+                                return Err(error(message));
+                            }
+                        },
+                        ParamType::Variant(variant) => match variant.case_index(case.name.value) {
+                            Some(index) => variant.cases[index].1.as_ref(),
+                            None => {
+                                let message = format!(
+                                    "Variant `{}` has no case `{}`",
+                                    variant.name, case.name.value
+                                );
+                                return Err(error(message));
+                            }
+                        },
+                        _ => unreachable!("matched values are enums or variants"),
+                    };
+
+                    // the payload can be ignored, but can't be bound if there isn't one
+                    if let Some(binding) = &case.binding {
+                        let Some(payload) = payload else {
+                            return Err(error(format!(
+                                "Case `{}` has no payload",
+                                case.name.value
+                            )));
+                        };
+                        scope.push((binding.value, payload));
+                    }
+                    self.check_sequence(src, &case.contents, scope)?;
+                    if case.binding.is_some() {
+                        scope.pop();
+                    }
+                }
+            }
+            Node::Super { .. } => unreachable!("super() is resolved along with the blocks"),
+            Node::Include { file, .. } | Node::Block { contents: file, .. } => {
+                self.check_sequence(&file.source, &file.contents, scope)?;
+            }
+            Node::Macro { args, contents, .. } => {
+                let mut scope = args
+                    .iter()
+                    .map(|arg| (arg.value, &STRING_BINDING))
+                    .collect();
+                self.check_sequence(&contents.source, &contents.contents, &mut scope)?;
+            }
+            Node::Call { args, .. } => {
+                for arg in args {
+                    if let Expr::Path(path) = arg {
+                        self.check_interpolated(src, scope, path)?;
+                    }
+                }
+            }
+            Node::Set { name, terms, .. } => {
+                for term in terms {
+                    if let Expr::Path(path) = &term.value {
+                        self.check_interpolated(src, scope, path)?;
+                    }
+                }
+                scope.push((name.value, &STRING_BINDING));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that a parameter passed as a string can be rendered as one
+    fn check_interpolated<'a>(
+        &'a self,
+        src: &Arc<NamedSource>,
+        scope: &Scope<'a, 'source>,
+        path: &Path<'source>,
+    ) -> Result<(), TypeError> {
+        let ty = self.check_path(src, scope, path)?;
+        // a `none` option is rendered as an empty string
+        if !matches!(
+            ty,
+            ParamType::String | ParamType::Number(_) | ParamType::Option(_)
+        ) {
+            return Err(mismatch(src, path, ty, "a string"));
+        }
+        Ok(())
+    }
+
+    /// Checks that a condition only tests and compares parameters that support it
+    fn check_cond<'a>(
+        &'a self,
+        src: &Arc<NamedSource>,
+        scope: &Scope<'a, 'source>,
+        expr: &Expr<'source>,
+    ) -> Result<(), TypeError> {
+        match expr {
+            Expr::Path(path) => {
+                let ty = self.check_path(src, scope, path)?;
+                if matches!(
+                    ty,
+                    ParamType::Record(_) | ParamType::Enum(_) | ParamType::Variant(_)
+                ) {
+                    return Err(mismatch(src, path, ty, "a bool, string, or list"));
+                }
+            }
+            Expr::Literal(literal) => {
+                return Err(TypeError {
+                    src: src.clone(),
+                    span: literal.span,
+                    message: "Literals can only be compared".into(),
+                })
+            }
+            Expr::Not { operand, .. } => self.check_cond(src, scope, operand)?,
+            Expr::Binary { op, left, right } if op.value.is_comparison() => {
+                let left_operand = self.check_operand(src, scope, left)?;
+                let right_operand = self.check_operand(src, scope, right)?;
+                compare_as(op.value, left_operand, right_operand).map_err(|message| TypeError {
+                    src: src.clone(),
+                    span: expr.span(),
+                    message,
+                })?;
+            }
+            Expr::Binary { left, right, .. } => {
+                self.check_cond(src, scope, left)?;
+                self.check_cond(src, scope, right)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks one side of a comparison
+    fn check_operand<'a>(
+        &'a self,
+        src: &Arc<NamedSource>,
+        scope: &Scope<'a, 'source>,
+        expr: &Expr<'source>,
+    ) -> Result<Operand<'a, 'source>, TypeError> {
+        match expr {
+            Expr::Path(path) => Ok(Operand::Value(self.check_path(src, scope, path)?)),
+            Expr::Literal(_) => Ok(Operand::of(expr, |_| unreachable!("literals aren't paths"))),
+            Expr::Not { .. } | Expr::Binary { .. } => {
+                self.check_cond(src, scope, expr)?;
+                Ok(Operand::Condition)
+            }
+        }
+    }
+
+    /// Finds the type of the value named by a path
+    fn check_path<'a>(
+        &'a self,
+        src: &Arc<NamedSource>,
+        scope: &Scope<'a, 'source>,
+        path: &Path<'source>,
+    ) -> Result<&'a ParamType<'source>, TypeError> {
+        let root = path.root().value;
+        let (mut ty, fields) = match scope.iter().rev().find(|(name, _)| *name == root) {
+            Some((_, ty)) => (*ty, &path.segments[1..]),
+            None => (self.param_type(), &path.segments[..]),
+        };
+        for segment in fields {
+            let field = match ty {
+                ParamType::Record(record) => record
+                    .field_index(segment.value)
+                    .map(|index| &record.fields[index].1),
+                _ => None,
+            };
+            ty = field.ok_or_else(|| TypeError {
+                src: src.clone(),
+                span: segment.span,
+                message: match ty {
+                    ParamType::Record(record) => {
+                        format!("Record `{}` has no field `{}`", record.name, segment.value)
+                    }
+                    _ => format!("Expected a record, found {}", ty.describe()),
+                },
+            })?;
+        }
+        Ok(ty)
+    }
+}
+
+fn mismatch(src: &Arc<NamedSource>, path: &Path, ty: &ParamType, expected: &str) -> TypeError {
+    TypeError {
+        src: src.clone(),
+        span: path.span(),
+        message: format!("Expected {}, found {}", expected, ty.describe()),
+    }
+}
+
+/// The number type a WIT primitive type is, if it's a number
+fn wit_number_type(ty: &Type) -> Option<NumberType> {
+    let number = match ty {
+        Type::U8 => NumberType::U8,
+        Type::U16 => NumberType::U16,
+        Type::U32 => NumberType::U32,
+        Type::U64 => NumberType::U64,
+        Type::S8 => NumberType::S8,
+        Type::S16 => NumberType::S16,
+        Type::S32 => NumberType::S32,
+        Type::S64 => NumberType::S64,
+        Type::F32 => NumberType::F32,
+        Type::F64 => NumberType::F64,
+        _ => return None,
+    };
+    Some(number)
+}
+
+/// The name of a WIT type as it's written in WIT, for use in diagnostics
+fn wit_type_name(resolve: &Resolve, ty: &Type) -> String {
+    let name = match ty {
+        Type::Bool => "bool",
+        Type::U8 => "u8",
+        Type::U16 => "u16",
+        Type::U32 => "u32",
+        Type::U64 => "u64",
+        Type::S8 => "s8",
+        Type::S16 => "s16",
+        Type::S32 => "s32",
+        Type::S64 => "s64",
+        Type::F32 => "f32",
+        Type::F64 => "f64",
+        Type::Char => "char",
+        Type::String => "string",
+        Type::Id(id) => {
+            let def = &resolve.types[*id];
+            return match (&def.name, &def.kind) {
+                (Some(name), _) => name.clone(),
+                (None, TypeDefKind::List(item)) => {
+                    format!("list<{}>", wit_type_name(resolve, item))
+                }
+                (None, TypeDefKind::Option(item)) => {
+                    format!("option<{}>", wit_type_name(resolve, item))
+                }
+                (None, TypeDefKind::Tuple(_)) => "tuple".into(),
+                (None, TypeDefKind::Result(_)) => "result".into(),
+                (None, _) => "anonymous type".into(),
+            };
+        }
+    };
+    name.into()
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use miette::{miette, Diagnostic, IntoDiagnostic, NamedSource, Result, SourceSpan};
+use thiserror::Error;
+
+use crate::{
+    parse::{parse_template, FileData},
+    tokens::{Token, Tokenizer},
+    Config,
+};
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("{message}")]
+#[diagnostic()]
+pub struct IncludeError {
+    #[source_code]
+    src: Arc<NamedSource>,
+    #[label("Here")]
+    span: SourceSpan,
+    message: String,
+}
+
+/// A template read from a file along with every file it includes or extends.
+/// They're all read before parsing so that the parsed nodes can borrow their text.
+pub struct SourceFiles {
+    // The template comes first, followed by the files it references in the order they're found
+    files: Vec<SourceFile>,
+}
+
+struct SourceFile {
+    // The canonical path, which identifies the file when it's included more than once
+    path: PathBuf,
+    source: Arc<NamedSource>,
+    text: String,
+    // The file each include or extends names, by the path written in it
+    includes: Vec<(String, usize)>,
+}
+
+impl SourceFiles {
+    /// Reads a template and the files it includes or extends, directly or indirectly.
+    /// Their paths are relative to the file referencing them.
+    pub fn load(config: &Config, path: &Path) -> Result<Self> {
+        let name: String = path
+            .file_name()
+            .ok_or_else(|| miette!("No file name found"))?
+            .to_str()
+            .ok_or_else(|| miette!("File name was not valid utf-8"))?
+            .into();
+        let text = fs::read_to_string(path).into_diagnostic()?;
+        let path = path.canonicalize().into_diagnostic()?;
+
+        let mut files = SourceFiles { files: Vec::new() };
+        files.load_file(config, path, name, text, &mut Vec::new())?;
+        Ok(files)
+    }
+
+    /// Adds a file and then loads the files it includes or extends,
+    /// where `chain` holds the files referencing it, which it can't reference in turn
+    fn load_file(
+        &mut self,
+        config: &Config,
+        path: PathBuf,
+        name: String,
+        text: String,
+        chain: &mut Vec<usize>,
+    ) -> Result<usize> {
+        let index = self.files.len();
+        let source = Arc::new(NamedSource::new(name, text.clone()));
+        self.files.push(SourceFile {
+            path,
+            source: source.clone(),
+            text,
+            includes: Vec::new(),
+        });
+
+        let tokens = Tokenizer::new(source.clone(), &self.files[index].text)
+            .trim_blocks(config.trim_blocks)
+            .lstrip_blocks(config.lstrip_blocks)
+            .tokenize()?;
+        let includes: Vec<(String, SourceSpan, &str)> = tokens
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [(_, Token::Include), (span, Token::String { value })] => {
+                    Some((value.to_string(), *span, "Include"))
+                }
+                [(_, Token::Extends), (span, Token::String { value })] => {
+                    Some((value.to_string(), *span, "Inheritance"))
+                }
+                _ => None,
+            })
+            .collect();
+
+        chain.push(index);
+        let dir = self.files[index].path.parent().unwrap().to_owned();
+        for (included, span, kind) in includes {
+            let error = |message: String| IncludeError {
+                src: source.clone(),
+                span,
+                message,
+            };
+            let unreadable =
+                |error: std::io::Error| format!("Can't read `{}`: {}", included, error);
+            let path = dir
+                .join(&included)
+                .canonicalize()
+                .map_err(|e| error(unreadable(e)))?;
+
+            if let Some(position) = chain.iter().position(|&file| self.files[file].path == path) {
+                let names: Vec<&str> = chain[position..]
+                    .iter()
+                    .chain(std::iter::once(&chain[position]))
+                    .map(|&file| self.files[file].source.name())
+                    .collect();
+                return Err(error(format!("{} cycle: {}", kind, names.join(" -> "))).into());
+            }
+            let file = match self.files.iter().position(|file| file.path == path) {
+                Some(file) => file,
+                None => {
+                    let text = fs::read_to_string(&path).map_err(|e| error(unreadable(e)))?;
+                    let name = self.display_name(&path);
+                    self.load_file(config, path, name, text, chain)?
+                }
+            };
+            self.files[index].includes.push((included, file));
+        }
+        chain.pop();
+
+        Ok(index)
+    }
+
+    /// The name of a referenced file in diagnostics, relative to the template if it can be
+    fn display_name(&self, path: &Path) -> String {
+        let root = self.files[0].path.parent().unwrap();
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    /// Parses the template, splicing in the files it includes and overriding the blocks of those it extends
+    pub fn parse(&self, config: &Config) -> Result<FileData<'_>> {
+        let root = &self.files[0];
+        parse_template(config, root.source.clone(), &root.text, Some((self, 0)))
+    }
+
+    /// The file included or extended from a file by the path written in it
+    pub(crate) fn included(&self, file: usize, path: &str) -> usize {
+        let (_, included) = self.files[file]
+            .includes
+            .iter()
+            .find(|(included, _)| included == path)
+            .expect("included files are loaded with the template");
+        *included
+    }
+
+    /// The source and text of a file
+    pub(crate) fn source(&self, file: usize) -> (&Arc<NamedSource>, &str) {
+        let file = &self.files[file];
+        (&file.source, &file.text)
+    }
+}
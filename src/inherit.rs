@@ -0,0 +1,126 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use miette::{NamedSource, Result};
+
+use crate::parse::{FileData, Node, ParseError, M};
+
+/// The blocks a template defines, by name
+type Blocks<'a, 'source> = BTreeMap<&'source str, (M<&'source str>, &'a FileData<'source>)>;
+
+/// Resolves the blocks of a template and the ones it extends,
+/// given from the template being parsed up to the one extending no other.
+/// The result is the last template with each block holding its last override,
+/// preceded by the macros defined by the templates extending it.
+pub fn resolve(chain: Vec<FileData>) -> Result<FileData> {
+    let levels = chain
+        .iter()
+        .map(|file_data| {
+            let mut blocks = BTreeMap::new();
+            collect_blocks(&file_data.contents, &file_data.source, &mut blocks)?;
+            Ok(blocks)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (base, children) = chain.split_last().unwrap();
+    let mut base = FileData {
+        source: base.source.clone(),
+        contents: children
+            .iter()
+            .flat_map(|file_data| &file_data.contents)
+            .filter(|node| matches!(node, Node::Macro { .. }))
+            .chain(&base.contents)
+            .cloned()
+            .collect(),
+    };
+    resolve_nodes(&mut base.contents, &base.source, None, &levels)?;
+    Ok(base)
+}
+
+/// Collects the blocks defined in a template, including those within other blocks
+fn collect_blocks<'a, 'source>(
+    nodes: &'a [Node<'source>],
+    source: &'a Arc<NamedSource>,
+    blocks: &mut Blocks<'a, 'source>,
+) -> Result<()> {
+    for node in nodes {
+        if let Node::Block { name, contents, .. } = node {
+            if blocks
+                .insert(name.value, (name.clone(), contents))
+                .is_some()
+            {
+                let message = format!("Duplicate block `{}`", name.value);
+                return Err(ParseError::new(source.clone(), name.span, message).into());
+            }
+        }
+        for (children, source) in node.children(source) {
+            collect_blocks(children, source, blocks)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the contents of each block with its last override,
+/// where `block` is the name of the enclosing block and the level its contents are from
+fn resolve_nodes<'source>(
+    nodes: &mut [Node<'source>],
+    source: &Arc<NamedSource>,
+    block: Option<(&'source str, usize)>,
+    levels: &[Blocks<'_, 'source>],
+) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Block { name, contents, .. } => {
+                let level = levels
+                    .iter()
+                    .position(|blocks| blocks.contains_key(name.value))
+                    .unwrap();
+                *contents = resolve_block(name.value, level, levels)?;
+            }
+            Node::Super { span } => {
+                let Some((name, level)) = block else {
+                    let message = "`super()` can only be used in a block".into();
+                    return Err(ParseError::new(source.clone(), *span, message).into());
+                };
+                let Some(parent) =
+                    (level + 1..levels.len()).find(|&level| levels[level].contains_key(name))
+                else {
+                    let message = format!(
+                        "No block `{}` in a parent template for `super()` to render",
+                        name
+                    );
+                    return Err(ParseError::new(source.clone(), *span, message).into());
+                };
+                *node = Node::Block {
+                    block_kwd: *span,
+                    name: levels[parent][name].0.clone(),
+                    contents: resolve_block(name, parent, levels)?,
+                };
+            }
+            Node::Include { file, .. } | Node::Macro { contents: file, .. } => {
+                resolve_nodes(&mut file.contents, &file.source, block, levels)?
+            }
+            _ => {
+                for children in node.children_mut() {
+                    resolve_nodes(children, source, block, levels)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The contents of a block as defined at a level, with the blocks within resolved in turn
+fn resolve_block<'source>(
+    name: &'source str,
+    level: usize,
+    levels: &[Blocks<'_, 'source>],
+) -> Result<FileData<'source>> {
+    let mut contents = levels[level][name].1.clone();
+    resolve_nodes(
+        &mut contents.contents,
+        &contents.source,
+        Some((name, level)),
+        levels,
+    )?;
+    Ok(contents)
+}
@@ -1,10 +1,38 @@
 mod gen;
+mod include;
+mod inherit;
 mod parse;
 mod tokens;
 
-pub use crate::gen::{component::gen_component, template::{TemplateGenerator, Params}};
-pub use crate::parse::{parse_file, FileData, Node, M};
+pub use crate::gen::{
+    component::gen_component,
+    params::{Enum, NumberType, ParamType, Params, Record, TypeError, Variant},
+    template::TemplateGenerator,
+    wit::{gen_wit, WitError},
+};
+pub use crate::include::{IncludeError, SourceFiles};
+pub use crate::parse::{
+    parse_file, BinaryOp, CaseBranch, ElIfBranch, ElseBranch, Expr, FileData, Filter, FilterError,
+    FilterKind, Literal, LiteralKind, Node, ParseError, Path, M,
+};
 
 pub struct Config {
     pub export_func_name: String,
+    /// Whether to HTML escape interpolated parameters unless they're marked `safe`
+    pub autoescape: bool,
+    /// Whether to remove the first newline after a block tag or comment
+    pub trim_blocks: bool,
+    /// Whether to strip the spaces and tabs before a block tag or comment that starts a line
+    pub lstrip_blocks: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            export_func_name: "apply".into(),
+            autoescape: false,
+            trim_blocks: false,
+            lstrip_blocks: false,
+        }
+    }
 }
@@ -1,10 +1,10 @@
-use std::{fs, path::PathBuf, sync::Arc};
+use std::{fs, path::PathBuf, process::ExitCode};
 
-use anyhow::{Context, Result};
-use clap::Parser;
-use miette::NamedSource;
+use clap::{Parser, ValueEnum};
+use miette::{miette, IntoDiagnostic, JSONReportHandler, Result};
+use wit_parser::Resolve;
 
-use template_compiler::{gen_component, parse_file, Config, TemplateGenerator, Params};
+use template_compiler::{gen_component, gen_wit, Config, Params, SourceFiles, TemplateGenerator};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -17,31 +17,104 @@ struct Args {
     // The export name for the template function
     #[arg(short, long)]
     export_name: Option<String>,
+
+    // A WIT world declaring the export function to check the template against
+    #[arg(long)]
+    wit: Option<PathBuf>,
+
+    // Where to write the WIT world describing the generated component
+    #[arg(long)]
+    emit_wit: Option<PathBuf>,
+
+    // Escape interpolated parameters as HTML (the default for .html inputs)
+    #[arg(long, overrides_with = "no_autoescape")]
+    autoescape: bool,
+    #[arg(long, overrides_with = "autoescape")]
+    no_autoescape: bool,
+
+    // Remove the first newline after a block tag
+    #[arg(long)]
+    trim_blocks: bool,
+
+    // Strip the spaces and tabs before a block tag that starts a line
+    #[arg(long)]
+    lstrip_blocks: bool,
+
+    // How errors are reported
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    message_format: MessageFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum MessageFormat {
+    /// Graphical reports with source snippets, written to stderr
+    Human,
+    /// One JSON object per error with byte spans, written to stdout
+    Json,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let args = Args::parse();
+    let message_format = args.message_format;
+    let Err(report) = compile(args) else {
+        return ExitCode::SUCCESS;
+    };
+
+    match message_format {
+        MessageFormat::Human => eprintln!("{:?}", report),
+        MessageFormat::Json => {
+            let mut json = String::new();
+            JSONReportHandler::new()
+                .render_report(&mut json, report.as_ref())
+                .expect("formatting into a string can't fail");
+            println!("{}", json);
+        }
+    }
+    ExitCode::FAILURE
+}
+
+fn compile(args: Args) -> Result<()> {
+    let is_html = matches!(
+        args.input.extension().and_then(|ext| ext.to_str()),
+        Some("html" | "htm")
+    );
     let config = Config {
         export_func_name: args.export_name.unwrap_or("apply".into()),
+        autoescape: (is_html || args.autoescape) && !args.no_autoescape,
+        trim_blocks: args.trim_blocks,
+        lstrip_blocks: args.lstrip_blocks,
     };
 
-    let name: String = args
-        .input
-        .file_name()
-        .context("No file name found")?
-        .to_str()
-        .context("File name was not valid utf-8")?
-        .into();
+    let sources = SourceFiles::load(&config, &args.input)?;
 
-    let text = fs::read_to_string(args.input)?;
-
-    let source = Arc::new(NamedSource::new(name, text.clone()));
+    let wit = match args.wit {
+        Some(path) => {
+            let mut resolve = Resolve::new();
+            let (package, _) = resolve
+                .push_path(path)
+                .map_err(|error| miette!("{:#}", error))?;
+            let world = resolve
+                .select_world(package, None)
+                .map_err(|error| miette!("{:#}", error))?;
+            Some((resolve, world))
+        }
+        None => None,
+    };
 
-    let file_data = parse_file(source, &text)?;
-    let params = Params::new(&file_data.contents);
-    let template = TemplateGenerator::new(params, &file_data);
+    let file_data = sources.parse(&config)?;
+    let params = match &wit {
+        Some((resolve, world)) => {
+            Params::from_wit(resolve, *world, &config.export_func_name, &file_data)?
+        }
+        None => Params::new(&file_data)?,
+    };
+    let template = TemplateGenerator::new(&config, params, &file_data);
     let component = gen_component(&config, &template);
-    fs::write(args.output, component.finish().as_slice())?;
+    fs::write(args.output, component.finish().as_slice()).into_diagnostic()?;
+
+    if let Some(path) = args.emit_wit {
+        fs::write(path, gen_wit(&config, template.params())).into_diagnostic()?;
+    }
 
     Ok(())
 }
@@ -1,11 +1,17 @@
-use std::{sync::Arc, iter::Peekable};
+use std::{collections::BTreeMap, iter::Peekable, sync::Arc, vec::IntoIter};
 
-use anyhow::Result;
-use miette::{NamedSource, SourceSpan};
+use miette::Result;
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
 
-use crate::tokens::{Token, Tokenizer};
+use crate::{
+    include::SourceFiles,
+    inherit,
+    tokens::{Token, Tokenizer},
+    Config,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct M<T> {
     pub span: SourceSpan,
     pub value: T,
@@ -17,106 +23,1514 @@ impl<T> M<T> {
     }
 }
 
-#[derive(Debug)]
+/// A dot separated parameter name like `user.name`
+#[derive(Debug, Clone)]
+pub struct Path<'source> {
+    pub segments: Vec<M<&'source str>>,
+}
+
+impl<'source> Path<'source> {
+    /// The first name in the path
+    pub fn root(&self) -> &M<&'source str> {
+        &self.segments[0]
+    }
+
+    /// The span covering the whole path
+    pub fn span(&self) -> SourceSpan {
+        self.prefix_span(self.segments.len())
+    }
+
+    /// The span covering the first `len` names in the path
+    pub fn prefix_span(&self, len: usize) -> SourceSpan {
+        let start = self.segments[0].span.offset();
+        let last = &self.segments[len - 1].span;
+        SourceSpan::from((start, last.offset() + last.len() - start))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FileData<'source> {
     pub source: Arc<NamedSource>,
     pub contents: Vec<Node<'source>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Node<'source> {
     Text {
         index: usize,
         text: M<&'source str>,
     },
     Parameter {
-        name: M<&'source str>,
+        name: Path<'source>,
+        /// The type written after the name, e.g. `u32` in `{{ count: u32 }}`
+        annotation: Option<M<&'source str>>,
+        filters: Vec<Filter<'source>>,
     },
     Conditional {
         if_kwd: SourceSpan,
-        cond_ident: M<&'source str>,
+        cond: Expr<'source>,
         contents: Vec<Node<'source>>,
+        elif_branches: Vec<ElIfBranch<'source>>,
+        else_branch: Option<ElseBranch<'source>>,
         endif_kwd: SourceSpan,
     },
+    Loop {
+        for_kwd: SourceSpan,
+        item_ident: M<&'source str>,
+        in_kwd: SourceSpan,
+        list_path: Path<'source>,
+        contents: Vec<Node<'source>>,
+        endfor_kwd: SourceSpan,
+    },
+    Match {
+        match_kwd: SourceSpan,
+        path: Path<'source>,
+        cases: Vec<CaseBranch<'source>>,
+        endmatch_kwd: SourceSpan,
+    },
+    /// Another template spliced in at compile time, whose spans point into its own source
+    Include {
+        include_kwd: SourceSpan,
+        path: M<&'source str>,
+        file: FileData<'source>,
+    },
+    /// A block that templates extending this one can override.
+    /// Once the template is parsed, the contents are those of the last override,
+    /// with spans pointing into the source of the template it's from.
+    Block {
+        block_kwd: SourceSpan,
+        name: M<&'source str>,
+        contents: FileData<'source>,
+    },
+    /// `{{ super() }}`, which renders the block the enclosing block overrides.
+    /// It's replaced by that block once the template is parsed.
+    Super {
+        span: SourceSpan,
+    },
+    /// A macro like `{% macro card(title, body) %}`, which renders nothing where it's defined.
+    /// Its contents can use its arguments, which are strings, and the template's parameters.
+    Macro {
+        macro_kwd: SourceSpan,
+        name: M<&'source str>,
+        args: Vec<M<&'source str>>,
+        contents: FileData<'source>,
+        endmacro_kwd: SourceSpan,
+    },
+    /// A call to a macro like `{{ card(post.title, "Read more") }}`,
+    /// where each argument is a parameter or a string literal
+    Call {
+        name: M<&'source str>,
+        args: Vec<Expr<'source>>,
+    },
+    /// A variable like `{% set heading = title | upper ~ "!" %}`, which renders nothing where it's set.
+    /// Its value is a string, which the nodes after it in the same sequence can use in place of a parameter.
+    Set {
+        set_kwd: SourceSpan,
+        name: M<&'source str>,
+        /// Which of the template's variables this is, numbered once the template is parsed
+        index: usize,
+        terms: Vec<Term<'source>>,
+    },
 }
 
+impl<'source> Node<'source> {
+    /// The sequences of nodes nested directly within this one, along with the source of the file they're from
+    pub(crate) fn children<'a>(
+        &'a self,
+        source: &'a Arc<NamedSource>,
+    ) -> Vec<(&'a [Node<'source>], &'a Arc<NamedSource>)> {
+        match self {
+            Node::Text { .. }
+            | Node::Parameter { .. }
+            | Node::Super { .. }
+            | Node::Call { .. }
+            | Node::Set { .. } => Vec::new(),
+            Node::Conditional {
+                contents,
+                elif_branches,
+                else_branch,
+                ..
+            } => std::iter::once(contents)
+                .chain(elif_branches.iter().map(|branch| &branch.contents))
+                .chain(else_branch.iter().map(|branch| &branch.contents))
+                .map(|contents| (contents.as_slice(), source))
+                .collect(),
+            Node::Loop { contents, .. } => vec![(contents, source)],
+            Node::Match { cases, .. } => cases
+                .iter()
+                .map(|case| (case.contents.as_slice(), source))
+                .collect(),
+            Node::Include { file, .. }
+            | Node::Block { contents: file, .. }
+            | Node::Macro { contents: file, .. } => {
+                vec![(&file.contents, &file.source)]
+            }
+        }
+    }
+
+    /// The sequences of nodes nested directly within this one
+    pub(crate) fn children_mut(&mut self) -> Vec<&mut Vec<Node<'source>>> {
+        match self {
+            Node::Text { .. }
+            | Node::Parameter { .. }
+            | Node::Super { .. }
+            | Node::Call { .. }
+            | Node::Set { .. } => Vec::new(),
+            Node::Conditional {
+                contents,
+                elif_branches,
+                else_branch,
+                ..
+            } => std::iter::once(contents)
+                .chain(elif_branches.iter_mut().map(|branch| &mut branch.contents))
+                .chain(else_branch.iter_mut().map(|branch| &mut branch.contents))
+                .collect(),
+            Node::Loop { contents, .. } => vec![contents],
+            Node::Match { cases, .. } => cases.iter_mut().map(|case| &mut case.contents).collect(),
+            Node::Include { file, .. }
+            | Node::Block { contents: file, .. }
+            | Node::Macro { contents: file, .. } => {
+                vec![&mut file.contents]
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ElIfBranch<'source> {
+    pub elif_kwd: SourceSpan,
+    pub cond: Expr<'source>,
+    pub contents: Vec<Node<'source>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ElseBranch<'source> {
+    pub else_kwd: SourceSpan,
+    pub contents: Vec<Node<'source>>,
+}
+
+/// The contents rendered when a matched value is the named case
+#[derive(Debug, Clone)]
+pub struct CaseBranch<'source> {
+    pub case_kwd: SourceSpan,
+    pub name: M<&'source str>,
+    /// The name the payload of a variant case is bound to, e.g. `name` in `{% case user(name) %}`
+    pub binding: Option<M<&'source str>>,
+    pub contents: Vec<Node<'source>>,
+}
+
+/// One of the values joined with `~` into the value of a variable, e.g. `title | upper`
+#[derive(Debug, Clone)]
+pub struct Term<'source> {
+    /// A parameter or a string literal
+    pub value: Expr<'source>,
+    pub filters: Vec<Filter<'source>>,
+}
+
+/// The condition of an `if` or `elif`, like `not draft and (views > 10 or pinned)`
+#[derive(Debug, Clone)]
+pub enum Expr<'source> {
+    /// A parameter, which holds when it's truthy
+    Path(Path<'source>),
+    /// A literal, which can only be compared
+    Literal(M<Literal<'source>>),
+    Not {
+        not_kwd: SourceSpan,
+        operand: Box<Expr<'source>>,
+    },
+    Binary {
+        op: M<BinaryOp>,
+        left: Box<Expr<'source>>,
+        right: Box<Expr<'source>>,
+    },
+}
+
+impl<'source> Expr<'source> {
+    /// The span covering the whole expression, except any outer parentheses
+    pub fn span(&self) -> SourceSpan {
+        let (start, end) = match self {
+            Expr::Path(path) => return path.span(),
+            Expr::Literal(literal) => return literal.span,
+            Expr::Not { not_kwd, operand } => (*not_kwd, operand.span()),
+            Expr::Binary { left, right, .. } => (left.span(), right.span()),
+        };
+        SourceSpan::from((start.offset(), end.offset() + end.len() - start.offset()))
+    }
+}
+
+/// An operator joining two expressions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl BinaryOp {
+    /// The comparison operator written as the token, if it is one
+    fn comparison(token: &Token) -> Option<Self> {
+        match token {
+            Token::EqEq => Some(BinaryOp::Eq),
+            Token::NotEq => Some(BinaryOp::Ne),
+            Token::Lt => Some(BinaryOp::Lt),
+            Token::LtEq => Some(BinaryOp::Le),
+            Token::Gt => Some(BinaryOp::Gt),
+            Token::GtEq => Some(BinaryOp::Ge),
+            _ => None,
+        }
+    }
+
+    /// Whether the operator compares its operands rather than combining conditions
+    pub fn is_comparison(&self) -> bool {
+        !matches!(self, BinaryOp::Or | BinaryOp::And)
+    }
+
+    /// Whether the operator orders its operands, which only works for numbers
+    pub fn is_ordering(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge
+        )
+    }
+}
+
+/// A built-in filter that transforms an interpolated parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FilterKind {
+    Upper,
+    Lower,
+    Trim,
+    Truncate,
+    Replace,
+    Default,
+    Escape,
+    Safe,
+}
+
+impl FilterKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "upper" => Some(FilterKind::Upper),
+            "lower" => Some(FilterKind::Lower),
+            "trim" => Some(FilterKind::Trim),
+            "truncate" => Some(FilterKind::Truncate),
+            "replace" => Some(FilterKind::Replace),
+            "default" => Some(FilterKind::Default),
+            "escape" | "e" => Some(FilterKind::Escape),
+            "safe" => Some(FilterKind::Safe),
+            _ => None,
+        }
+    }
+
+    /// The kinds of the literal arguments the filter takes
+    pub fn arg_kinds(&self) -> &'static [LiteralKind] {
+        match self {
+            FilterKind::Upper
+            | FilterKind::Lower
+            | FilterKind::Trim
+            | FilterKind::Escape
+            | FilterKind::Safe => &[],
+            FilterKind::Truncate => &[LiteralKind::Integer],
+            FilterKind::Replace => &[LiteralKind::String, LiteralKind::String],
+            FilterKind::Default => &[LiteralKind::String],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralKind {
+    String,
+    Integer,
+}
+
+#[derive(Debug, Clone)]
+pub enum Literal<'source> {
+    String(&'source str),
+    Integer(i64),
+}
+
+impl<'source> Literal<'source> {
+    pub fn kind(&self) -> LiteralKind {
+        match self {
+            Literal::String(_) => LiteralKind::String,
+            Literal::Integer(_) => LiteralKind::Integer,
+        }
+    }
+}
+
+/// A filter and its arguments, e.g. `truncate(20)`
+#[derive(Debug, Clone)]
+pub struct Filter<'source> {
+    pub kind: M<FilterKind>,
+    pub args: Vec<M<Literal<'source>>>,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("{message}")]
+#[diagnostic()]
+pub struct FilterError {
+    #[source_code]
+    src: Arc<NamedSource>,
+    #[label("Here")]
+    span: SourceSpan,
+    message: String,
+}
+
+/// A token that doesn't fit the structure of the template
+#[derive(Error, Debug, Diagnostic)]
+#[error("{message}")]
+#[diagnostic()]
+pub struct ParseError {
+    #[source_code]
+    src: Arc<NamedSource>,
+    #[label("Here")]
+    span: SourceSpan,
+    message: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(src: Arc<NamedSource>, span: SourceSpan, message: String) -> Self {
+        ParseError { src, span, message }
+    }
+}
+
+/// Parses a template that isn't read from a file, so it can't include or extend other files
 pub fn parse_file<'source>(
+    config: &Config,
     source: Arc<NamedSource>,
     text: &'source str,
 ) -> Result<FileData<'source>> {
-    let tokens = Tokenizer::new(source.clone(), text).tokenize()?;
-    let mut token_iter = tokens.into_iter().peekable();
+    parse_template(config, source, text, None)
+}
 
-    let contents = parse_tokens(source.clone(), &mut token_iter)?;
+/// Parses a template, resolving the files it includes and extends
+/// through the files loaded with it, if there are any
+pub(crate) fn parse_template<'source>(
+    config: &Config,
+    source: Arc<NamedSource>,
+    text: &'source str,
+    files: Option<(&'source SourceFiles, usize)>,
+) -> Result<FileData<'source>> {
+    let mut file_data = parse_source(config, source, text, files)?;
+    check_calls(&file_data)?;
+    number_nodes(&mut file_data.contents, &mut 0, &mut 0);
+    Ok(file_data)
+}
 
-    Ok(FileData { source, contents })
+/// Parses one file and the files it includes, then overrides the blocks of the template it extends
+fn parse_source<'source>(
+    config: &Config,
+    source: Arc<NamedSource>,
+    text: &'source str,
+    files: Option<(&'source SourceFiles, usize)>,
+) -> Result<FileData<'source>> {
+    let (file_data, mut extends) = parse_unresolved(config, source, text, files)?;
+    let mut chain = vec![file_data];
+    while let Some((files, parent)) = extends {
+        let (source, text) = files.source(parent);
+        let (file_data, parent_extends) =
+            parse_unresolved(config, source.clone(), text, Some((files, parent)))?;
+        chain.push(file_data);
+        extends = parent_extends;
+    }
+    inherit::resolve(chain)
 }
 
-fn parse_tokens<'source, Iter>(source: Arc<NamedSource>, token_iter: &mut Peekable<Iter>) -> Result<Vec<Node<'source>>>
-where
-    Iter: Iterator<Item = (SourceSpan, Token<'source>)>,
-{
-    let mut contents = Vec::new();
+/// Parses one file and the files it includes, along with the file it extends if there is one
+fn parse_unresolved<'source>(
+    config: &Config,
+    source: Arc<NamedSource>,
+    text: &'source str,
+    files: Option<(&'source SourceFiles, usize)>,
+) -> Result<(FileData<'source>, Option<(&'source SourceFiles, usize)>)> {
+    let tokens = Tokenizer::new(source.clone(), text)
+        .trim_blocks(config.trim_blocks)
+        .lstrip_blocks(config.lstrip_blocks)
+        .tokenize()?;
+    let mut parser = Parser {
+        config,
+        source: source.clone(),
+        tokens: tokens.into_iter().peekable(),
+        end: text.len(),
+        files,
+        extends: None,
+    };
+
+    let contents = parser.parse_sequence(Block::Root)?;
+    let extends = parser.extends.map(|parent| (files.unwrap().0, parent));
 
-    while let Some((span, token)) = token_iter.next() {
-        match token {
-            Token::CommandStart => {
-                if token_iter.peek().unwrap().1 == Token::EndIf {
-                    return Ok(contents);
+    Ok((FileData { source, contents }, extends))
+}
+
+/// Calls `f` with each node of a sequence and the nodes nested within it,
+/// along with the source of the file each is from
+fn visit<'a, 'source>(
+    nodes: &'a [Node<'source>],
+    source: &'a Arc<NamedSource>,
+    f: &mut impl FnMut(&'a Node<'source>, &'a Arc<NamedSource>) -> Result<()>,
+) -> Result<()> {
+    for node in nodes {
+        f(node, source)?;
+        for (children, source) in node.children(source) {
+            visit(children, source, f)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that each call names a macro defined somewhere in the template and passes all of its arguments,
+/// and that no macro calls itself, directly or through other macros, since rendering it would never end
+fn check_calls(file_data: &FileData) -> Result<()> {
+    let mut macros = BTreeMap::new();
+    visit(
+        &file_data.contents,
+        &file_data.source,
+        &mut |node, source| {
+            if let Node::Macro {
+                name,
+                args,
+                contents,
+                ..
+            } = node
+            {
+                if macros.insert(name.value, (args.len(), contents)).is_some() {
+                    let message = format!("Duplicate macro \"{}\"", name.value);
+                    return Err(ParseError::new(source.clone(), name.span, message).into());
+                }
+            }
+            Ok(())
+        },
+    )?;
+    visit(
+        &file_data.contents,
+        &file_data.source,
+        &mut |node, source| {
+            if let Node::Call { name, args } = node {
+                let Some(&(arg_count, _)) = macros.get(name.value) else {
+                    let message = format!("Unknown macro \"{}\"", name.value);
+                    return Err(ParseError::new(source.clone(), name.span, message).into());
+                };
+                if args.len() != arg_count {
+                    let message = format!(
+                        "Macro \"{}\" takes {} argument(s) but was given {}",
+                        name.value,
+                        arg_count,
+                        args.len()
+                    );
+                    return Err(ParseError::new(source.clone(), name.span, message).into());
+                }
+            }
+            Ok(())
+        },
+    )?;
+    let mut checked = Vec::new();
+    for name in macros.keys() {
+        check_recursion(name, &macros, &mut Vec::new(), &mut checked)?;
+    }
+    Ok(())
+}
+
+/// Checks that a macro doesn't call any of the macros `calling` it,
+/// which are those whose calls led to it, or any it calls in turn
+fn check_recursion<'source>(
+    name: &'source str,
+    macros: &BTreeMap<&'source str, (usize, &FileData<'source>)>,
+    calling: &mut Vec<&'source str>,
+    checked: &mut Vec<&'source str>,
+) -> Result<()> {
+    if checked.contains(&name) {
+        return Ok(());
+    }
+    calling.push(name);
+    let (_, contents) = macros[name];
+    let mut calls = Vec::new();
+    collect_calls(&contents.contents, &contents.source, &mut calls);
+    for (call, source) in calls {
+        if calling.contains(&call.value) {
+            let message = format!("Macro \"{}\" is called recursively", call.value);
+            return Err(ParseError::new(source.clone(), call.span, message).into());
+        }
+        check_recursion(call.value, macros, calling, checked)?;
+    }
+    calling.pop();
+    checked.push(name);
+    Ok(())
+}
+
+/// Collects the macro calls rendered by a sequence of nodes,
+/// skipping the contents of macros defined in it since those only render where they're called
+fn collect_calls<'a, 'source>(
+    nodes: &'a [Node<'source>],
+    source: &'a Arc<NamedSource>,
+    calls: &mut Vec<(&'a M<&'source str>, &'a Arc<NamedSource>)>,
+) {
+    for node in nodes {
+        match node {
+            Node::Call { name, .. } => calls.push((name, source)),
+            Node::Macro { .. } => continue,
+            _ => {}
+        }
+        for (children, source) in node.children(source) {
+            collect_calls(children, source, calls);
+        }
+    }
+}
+
+/// Numbers the text nodes in the order they're written, which is the order of their data segments,
+/// and the variables, which each get their own locals.
+/// This is done once the whole template is parsed, since blocks move and copy nodes around.
+fn number_nodes(nodes: &mut [Node], texts: &mut usize, sets: &mut usize) {
+    for node in nodes {
+        let count = match node {
+            Node::Text { index, .. } => Some((index, &mut *texts)),
+            Node::Set { index, .. } => Some((index, &mut *sets)),
+            _ => None,
+        };
+        if let Some((index, count)) = count {
+            *index = *count;
+            *count += 1;
+        }
+        for children in node.children_mut() {
+            number_nodes(children, texts, sets);
+        }
+    }
+}
+
+/// The block a sequence of nodes is the contents of
+#[derive(Clone, Copy)]
+enum Block {
+    Root,
+    If { if_kwd: SourceSpan },
+    For { for_kwd: SourceSpan },
+    Match { match_kwd: SourceSpan },
+    Named { block_kwd: SourceSpan },
+    Macro { macro_kwd: SourceSpan },
+}
+
+struct Parser<'config, 'source> {
+    config: &'config Config,
+    source: Arc<NamedSource>,
+    tokens: Peekable<IntoIter<(SourceSpan, Token<'source>)>>,
+    // The offset of the end of the file
+    end: usize,
+    // The files loaded with the template and which of them is being parsed
+    files: Option<(&'source SourceFiles, usize)>,
+    // The file this one extends
+    extends: Option<usize>,
+}
+
+impl<'config, 'source> Parser<'config, 'source> {
+    /// Parses nodes until the end of the block they're in.
+    /// Returns after consuming the command start of the tag ending the block.
+    fn parse_sequence(&mut self, block: Block) -> Result<Vec<Node<'source>>> {
+        let mut contents = Vec::new();
+
+        while let Some((span, token)) = self.tokens.next() {
+            match token {
+                Token::CommandStart => match (self.peek(), block) {
+                    (Some(Token::ElIf | Token::Else | Token::EndIf), Block::If { .. })
+                    | (Some(Token::EndFor), Block::For { .. })
+                    | (Some(Token::Case | Token::EndCase | Token::EndMatch), Block::Match { .. })
+                    | (Some(Token::EndBlock), Block::Named { .. })
+                    | (Some(Token::EndMacro), Block::Macro { .. }) => return Ok(contents),
+                    (Some(Token::For), _) => contents.push(self.parse_loop()?),
+                    (Some(Token::If), _) => contents.push(self.parse_conditional()?),
+                    (Some(Token::Match), _) => contents.push(self.parse_match()?),
+                    (Some(Token::Set), _) => contents.push(self.parse_set()?),
+                    (Some(Token::Include), _) => contents.push(self.parse_include()?),
+                    (Some(Token::Block), _) => contents.push(self.parse_block()?),
+                    (Some(Token::Extends), Block::Root) => self.parse_extends()?,
+                    (Some(Token::Macro), Block::Root) => contents.push(self.parse_macro()?),
+                    (
+                        Some(
+                            Token::ElIf
+                            | Token::Else
+                            | Token::EndIf
+                            | Token::EndFor
+                            | Token::Case
+                            | Token::EndCase
+                            | Token::EndMatch
+                            | Token::EndBlock
+                            | Token::Extends
+                            | Token::Macro
+                            | Token::EndMacro,
+                        ),
+                        _,
+                    ) => {
+                        let (span, token) = self.tokens.next().unwrap();
+                        return Err(self.error(span, format!("Unexpected {}", token.describe())));
+                    }
+                    _ => {
+                        let found = self.tokens.next();
+                        return Err(self.expected(
+                            "if, for, match, set, block, include, extends, or macro",
+                            found,
+                        ));
+                    }
+                },
+                Token::ParamStart => {
+                    let name = self.match_path()?;
+                    if name.segments.len() == 1 && self.peek() == Some(&Token::LParen) {
+                        contents.push(self.parse_call(name.segments.into_iter().next().unwrap())?);
+                        self.match_token(Token::ParamEnd)?;
+                        continue;
+                    }
+                    let mut annotation = None;
+                    if self.peek() == Some(&Token::Colon) {
+                        self.match_token(Token::Colon)?;
+                        annotation = Some(self.match_ident()?);
+                    }
+                    let filters = self.parse_filters()?;
+                    contents.push(Node::Parameter {
+                        name,
+                        annotation,
+                        filters,
+                    });
+                    self.match_token(Token::ParamEnd)?;
+                }
+                Token::Text { text, .. } => {
+                    // texts are numbered once the template is parsed
+                    contents.push(Node::Text {
+                        index: 0,
+                        text: M::new(text, span),
+                    });
+                }
+                token => return Err(self.error(span, format!("Unexpected {}", token.describe()))),
+            }
+        }
+
+        match block {
+            Block::Root => Ok(contents),
+            Block::If { if_kwd } => Err(self.error(if_kwd, "Unclosed if".into())),
+            Block::For { for_kwd } => Err(self.error(for_kwd, "Unclosed for".into())),
+            Block::Match { match_kwd } => Err(self.error(match_kwd, "Unclosed match".into())),
+            Block::Named { block_kwd } => Err(self.error(block_kwd, "Unclosed block".into())),
+            Block::Macro { macro_kwd } => Err(self.error(macro_kwd, "Unclosed macro".into())),
+        }
+    }
+
+    fn parse_loop(&mut self) -> Result<Node<'source>> {
+        let for_kwd = self.match_token(Token::For)?;
+        let item_ident = self.match_ident()?;
+        let in_kwd = self.match_token(Token::In)?;
+        let list_path = self.match_path()?;
+        self.match_token(Token::CommandEnd)?;
+
+        let contents = self.parse_sequence(Block::For { for_kwd })?;
+
+        let endfor_kwd = self.match_token(Token::EndFor)?;
+        self.match_token(Token::CommandEnd)?;
+
+        Ok(Node::Loop {
+            for_kwd,
+            item_ident,
+            in_kwd,
+            list_path,
+            contents,
+            endfor_kwd,
+        })
+    }
+
+    fn parse_conditional(&mut self) -> Result<Node<'source>> {
+        let if_kwd = self.match_token(Token::If)?;
+        let cond = self.parse_expr()?;
+        self.match_token(Token::CommandEnd)?;
+
+        let block = Block::If { if_kwd };
+        let if_contents = self.parse_sequence(block)?;
+
+        let mut elif_branches = Vec::new();
+        while self.peek() == Some(&Token::ElIf) {
+            let elif_kwd = self.match_token(Token::ElIf)?;
+            let cond = self.parse_expr()?;
+            self.match_token(Token::CommandEnd)?;
+
+            let contents = self.parse_sequence(block)?;
+
+            elif_branches.push(ElIfBranch {
+                elif_kwd,
+                cond,
+                contents,
+            });
+        }
+
+        let mut else_branch = None;
+        if self.peek() == Some(&Token::Else) {
+            let else_kwd = self.match_token(Token::Else)?;
+            self.match_token(Token::CommandEnd)?;
+
+            let contents = self.parse_sequence(block)?;
+
+            else_branch = Some(ElseBranch { else_kwd, contents });
+        }
+
+        let endif_kwd = self.match_token(Token::EndIf)?;
+        self.match_token(Token::CommandEnd)?;
+
+        Ok(Node::Conditional {
+            if_kwd,
+            cond,
+            contents: if_contents,
+            elif_branches,
+            else_branch,
+            endif_kwd,
+        })
+    }
+
+    fn parse_match(&mut self) -> Result<Node<'source>> {
+        let match_kwd = self.match_token(Token::Match)?;
+        let path = self.match_path()?;
+        self.match_token(Token::CommandEnd)?;
+        self.match_case_start()?;
+
+        let block = Block::Match { match_kwd };
+        let mut cases: Vec<CaseBranch> = Vec::new();
+        while self.peek() == Some(&Token::Case) {
+            let case_kwd = self.match_token(Token::Case)?;
+            let name = self.match_ident()?;
+            let mut binding = None;
+            if self.peek() == Some(&Token::LParen) {
+                self.match_token(Token::LParen)?;
+                binding = Some(self.match_ident()?);
+                self.match_token(Token::RParen)?;
+            }
+            self.match_token(Token::CommandEnd)?;
+            if cases.iter().any(|case| case.name.value == name.value) {
+                return Err(self.error(name.span, format!("Duplicate case `{}`", name.value)));
+            }
+
+            let contents = self.parse_sequence(block)?;
+
+            // a case can optionally be closed, which lets whitespace follow it
+            if self.peek() == Some(&Token::EndCase) {
+                self.match_token(Token::EndCase)?;
+                self.match_token(Token::CommandEnd)?;
+                self.match_case_start()?;
+            }
+
+            cases.push(CaseBranch {
+                case_kwd,
+                name,
+                binding,
+                contents,
+            });
+        }
+
+        let endmatch_kwd = self.match_token(Token::EndMatch)?;
+        self.match_token(Token::CommandEnd)?;
+
+        Ok(Node::Match {
+            match_kwd,
+            path,
+            cases,
+            endmatch_kwd,
+        })
+    }
+
+    /// Skips the whitespace before the next case (or the end of the match)
+    /// and the `{%` that starts it
+    fn match_case_start(&mut self) -> Result<()> {
+        while let Some((span, Token::Text { text, .. })) = self.tokens.peek() {
+            if !text.trim().is_empty() {
+                let span = *span;
+                return Err(self.error(span, "Expected case, found text".into()));
+            }
+            self.tokens.next();
+        }
+        self.match_token(Token::CommandStart)?;
+        Ok(())
+    }
+
+    /// Parses a variable and the terms of its value, which are joined with `~`
+    fn parse_set(&mut self) -> Result<Node<'source>> {
+        let set_kwd = self.match_token(Token::Set)?;
+        let name = self.match_ident()?;
+        self.match_token(Token::Eq)?;
+
+        let mut terms = Vec::new();
+        loop {
+            let value = match self.peek() {
+                Some(Token::String { .. }) => Expr::Literal(self.match_literal()?),
+                Some(Token::Identifier { .. }) => Expr::Path(self.match_path()?),
+                _ => {
+                    let found = self.tokens.next();
+                    return Err(self.expected("string or parameter", found));
                 }
+            };
+            let filters = self.parse_filters()?;
+            terms.push(Term { value, filters });
+            if self.peek() != Some(&Token::Tilde) {
+                break;
+            }
+            self.match_token(Token::Tilde)?;
+        }
+        self.match_token(Token::CommandEnd)?;
+
+        Ok(Node::Set {
+            set_kwd,
+            name,
+            // variables are numbered once the template is parsed
+            index: 0,
+            terms,
+        })
+    }
+
+    /// Parses an include and the file it names, which was loaded along with the template
+    fn parse_include(&mut self) -> Result<Node<'source>> {
+        let include_kwd = self.match_token(Token::Include)?;
+        let path = match self.tokens.next() {
+            Some((span, Token::String { value })) => M::new(value, span),
+            found => return Err(self.expected("string", found)),
+        };
+        self.match_token(Token::CommandEnd)?;
+
+        let Some((files, file)) = self.files else {
+            return Err(self.error(
+                path.span,
+                "Only templates read from files can include others".into(),
+            ));
+        };
+        let included = files.included(file, path.value);
+        let (source, text) = files.source(included);
+        let file = parse_source(self.config, source.clone(), text, Some((files, included)))?;
+        Ok(Node::Include {
+            include_kwd,
+            path,
+            file,
+        })
+    }
+
+    /// Parses the template this one extends, which was loaded along with it
+    fn parse_extends(&mut self) -> Result<()> {
+        let extends_kwd = self.match_token(Token::Extends)?;
+        let path = match self.tokens.next() {
+            Some((span, Token::String { value })) => M::new(value, span),
+            found => return Err(self.expected("string", found)),
+        };
+        self.match_token(Token::CommandEnd)?;
+
+        let Some((files, file)) = self.files else {
+            return Err(self.error(
+                path.span,
+                "Only templates read from files can extend others".into(),
+            ));
+        };
+        if self.extends.is_some() {
+            return Err(self.error(extends_kwd, "A template can only extend one other".into()));
+        }
+        self.extends = Some(files.included(file, path.value));
+        Ok(())
+    }
 
-                let if_kwd = match_token(token_iter, Token::If)?;
-                let cond_ident = match_ident(token_iter)?;
-                match_token(token_iter, Token::CommandEnd)?;
+    fn parse_block(&mut self) -> Result<Node<'source>> {
+        let block_kwd = self.match_token(Token::Block)?;
+        let name = self.match_ident()?;
+        self.match_token(Token::CommandEnd)?;
 
-                let if_contents = parse_tokens(source.clone(), token_iter)?;
+        let contents = self.parse_sequence(Block::Named { block_kwd })?;
 
-                let endif_kwd = match_token(token_iter, Token::EndIf)?;
-                match_token(token_iter, Token::CommandEnd)?;
+        self.match_token(Token::EndBlock)?;
+        // the name can be repeated after `endblock`
+        if let Some(Token::Identifier { name: end_name }) = self.peek() {
+            if *end_name != name.value {
+                let (span, _) = self.tokens.next().unwrap();
+                return Err(self.error(span, format!("Expected endblock for `{}`", name.value)));
+            }
+            self.tokens.next();
+        }
+        self.match_token(Token::CommandEnd)?;
 
-                contents.push(Node::Conditional {
-                    if_kwd,
-                    cond_ident,
-                    contents: if_contents,
-                    endif_kwd,
-                })
+        Ok(Node::Block {
+            block_kwd,
+            name,
+            contents: FileData {
+                source: self.source.clone(),
+                contents,
+            },
+        })
+    }
+
+    fn parse_macro(&mut self) -> Result<Node<'source>> {
+        let macro_kwd = self.match_token(Token::Macro)?;
+        let name = self.match_ident()?;
+        self.match_token(Token::LParen)?;
+        let mut args: Vec<M<&str>> = Vec::new();
+        while self.peek() != Some(&Token::RParen) {
+            let arg = self.match_ident()?;
+            if args.iter().any(|other| other.value == arg.value) {
+                return Err(self.error(arg.span, format!("Duplicate argument `{}`", arg.value)));
             }
-            Token::ParamStart => {
-                let name = match_ident(token_iter)?;
-                contents.push(Node::Parameter { name });
-                match_token(token_iter, Token::ParamEnd)?;
+            args.push(arg);
+            if self.peek() == Some(&Token::Comma) {
+                self.match_token(Token::Comma)?;
+            } else {
+                break;
             }
-            Token::Text { index, text } => {
-                contents.push(Node::Text {
-                    index,
-                    text: M::new(text, span),
-                });
+        }
+        self.match_token(Token::RParen)?;
+        self.match_token(Token::CommandEnd)?;
+
+        let contents = self.parse_sequence(Block::Macro { macro_kwd })?;
+
+        let endmacro_kwd = self.match_token(Token::EndMacro)?;
+        self.match_token(Token::CommandEnd)?;
+
+        Ok(Node::Macro {
+            macro_kwd,
+            name,
+            args,
+            contents: FileData {
+                source: self.source.clone(),
+                contents,
+            },
+            endmacro_kwd,
+        })
+    }
+
+    /// Parses the arguments of a call to a macro, or of `super()`
+    fn parse_call(&mut self, name: M<&'source str>) -> Result<Node<'source>> {
+        self.match_token(Token::LParen)?;
+        if name.value == "super" {
+            self.match_token(Token::RParen)?;
+            return Ok(Node::Super { span: name.span });
+        }
+
+        let mut args = Vec::new();
+        while self.peek() != Some(&Token::RParen) {
+            let arg = match self.peek() {
+                Some(Token::String { .. }) => Expr::Literal(self.match_literal()?),
+                Some(Token::Identifier { .. }) => Expr::Path(self.match_path()?),
+                _ => {
+                    let found = self.tokens.next();
+                    return Err(self.expected("string or parameter", found));
+                }
+            };
+            args.push(arg);
+            if self.peek() == Some(&Token::Comma) {
+                self.match_token(Token::Comma)?;
+            } else {
+                break;
             }
-            _ => todo!(),
         }
+        self.match_token(Token::RParen)?;
+        Ok(Node::Call { name, args })
     }
 
-    Ok(contents)
-}
+    /// Parses a condition, where `or` binds loosest, followed by `and`, `not`, and comparisons
+    fn parse_expr(&mut self) -> Result<Expr<'source>> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            let op = M::new(BinaryOp::Or, self.match_token(Token::Or)?);
+            let right = self.parse_and()?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
 
-fn match_token<'source, Iter>(token_iter: &mut Iter, token: Token<'source>) -> Result<SourceSpan>
-where
-    Iter: Iterator<Item = (SourceSpan, Token<'source>)>,
-{
-    match token_iter.next() {
-        Some((span, t)) if t == token => Ok(span),
-        Some(st) => { dbg!(st); todo!() },
-        None => todo!("error"),
+    fn parse_and(&mut self) -> Result<Expr<'source>> {
+        let mut left = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            let op = M::new(BinaryOp::And, self.match_token(Token::And)?);
+            let right = self.parse_not()?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr<'source>> {
+        if self.peek() == Some(&Token::Not) {
+            let not_kwd = self.match_token(Token::Not)?;
+            let operand = self.parse_not()?;
+            return Ok(Expr::Not {
+                not_kwd,
+                operand: Box::new(operand),
+            });
+        }
+        self.parse_comparison()
+    }
+
+    /// Parses an operand optionally compared to another,
+    /// since comparisons can't be chained
+    fn parse_comparison(&mut self) -> Result<Expr<'source>> {
+        let left = self.parse_operand()?;
+        let Some(op) = self.peek().and_then(BinaryOp::comparison) else {
+            return Ok(left);
+        };
+        let (span, _) = self.tokens.next().unwrap();
+        let right = self.parse_operand()?;
+        Ok(Expr::Binary {
+            op: M::new(op, span),
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    fn parse_operand(&mut self) -> Result<Expr<'source>> {
+        match self.peek() {
+            Some(Token::LParen) => {
+                self.match_token(Token::LParen)?;
+                let expr = self.parse_expr()?;
+                self.match_token(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::String { .. } | Token::Integer { .. }) => {
+                Ok(Expr::Literal(self.match_literal()?))
+            }
+            _ => Ok(Expr::Path(self.match_path()?)),
+        }
+    }
+
+    /// Parses a sequence of filters each starting with `|`
+    fn parse_filters(&mut self) -> Result<Vec<Filter<'source>>> {
+        let mut filters = Vec::new();
+        while self.peek() == Some(&Token::Pipe) {
+            self.match_token(Token::Pipe)?;
+            let name = self.match_ident()?;
+            let kind = FilterKind::from_name(name.value).ok_or_else(|| FilterError {
+                src: self.source.clone(),
+                span: name.span,
+                message: format!("Unknown filter \"{}\"", name.value),
+            })?;
+
+            let mut args = Vec::new();
+            if self.peek() == Some(&Token::LParen) {
+                self.match_token(Token::LParen)?;
+                while self.peek() != Some(&Token::RParen) {
+                    args.push(self.match_literal()?);
+                    if self.peek() == Some(&Token::Comma) {
+                        self.match_token(Token::Comma)?;
+                    } else {
+                        break;
+                    }
+                }
+                self.match_token(Token::RParen)?;
+            }
+
+            let arg_kinds = kind.arg_kinds();
+            if args.len() != arg_kinds.len() {
+                return Err(FilterError {
+                    src: self.source.clone(),
+                    span: name.span,
+                    message: format!(
+                        "Filter \"{}\" takes {} argument(s) but was given {}",
+                        name.value,
+                        arg_kinds.len(),
+                        args.len()
+                    ),
+                }
+                .into());
+            }
+            for (arg, arg_kind) in args.iter().zip(arg_kinds) {
+                let expected = match (arg.value.kind(), arg_kind, &arg.value) {
+                    (LiteralKind::String, LiteralKind::Integer, _) => Some("an integer"),
+                    (LiteralKind::Integer, LiteralKind::String, _) => Some("a string"),
+                    (_, _, Literal::Integer(value)) if u32::try_from(*value).is_err() => {
+                        Some("an integer between 0 and 4294967295")
+                    }
+                    _ => None,
+                };
+                if let Some(expected) = expected {
+                    return Err(FilterError {
+                        src: self.source.clone(),
+                        span: arg.span,
+                        message: format!("Expected {}", expected),
+                    }
+                    .into());
+                }
+            }
+
+            filters.push(Filter {
+                kind: M::new(kind, name.span),
+                args,
+            });
+        }
+        Ok(filters)
+    }
+
+    fn peek(&mut self) -> Option<&Token<'source>> {
+        self.tokens.peek().map(|(_, token)| token)
+    }
+
+    fn match_token(&mut self, token: Token<'source>) -> Result<SourceSpan> {
+        match self.tokens.next() {
+            Some((span, t)) if t == token => Ok(span),
+            found => Err(self.expected(&token.describe(), found)),
+        }
+    }
+
+    fn match_ident(&mut self) -> Result<M<&'source str>> {
+        match self.tokens.next() {
+            Some((span, Token::Identifier { name })) => Ok(M::new(name, span)),
+            found => Err(self.expected("identifier", found)),
+        }
+    }
+
+    fn match_path(&mut self) -> Result<Path<'source>> {
+        let mut segments = vec![self.match_ident()?];
+        while self.peek() == Some(&Token::Dot) {
+            self.match_token(Token::Dot)?;
+            segments.push(self.match_ident()?);
+        }
+        Ok(Path { segments })
+    }
+
+    fn match_literal(&mut self) -> Result<M<Literal<'source>>> {
+        match self.tokens.next() {
+            Some((span, Token::String { value })) => Ok(M::new(Literal::String(value), span)),
+            Some((span, Token::Integer { value })) => Ok(M::new(Literal::Integer(value), span)),
+            found => Err(self.expected("string or integer", found)),
+        }
+    }
+
+    fn error(&self, span: SourceSpan, message: String) -> miette::Report {
+        ParseError::new(self.source.clone(), span, message).into()
+    }
+
+    /// An error for finding a token (or the end of the file) where something else was expected
+    fn expected(
+        &self,
+        expected: &str,
+        found: Option<(SourceSpan, Token<'source>)>,
+    ) -> miette::Report {
+        let (span, found) = match found {
+            Some((span, token)) => (span, token.describe()),
+            None => (SourceSpan::from((self.end, 0)), "end of file".into()),
+        };
+        self.error(span, format!("Expected {}, found {}", expected, found))
     }
 }
 
-fn match_ident<'source, Iter>(token_iter: &mut Iter) -> Result<M<&'source str>>
-where
-    Iter: Iterator<Item = (SourceSpan, Token<'source>)>,
-{
-    match token_iter.next() {
-        Some((span, Token::Identifier { name })) => Ok(M::new(name, span)),
-        Some(_) => todo!("error"),
-        None => todo!("error"),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Parses the text and returns the message and span of the parse error
+    fn parse_error(text: &str) -> (String, SourceSpan) {
+        let source = Arc::new(NamedSource::new("parse_error_test", text.to_owned()));
+        let error = parse_file(&Config::default(), source, text).unwrap_err();
+        let error = error.downcast::<ParseError>().unwrap();
+        (error.message, error.span)
+    }
+
+    #[test]
+    fn unclosed_if_test() {
+        assert_eq!(
+            parse_error("A {% if foo %}B"),
+            ("Unclosed if".into(), SourceSpan::from((5, 2)))
+        );
+        assert_eq!(
+            parse_error("{% if foo %}{% else %}B"),
+            ("Unclosed if".into(), SourceSpan::from((3, 2)))
+        );
+    }
+
+    #[test]
+    fn unclosed_for_test() {
+        assert_eq!(
+            parse_error("{% for x in xs %}{{ x }}"),
+            ("Unclosed for".into(), SourceSpan::from((3, 3)))
+        );
+    }
+
+    #[test]
+    fn unexpected_end_test() {
+        assert_eq!(
+            parse_error("A {% endif %}"),
+            ("Unexpected endif".into(), SourceSpan::from((5, 5)))
+        );
+        assert_eq!(
+            parse_error("{% if foo %}{% endfor %}"),
+            ("Unexpected endfor".into(), SourceSpan::from((15, 6)))
+        );
+        assert_eq!(
+            parse_error("{% for x in xs %}{% else %}{% endfor %}"),
+            ("Unexpected else".into(), SourceSpan::from((20, 4)))
+        );
+    }
+
+    #[test]
+    fn expected_identifier_test() {
+        assert_eq!(
+            parse_error("A {{ }}"),
+            (
+                "Expected identifier, found \"}}\"".into(),
+                SourceSpan::from((5, 2))
+            )
+        );
+        assert_eq!(
+            parse_error("{{ user. }}"),
+            (
+                "Expected identifier, found \"}}\"".into(),
+                SourceSpan::from((9, 2))
+            )
+        );
+        assert_eq!(
+            parse_error("{% for 'x' in xs %}{% endfor %}"),
+            (
+                "Expected identifier, found string".into(),
+                SourceSpan::from((7, 3))
+            )
+        );
+    }
+
+    #[test]
+    fn expected_token_test() {
+        assert_eq!(
+            parse_error("{{ x y }}"),
+            (
+                "Expected \"}}\", found identifier \"y\"".into(),
+                SourceSpan::from((5, 1))
+            )
+        );
+        assert_eq!(
+            parse_error("{% for x xs %}{% endfor %}"),
+            (
+                "Expected in, found identifier \"xs\"".into(),
+                SourceSpan::from((9, 2))
+            )
+        );
+        assert_eq!(
+            parse_error("{{ x | truncate(10 }}"),
+            (
+                "Expected \")\", found \"}}\"".into(),
+                SourceSpan::from((19, 2))
+            )
+        );
+        assert_eq!(
+            parse_error("{% if a %}{% else %}{% else %}{% endif %}"),
+            (
+                "Expected endif, found else".into(),
+                SourceSpan::from((23, 4))
+            )
+        );
+    }
+
+    #[test]
+    fn match_test() {
+        assert_eq!(
+            parse_error("{% match status %} x {% case active %}{% endmatch %}"),
+            (
+                "Expected case, found text".into(),
+                SourceSpan::from((18, 3))
+            )
+        );
+        assert_eq!(
+            parse_error("{% match status %}{% case a %}{% case a %}{% endmatch %}"),
+            ("Duplicate case `a`".into(), SourceSpan::from((38, 1)))
+        );
+        assert_eq!(
+            parse_error("{% match status %}{% case a %}"),
+            ("Unclosed match".into(), SourceSpan::from((3, 5)))
+        );
+        assert_eq!(
+            parse_error("{% if a %}{% case a %}{% endif %}"),
+            ("Unexpected case".into(), SourceSpan::from((13, 4)))
+        );
+        assert_eq!(
+            parse_error("{% match user %}{% case member(name %}{% endmatch %}"),
+            (
+                "Expected \")\", found \"%}\"".into(),
+                SourceSpan::from((36, 2))
+            )
+        );
+        assert_eq!(
+            parse_error("{% match user %}{% case guest %}{% endcase %} x {% endmatch %}"),
+            (
+                "Expected case, found text".into(),
+                SourceSpan::from((45, 3))
+            )
+        );
+    }
+
+    /// Parses the condition of an `if` and writes it with every operation in parentheses
+    fn parse_cond(cond: &str) -> String {
+        fn show(expr: &Expr) -> String {
+            match expr {
+                Expr::Path(path) => path
+                    .segments
+                    .iter()
+                    .map(|s| s.value)
+                    .collect::<Vec<_>>()
+                    .join("."),
+                Expr::Literal(literal) => match literal.value {
+                    Literal::String(value) => format!("'{}'", value),
+                    Literal::Integer(value) => value.to_string(),
+                },
+                Expr::Not { operand, .. } => format!("(not {})", show(operand)),
+                Expr::Binary { op, left, right } => {
+                    format!("({} {:?} {})", show(left), op.value, show(right))
+                }
+            }
+        }
+        let text = format!("{{% if {} %}}{{% endif %}}", cond);
+        let source = Arc::new(NamedSource::new("parse_cond_test", text.clone()));
+        let file_data = parse_file(&Config::default(), source, &text).unwrap();
+        let Node::Conditional { cond, .. } = &file_data.contents[0] else {
+            unreachable!()
+        };
+        show(cond)
+    }
+
+    #[test]
+    fn expr_test() {
+        assert_eq!(parse_cond("not a and b or c"), "(((not a) And b) Or c)");
+        assert_eq!(parse_cond("a or b and not c"), "(a Or (b And (not c)))");
+        assert_eq!(parse_cond("not user.age >= 18"), "(not (user.age Ge 18))");
+        assert_eq!(
+            parse_cond("(a or b) and name != 'x'"),
+            "((a Or b) And (name Ne 'x'))"
+        );
+        assert_eq!(
+            parse_error("{% if a == b == c %}{% endif %}"),
+            (
+                "Expected \"%}\", found \"==\"".into(),
+                SourceSpan::from((13, 2))
+            )
+        );
+        assert_eq!(
+            parse_error("{% if (a or b %}{% endif %}"),
+            (
+                "Expected \")\", found \"%}\"".into(),
+                SourceSpan::from((14, 2))
+            )
+        );
+    }
+
+    #[test]
+    fn include_test() {
+        assert_eq!(
+            parse_error("A {% include 'x.html' %}"),
+            (
+                "Only templates read from files can include others".into(),
+                SourceSpan::from((13, 8))
+            )
+        );
+        assert_eq!(
+            parse_error("{% include x %}"),
+            (
+                "Expected string, found identifier \"x\"".into(),
+                SourceSpan::from((11, 1))
+            )
+        );
+    }
+
+    #[test]
+    fn block_test() {
+        assert_eq!(
+            parse_error("{% block main %}A"),
+            ("Unclosed block".into(), SourceSpan::from((3, 5)))
+        );
+        assert_eq!(
+            parse_error("{% block main %}A{% endblock side %}"),
+            (
+                "Expected endblock for `main`".into(),
+                SourceSpan::from((29, 4))
+            )
+        );
+        assert_eq!(
+            parse_error("{% block a %}{% endblock %}{% block a %}{% endblock a %}"),
+            ("Duplicate block `a`".into(), SourceSpan::from((36, 1)))
+        );
+        assert_eq!(
+            parse_error("A {{ super() }}"),
+            (
+                "`super()` can only be used in a block".into(),
+                SourceSpan::from((5, 5))
+            )
+        );
+        assert_eq!(
+            parse_error("{% block a %}{{ super() }}{% endblock %}"),
+            (
+                "No block `a` in a parent template for `super()` to render".into(),
+                SourceSpan::from((16, 5))
+            )
+        );
+        assert_eq!(
+            parse_error("{% if x %}{% extends 'base.html' %}{% endif %}"),
+            ("Unexpected extends".into(), SourceSpan::from((13, 7)))
+        );
+        assert_eq!(
+            parse_error("{% extends 'base.html' %}"),
+            (
+                "Only templates read from files can extend others".into(),
+                SourceSpan::from((11, 11))
+            )
+        );
+    }
+
+    #[test]
+    fn macro_test() {
+        assert_eq!(
+            parse_error("{% macro card(title) %}{{ title }}"),
+            ("Unclosed macro".into(), SourceSpan::from((3, 5)))
+        );
+        assert_eq!(
+            parse_error("{% macro card(title, title) %}{% endmacro %}"),
+            (
+                "Duplicate argument `title`".into(),
+                SourceSpan::from((21, 5))
+            )
+        );
+        assert_eq!(
+            parse_error("{% if x %}{% macro card() %}{% endmacro %}{% endif %}"),
+            ("Unexpected macro".into(), SourceSpan::from((13, 5)))
+        );
+        assert_eq!(
+            parse_error("{% macro card(n) %}{% endmacro %}{{ card(3) }}"),
+            (
+                "Expected string or parameter, found integer".into(),
+                SourceSpan::from((41, 1))
+            )
+        );
+        assert_eq!(
+            parse_error("{% macro a() %}{% endmacro %}{% macro a() %}{% endmacro %}"),
+            ("Duplicate macro \"a\"".into(), SourceSpan::from((38, 1)))
+        );
+    }
+
+    #[test]
+    fn set_test() {
+        assert_eq!(
+            parse_error("{% set x = %}"),
+            (
+                "Expected string or parameter, found \"%}\"".into(),
+                SourceSpan::from((11, 2))
+            )
+        );
+        assert_eq!(
+            parse_error("{% set x = a ~ %}"),
+            (
+                "Expected string or parameter, found \"%}\"".into(),
+                SourceSpan::from((15, 2))
+            )
+        );
+        assert_eq!(
+            parse_error("{% set x = a b %}"),
+            (
+                "Expected \"%}\", found identifier \"b\"".into(),
+                SourceSpan::from((13, 1))
+            )
+        );
+        assert_eq!(
+            parse_error("{% set x.y = a %}"),
+            (
+                "Expected \"=\", found \".\"".into(),
+                SourceSpan::from((8, 1))
+            )
+        );
     }
 }
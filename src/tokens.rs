@@ -1,6 +1,6 @@
 use std::{str::CharIndices, sync::Arc};
 
-use anyhow::Result;
+use miette::Result;
 use miette::{Diagnostic, NamedSource, SourceSpan};
 use thiserror::Error;
 
@@ -13,11 +13,97 @@ pub enum Token<'source> {
     CommandStart,
     CommandEnd,
     If,
+    ElIf,
+    Else,
     EndIf,
+    For,
+    In,
+    EndFor,
+    Match,
+    Case,
+    EndCase,
+    EndMatch,
+    Include,
+    Extends,
+    Block,
+    EndBlock,
+    Macro,
+    EndMacro,
+    Set,
+    Not,
+    And,
+    Or,
+    Dot,
+    Colon,
+    Pipe,
+    Comma,
+    Tilde,
+    Eq,
+    LParen,
+    RParen,
+    EqEq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
     Identifier { name: &'source str },
+    String { value: &'source str },
+    Integer { value: i64 },
     Text { index: usize, text: &'source str },
 }
 
+impl<'source> Token<'source> {
+    /// Describes the token for use in diagnostics
+    pub fn describe(&self) -> String {
+        match self {
+            Token::ParamStart => "\"{{\"".into(),
+            Token::ParamEnd => "\"}}\"".into(),
+            Token::CommandStart => "\"{%\"".into(),
+            Token::CommandEnd => "\"%}\"".into(),
+            Token::If => "if".into(),
+            Token::ElIf => "elif".into(),
+            Token::Else => "else".into(),
+            Token::EndIf => "endif".into(),
+            Token::For => "for".into(),
+            Token::In => "in".into(),
+            Token::EndFor => "endfor".into(),
+            Token::Match => "match".into(),
+            Token::Case => "case".into(),
+            Token::EndCase => "endcase".into(),
+            Token::EndMatch => "endmatch".into(),
+            Token::Include => "include".into(),
+            Token::Extends => "extends".into(),
+            Token::Block => "block".into(),
+            Token::EndBlock => "endblock".into(),
+            Token::Macro => "macro".into(),
+            Token::EndMacro => "endmacro".into(),
+            Token::Set => "set".into(),
+            Token::Not => "not".into(),
+            Token::And => "and".into(),
+            Token::Or => "or".into(),
+            Token::Dot => "\".\"".into(),
+            Token::Colon => "\":\"".into(),
+            Token::Pipe => "\"|\"".into(),
+            Token::Comma => "\",\"".into(),
+            Token::Tilde => "\"~\"".into(),
+            Token::Eq => "\"=\"".into(),
+            Token::LParen => "\"(\"".into(),
+            Token::RParen => "\")\"".into(),
+            Token::EqEq => "\"==\"".into(),
+            Token::NotEq => "\"!=\"".into(),
+            Token::Lt => "\"<\"".into(),
+            Token::LtEq => "\"<=\"".into(),
+            Token::Gt => "\">\"".into(),
+            Token::GtEq => "\">=\"".into(),
+            Token::Identifier { name } => format!("identifier \"{}\"", name),
+            Token::String { .. } => "string".into(),
+            Token::Integer { .. } => "integer".into(),
+            Token::Text { .. } => "text".into(),
+        }
+    }
+}
+
 #[derive(Error, Debug, Diagnostic)]
 #[error("Expected {expected}")]
 #[diagnostic()]
@@ -35,7 +121,13 @@ pub struct Tokenizer<'source> {
     text: &'source str,
     char_indices: CharIndices<'source>,
     tokens: Tokens<'source>,
-    text_count: usize
+    text_count: usize,
+    // Whether the last tag ended with `-`, so the whitespace starting the next text is trimmed
+    trim_next_text: bool,
+    // Whether the last tag was a block tag and `trim_blocks` is on, so the next newline is removed
+    trim_next_newline: bool,
+    trim_blocks: bool,
+    lstrip_blocks: bool,
 }
 
 impl<'source> Tokenizer<'source> {
@@ -45,16 +137,36 @@ impl<'source> Tokenizer<'source> {
             text,
             char_indices: text.char_indices(),
             tokens: Vec::new(),
-            text_count: 0
+            text_count: 0,
+            trim_next_text: false,
+            trim_next_newline: false,
+            trim_blocks: false,
+            lstrip_blocks: false,
         }
     }
 
+    /// Removes the first newline after each block tag or comment
+    pub fn trim_blocks(mut self, trim_blocks: bool) -> Self {
+        self.trim_blocks = trim_blocks;
+        self
+    }
+
+    /// Strips the spaces and tabs before each block tag or comment that starts a line
+    pub fn lstrip_blocks(mut self, lstrip_blocks: bool) -> Self {
+        self.lstrip_blocks = lstrip_blocks;
+        self
+    }
+
     pub fn tokenize(mut self) -> Result<Tokens<'source>, TokenError> {
         while !self.peek_eof() {
             if self.peek_param_start() {
                 self.try_tokenize_param()?;
+            } else if self.peek_raw_start() {
+                self.try_tokenize_raw()?;
             } else if self.peek_command_start() {
                 self.try_tokenize_command()?;
+            } else if self.peek_comment_start() {
+                self.try_skip_comment()?;
             } else {
                 self.tokenize_text();
             }
@@ -68,63 +180,182 @@ impl<'source> Tokenizer<'source> {
         chars.next().is_none()
     }
 
+    fn peek_char(&self) -> Option<char> {
+        self.char_indices.clone().next().map(|(_, c)| c)
+    }
+
+    // The index of the next character, or the end of the text
+    fn next_index(&self) -> usize {
+        self.char_indices
+            .clone()
+            .next()
+            .map(|(i, _c)| i)
+            .unwrap_or(self.text.len())
+    }
+
     fn try_tokenize_command(&mut self) -> Result<(), TokenError> {
         self.tokenize_command_start();
         self.skip_whitespace();
         if !self.peek_eof() {
-            match self.try_tokenize_keyword()? {
-                Token::If => {
-                    self.skip_whitespace();
-                    self.try_tokenize_ident()?
-                }
-                Token::EndIf => {}
-                _ => unreachable!(),
-            }
+            self.try_tokenize_keyword()?;
         }
-        self.skip_whitespace();
+        self.try_tokenize_expression()?;
         if self.peek_command_end() {
             self.tokenize_command_end();
             Ok(())
         } else {
-            let index = self
-                .char_indices
-                .clone()
-                .next()
-                .map(|(i, _c)| i)
-                .unwrap_or(self.text.len());
             Err(TokenError {
                 src: self.source.to_owned(),
-                span: SourceSpan::from(index),
+                span: SourceSpan::from(self.next_index()),
                 expected: "Command End \"%}\"",
             })
         }
     }
 
+    fn peek_raw_start(&self) -> bool {
+        raw_tag(&self.text[self.next_index()..], "raw").is_some()
+    }
+
+    /// Tokenizes a raw block as a single text, leaving any tags inside it as they are
+    fn try_tokenize_raw(&mut self) -> Result<(), TokenError> {
+        let text = self.text;
+        let start = self.next_index();
+        let open = raw_tag(&text[start..], "raw").unwrap();
+        if self.lstrip_blocks {
+            self.lstrip_previous_text();
+        }
+        if open.trim_before {
+            self.trim_previous_text();
+        }
+
+        let mut content_start = start + open.len;
+        let rest = &text[content_start..];
+        if open.trim_after {
+            content_start += rest.len() - rest.trim_start().len();
+        } else if self.trim_blocks && rest.starts_with("\r\n") {
+            content_start += 2;
+        } else if self.trim_blocks && rest.starts_with('\n') {
+            content_start += 1;
+        }
+
+        let end_tag = text[content_start..]
+            .match_indices("{%")
+            .find_map(|(offset, _)| {
+                let tag_start = content_start + offset;
+                raw_tag(&text[tag_start..], "endraw").map(|tag| (tag_start, tag))
+            });
+        let Some((content_end, close)) = end_tag else {
+            return Err(TokenError {
+                src: self.source.to_owned(),
+                span: SourceSpan::from((start, open.len)),
+                expected: "Raw End \"{% endraw %}\"",
+            });
+        };
+
+        if content_end > content_start {
+            let content = &text[content_start..content_end];
+            let token = Token::Text {
+                index: self.text_count,
+                text: content,
+            };
+            self.text_count += 1;
+            self.push_token(token, content_start, content.len());
+            if self.lstrip_blocks {
+                self.lstrip_previous_text();
+            }
+            if close.trim_before {
+                self.trim_previous_text();
+            }
+        }
+
+        let end = content_end + close.len;
+        while self.next_index() < end {
+            self.char_indices.next();
+        }
+        if close.trim_after {
+            self.trim_next_text = true;
+        } else {
+            self.trim_next_newline = self.trim_blocks;
+        }
+        Ok(())
+    }
+
+    /// Skips a comment, which can span lines and never produces tokens
+    fn try_skip_comment(&mut self) -> Result<(), TokenError> {
+        let start = self.next_index();
+        self.trim_next_text = false;
+        self.trim_next_newline = false;
+        if self.lstrip_blocks {
+            self.lstrip_previous_text();
+        }
+        self.char_indices.nth(1);
+        if self.peek_check("-") {
+            self.trim_previous_text();
+        }
+        while !self.peek_eof() {
+            if self.peek_check("-#}") {
+                self.char_indices.nth(2);
+                self.trim_next_text = true;
+                return Ok(());
+            }
+            if self.peek_check("#}") {
+                self.char_indices.nth(1);
+                self.trim_next_newline = self.trim_blocks;
+                return Ok(());
+            }
+            self.char_indices.next();
+        }
+        Err(TokenError {
+            src: self.source.to_owned(),
+            span: SourceSpan::from((start, 2)),
+            expected: "Comment End \"#}\"",
+        })
+    }
+
     fn try_tokenize_param(&mut self) -> Result<(), TokenError> {
         self.tokenize_param_start();
-        self.skip_whitespace();
-        if !self.peek_eof() {
-            self.try_tokenize_ident()?;
-        }
-        self.skip_whitespace();
+        self.try_tokenize_expression()?;
         if self.peek_param_end() {
             self.tokenize_param_end();
             Ok(())
         } else {
-            let index = self
-                .char_indices
-                .clone()
-                .next()
-                .map(|(i, _c)| i)
-                .unwrap_or(self.text.len());
             Err(TokenError {
                 src: self.source.to_owned(),
-                span: SourceSpan::from(index),
+                span: SourceSpan::from(self.next_index()),
                 expected: "Parameter End \"}}\"",
             })
         }
     }
 
+    /// Tokenizes the names, literals and punctuation inside a parameter or command
+    /// up to (but not including) the closing `}}` or `%}`
+    fn try_tokenize_expression(&mut self) -> Result<(), TokenError> {
+        loop {
+            self.skip_whitespace();
+            if self.peek_param_end() || self.peek_command_end() {
+                return Ok(());
+            }
+            match self.peek_char() {
+                None => return Ok(()),
+                Some('.') => self.consume_as(Token::Dot, 1),
+                Some(':') => self.consume_as(Token::Colon, 1),
+                Some('|') => self.consume_as(Token::Pipe, 1),
+                Some(',') => self.consume_as(Token::Comma, 1),
+                Some('~') => self.consume_as(Token::Tilde, 1),
+                Some('(') => self.consume_as(Token::LParen, 1),
+                Some(')') => self.consume_as(Token::RParen, 1),
+                Some('<') if self.peek_check("<=") => self.consume_as(Token::LtEq, 2),
+                Some('<') => self.consume_as(Token::Lt, 1),
+                Some('>') if self.peek_check(">=") => self.consume_as(Token::GtEq, 2),
+                Some('>') => self.consume_as(Token::Gt, 1),
+                Some('=') | Some('!') => self.try_tokenize_equality()?,
+                Some('"') | Some('\'') => self.try_tokenize_string()?,
+                Some(c) if c.is_ascii_digit() => self.try_tokenize_integer()?,
+                Some(_) => self.try_tokenize_ident()?,
+            }
+        }
+    }
+
     fn skip_whitespace(&mut self) {
         let mut chars = self.char_indices.clone();
         while let Some((_, c)) = chars.next() {
@@ -141,15 +372,15 @@ impl<'source> Tokenizer<'source> {
     }
 
     fn tokenize_param_start(&mut self) {
-        self.consume_as(Token::ParamStart, 2);
+        self.tokenize_tag_start(Token::ParamStart);
     }
 
     fn peek_param_end(&self) -> bool {
-        self.peek_check("}}")
+        self.peek_check("}}") || self.peek_check("-}}")
     }
 
     fn tokenize_param_end(&mut self) {
-        self.consume_as(Token::ParamEnd, 2);
+        self.tokenize_tag_end(Token::ParamEnd);
     }
 
     fn peek_command_start(&self) -> bool {
@@ -157,15 +388,82 @@ impl<'source> Tokenizer<'source> {
     }
 
     fn tokenize_command_start(&mut self) {
-        self.consume_as(Token::CommandStart, 2);
+        if self.lstrip_blocks {
+            self.lstrip_previous_text();
+        }
+        self.tokenize_tag_start(Token::CommandStart);
     }
 
     fn peek_command_end(&self) -> bool {
-        self.peek_check("%}")
+        self.peek_check("%}") || self.peek_check("-%}")
     }
 
     fn tokenize_command_end(&mut self) {
-        self.consume_as(Token::CommandEnd, 2);
+        self.tokenize_tag_end(Token::CommandEnd);
+        if !self.trim_next_text {
+            self.trim_next_newline = self.trim_blocks;
+        }
+    }
+
+    /// Tokenizes `{{` or `{%`, where a following `-` trims the whitespace ending the previous text
+    fn tokenize_tag_start(&mut self, token: Token<'source>) {
+        let mut chars = self.char_indices.clone();
+        if chars.nth(2).is_some_and(|(_, c)| c == '-') {
+            self.trim_previous_text();
+            self.consume_as(token, 3);
+        } else {
+            self.consume_as(token, 2);
+        }
+    }
+
+    /// Tokenizes `}}` or `%}`, where a preceding `-` trims the whitespace starting the next text
+    fn tokenize_tag_end(&mut self, token: Token<'source>) {
+        if self.peek_check("-") {
+            self.consume_as(token, 3);
+            self.trim_next_text = true;
+        } else {
+            self.consume_as(token, 2);
+        }
+    }
+
+    /// Trims the whitespace ending the text right before a tag
+    fn trim_previous_text(&mut self) {
+        self.truncate_previous_text(|_, text| text.trim_end().len());
+    }
+
+    /// Strips the spaces and tabs ending the text right before a block tag,
+    /// but only if nothing else comes before the tag on its line
+    fn lstrip_previous_text(&mut self) {
+        let source = self.text;
+        self.truncate_previous_text(|start, text| {
+            let len = text.trim_end_matches([' ', '\t']).len();
+            let line_start = start + len;
+            if line_start == 0 || source[..line_start].ends_with('\n') {
+                len
+            } else {
+                text.len()
+            }
+        });
+    }
+
+    /// Shortens the text right before a tag to the length given its offset and contents,
+    /// removing the text entirely if nothing is left
+    fn truncate_previous_text(&mut self, new_len: impl FnOnce(usize, &str) -> usize) {
+        let Some((span, Token::Text { text, .. })) = self.tokens.last_mut() else {
+            return;
+        };
+        let len = new_len(span.offset(), text);
+        *text = &text[..len];
+        if text.is_empty() {
+            self.tokens.pop();
+            self.text_count -= 1;
+        } else {
+            *span = SourceSpan::from((span.offset(), len));
+        }
+    }
+
+    fn peek_comment_start(&self) -> bool {
+        self.peek_check("{#")
     }
 
     fn peek_check(&self, s: &str) -> bool {
@@ -195,30 +493,73 @@ impl<'source> Tokenizer<'source> {
         self.push_token(token, start, len);
     }
 
-    fn try_tokenize_keyword(&mut self) -> Result<Token<'source>, TokenError> {
-        if self.peek_check("if") {
-            self.consume_as(Token::If, 2);
-            Ok(Token::If)
-        } else if self.peek_check("endif") {
-            self.consume_as(Token::EndIf, 5);
-            Ok(Token::EndIf)
+    /// The length of the word starting at the next character
+    fn peek_word_len(&self) -> usize {
+        self.char_indices
+            .clone()
+            .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+            .map(|(_, c)| c.len_utf8())
+            .sum()
+    }
+
+    fn try_tokenize_keyword(&mut self) -> Result<(), TokenError> {
+        let start = self.next_index();
+        let len = self.peek_word_len();
+        let token = match &self.text[start..start + len] {
+            "if" => Token::If,
+            "elif" => Token::ElIf,
+            "else" => Token::Else,
+            "endif" => Token::EndIf,
+            "for" => Token::For,
+            "endfor" => Token::EndFor,
+            "match" => Token::Match,
+            "case" => Token::Case,
+            "endcase" => Token::EndCase,
+            "endmatch" => Token::EndMatch,
+            "include" => Token::Include,
+            "extends" => Token::Extends,
+            "block" => Token::Block,
+            "endblock" => Token::EndBlock,
+            "macro" => Token::Macro,
+            "endmacro" => Token::EndMacro,
+            "set" => Token::Set,
+            _ => {
+                return Err(TokenError {
+                    src: self.source.to_owned(),
+                    span: SourceSpan::from((start, 0)),
+                    expected: "Command must contain keyword",
+                })
+            }
+        };
+        self.consume_as(token, len);
+        Ok(())
+    }
+
+    /// Tokenizes `==` or `!=`, or the `=` of a `set`
+    fn try_tokenize_equality(&mut self) -> Result<(), TokenError> {
+        if self.peek_check("==") {
+            self.consume_as(Token::EqEq, 2);
+        } else if self.peek_check("!=") {
+            self.consume_as(Token::NotEq, 2);
+        } else if self.peek_check("=") {
+            self.consume_as(Token::Eq, 1);
         } else {
-            let (index, _) = self.char_indices.next().unwrap();
-            Err(TokenError {
+            return Err(TokenError {
                 src: self.source.to_owned(),
-                span: SourceSpan::from((index, 0)),
-                expected: "Command must contain keyword",
-            })
+                span: SourceSpan::from((self.next_index(), 1)),
+                expected: "Comparison \"!=\"",
+            });
         }
+        Ok(())
     }
 
+    /// Tokenizes an identifier, or a keyword like `in` or `and` which can't be used as one
     fn try_tokenize_ident(&mut self) -> Result<(), TokenError> {
-        let mut chars = self.char_indices.clone();
-        let (i, c) = chars.next().unwrap();
-        let start = i;
-        let mut len = c.len_utf8();
+        let start = self.next_index();
+        let first = self.peek_char();
 
-        if !(c.is_alphabetic() || c == '_') {
+        if !first.is_some_and(|c| c.is_alphabetic() || c == '_') {
+            let len = first.map(|c| c.len_utf8()).unwrap_or(0);
             return Err(TokenError {
                 src: self.source.to_owned(),
                 span: SourceSpan::from((start, len)),
@@ -226,27 +567,79 @@ impl<'source> Tokenizer<'source> {
             });
         }
 
-        while let Some((_, c)) = chars.next() {
-            if c.is_alphanumeric() || c == '_' {
-                self.char_indices = chars.clone();
-                len += c.len_utf8();
-            } else {
-                break;
+        let len = self.peek_word_len();
+        let name = &self.text[start..start + len];
+        let token = match name {
+            "in" => Token::In,
+            "not" => Token::Not,
+            "and" => Token::And,
+            "or" => Token::Or,
+            _ => Token::Identifier { name },
+        };
+        self.consume_as(token, name.chars().count());
+        Ok(())
+    }
+
+    /// Tokenizes a string literal delimited by matching single or double quotes
+    fn try_tokenize_string(&mut self) -> Result<(), TokenError> {
+        let (start, quote) = self.char_indices.next().unwrap();
+        for (i, c) in self.char_indices.by_ref() {
+            if c == quote {
+                let value = &self.text[start + 1..i];
+                self.push_token(Token::String { value }, start, i + 1 - start);
+                return Ok(());
             }
         }
+        Err(TokenError {
+            src: self.source.to_owned(),
+            span: SourceSpan::from((start, self.text.len() - start)),
+            expected: "Closing quote",
+        })
+    }
 
-        let name = &self.text[start..start + len];
-        let token = Token::Identifier { name };
-        self.push_token(token, start, len);
-        Ok(())
+    fn try_tokenize_integer(&mut self) -> Result<(), TokenError> {
+        let start = self.next_index();
+        let len = self.peek_word_len();
+        let digits = &self.text[start..start + len];
+        match digits.parse() {
+            Ok(value) => {
+                self.consume_as(Token::Integer { value }, len);
+                Ok(())
+            }
+            Err(_) => Err(TokenError {
+                src: self.source.to_owned(),
+                span: SourceSpan::from((start, len)),
+                expected: "Integer",
+            }),
+        }
     }
 
     fn tokenize_text(&mut self) {
+        if self.trim_next_text {
+            self.skip_whitespace();
+        } else if self.trim_next_newline {
+            if self.peek_check("\r\n") {
+                self.char_indices.nth(1);
+            } else if self.peek_check("\n") {
+                self.char_indices.next();
+            }
+        }
+        if self.trim_next_text || self.trim_next_newline {
+            self.trim_next_text = false;
+            self.trim_next_newline = false;
+            if self.peek_eof()
+                || self.peek_param_start()
+                || self.peek_command_start()
+                || self.peek_comment_start()
+            {
+                return;
+            }
+        }
         let (i, c) = self.char_indices.next().unwrap();
         let start = i;
         let mut len = c.len_utf8();
 
-        while !(self.peek_param_start() || self.peek_command_start()) {
+        while !(self.peek_param_start() || self.peek_command_start() || self.peek_comment_start()) {
             if let Some((_, c)) = self.char_indices.next() {
                 len += c.len_utf8();
             } else {
@@ -261,11 +654,37 @@ impl<'source> Tokenizer<'source> {
     }
 
     fn push_token(&mut self, token: Token<'source>, start: usize, len: usize) {
+        self.trim_next_text = false;
+        self.trim_next_newline = false;
         let span = SourceSpan::from((start, len));
         self.tokens.push((span, token));
     }
 }
 
+/// A `{% raw %}` or `{% endraw %}` tag, which is tokenized on its own since its contents aren't
+struct RawTag {
+    len: usize,
+    // Whether the tag starts with `{%-`
+    trim_before: bool,
+    // Whether the tag ends with `-%}`
+    trim_after: bool,
+}
+
+/// Finds the raw tag with the given keyword at the start of the text, if there is one
+fn raw_tag(text: &str, keyword: &str) -> Option<RawTag> {
+    let rest = text.strip_prefix("{%")?;
+    let trim_before = rest.starts_with('-');
+    let rest = rest.strip_prefix('-').unwrap_or(rest).trim_start();
+    let rest = rest.strip_prefix(keyword)?.trim_start();
+    let trim_after = rest.starts_with("-%}");
+    let rest = rest.strip_prefix('-').unwrap_or(rest).strip_prefix("%}")?;
+    Some(RawTag {
+        len: text.len() - rest.len(),
+        trim_before,
+        trim_after,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -328,4 +747,329 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn loop_test() {
+        let text = "{% for x in xs %}{{ x }}{% endfor %}";
+        let source = Arc::new(NamedSource::new("loop_test", text));
+        let tokenizer = Tokenizer::new(source, text);
+        let tokens = match tokenizer.tokenize() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                println!("{:?}", Report::new(error));
+                panic!("test failed");
+            }
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                (SourceSpan::from((0, 2)), Token::CommandStart),
+                (SourceSpan::from((3, 3)), Token::For),
+                (SourceSpan::from((7, 1)), Token::Identifier { name: "x" }),
+                (SourceSpan::from((9, 2)), Token::In),
+                (SourceSpan::from((12, 2)), Token::Identifier { name: "xs" }),
+                (SourceSpan::from((15, 2)), Token::CommandEnd),
+                (SourceSpan::from((17, 2)), Token::ParamStart),
+                (SourceSpan::from((20, 1)), Token::Identifier { name: "x" }),
+                (SourceSpan::from((22, 2)), Token::ParamEnd),
+                (SourceSpan::from((24, 2)), Token::CommandStart),
+                (SourceSpan::from((27, 6)), Token::EndFor),
+                (SourceSpan::from((34, 2)), Token::CommandEnd),
+            ]
+        )
+    }
+
+    #[test]
+    fn path_test() {
+        let text = "{{ user.name }}";
+        let source = Arc::new(NamedSource::new("path_test", text));
+        let tokenizer = Tokenizer::new(source, text);
+        let tokens = match tokenizer.tokenize() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                println!("{:?}", Report::new(error));
+                panic!("test failed");
+            }
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                (SourceSpan::from((0, 2)), Token::ParamStart),
+                (SourceSpan::from((3, 4)), Token::Identifier { name: "user" }),
+                (SourceSpan::from((7, 1)), Token::Dot),
+                (SourceSpan::from((8, 4)), Token::Identifier { name: "name" }),
+                (SourceSpan::from((13, 2)), Token::ParamEnd),
+            ]
+        )
+    }
+
+    #[test]
+    fn filter_test() {
+        let text = "{{ name | replace('a', \"b\") | truncate(10) }}";
+        let source = Arc::new(NamedSource::new("filter_test", text));
+        let tokenizer = Tokenizer::new(source, text);
+        let tokens = match tokenizer.tokenize() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                println!("{:?}", Report::new(error));
+                panic!("test failed");
+            }
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                (SourceSpan::from((0, 2)), Token::ParamStart),
+                (SourceSpan::from((3, 4)), Token::Identifier { name: "name" }),
+                (SourceSpan::from((8, 1)), Token::Pipe),
+                (
+                    SourceSpan::from((10, 7)),
+                    Token::Identifier { name: "replace" }
+                ),
+                (SourceSpan::from((17, 1)), Token::LParen),
+                (SourceSpan::from((18, 3)), Token::String { value: "a" }),
+                (SourceSpan::from((21, 1)), Token::Comma),
+                (SourceSpan::from((23, 3)), Token::String { value: "b" }),
+                (SourceSpan::from((26, 1)), Token::RParen),
+                (SourceSpan::from((28, 1)), Token::Pipe),
+                (
+                    SourceSpan::from((30, 8)),
+                    Token::Identifier { name: "truncate" }
+                ),
+                (SourceSpan::from((38, 1)), Token::LParen),
+                (SourceSpan::from((39, 2)), Token::Integer { value: 10 }),
+                (SourceSpan::from((41, 1)), Token::RParen),
+                (SourceSpan::from((43, 2)), Token::ParamEnd),
+            ]
+        )
+    }
+
+    #[test]
+    fn operator_test() {
+        let text = "{% if not a and b >= 2 or c != 'x' %}";
+        let source = Arc::new(NamedSource::new("operator_test", text));
+        let tokenizer = Tokenizer::new(source, text);
+        let tokens = match tokenizer.tokenize() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                println!("{:?}", Report::new(error));
+                panic!("test failed");
+            }
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                (SourceSpan::from((0, 2)), Token::CommandStart),
+                (SourceSpan::from((3, 2)), Token::If),
+                (SourceSpan::from((6, 3)), Token::Not),
+                (SourceSpan::from((10, 1)), Token::Identifier { name: "a" }),
+                (SourceSpan::from((12, 3)), Token::And),
+                (SourceSpan::from((16, 1)), Token::Identifier { name: "b" }),
+                (SourceSpan::from((18, 2)), Token::GtEq),
+                (SourceSpan::from((21, 1)), Token::Integer { value: 2 }),
+                (SourceSpan::from((23, 2)), Token::Or),
+                (SourceSpan::from((26, 1)), Token::Identifier { name: "c" }),
+                (SourceSpan::from((28, 2)), Token::NotEq),
+                (SourceSpan::from((31, 3)), Token::String { value: "x" }),
+                (SourceSpan::from((35, 2)), Token::CommandEnd),
+            ]
+        )
+    }
+
+    #[test]
+    fn comment_test() {
+        let text = "A {# note\n{{ x }} #}B{##}C";
+        let source = Arc::new(NamedSource::new("comment_test", text));
+        let tokenizer = Tokenizer::new(source, text);
+        let tokens = match tokenizer.tokenize() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                println!("{:?}", Report::new(error));
+                panic!("test failed");
+            }
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    SourceSpan::from((0, 2)),
+                    Token::Text {
+                        index: 0,
+                        text: "A "
+                    }
+                ),
+                (
+                    SourceSpan::from((20, 1)),
+                    Token::Text {
+                        index: 1,
+                        text: "B"
+                    }
+                ),
+                (
+                    SourceSpan::from((25, 1)),
+                    Token::Text {
+                        index: 2,
+                        text: "C"
+                    }
+                ),
+            ]
+        );
+
+        let text = "A {# note #} {# {{ x }}";
+        let source = Arc::new(NamedSource::new("comment_test", text));
+        let error = Tokenizer::new(source, text).tokenize().unwrap_err();
+        assert_eq!(error.span, SourceSpan::from((13, 2)));
+        assert_eq!(error.to_string(), "Expected Comment End \"#}\"");
+    }
+
+    #[test]
+    fn whitespace_control_test() {
+        let text = "A \n{%- if x -%}\n  B  {{- y }} \n{# c -#}\n C";
+        let source = Arc::new(NamedSource::new("whitespace_control_test", text));
+        let tokenizer = Tokenizer::new(source, text);
+        let tokens = match tokenizer.tokenize() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                println!("{:?}", Report::new(error));
+                panic!("test failed");
+            }
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                (SourceSpan::from((0, 1)), Token::Text { index: 0, text: "A" }),
+                (SourceSpan::from((3, 3)), Token::CommandStart),
+                (SourceSpan::from((7, 2)), Token::If),
+                (SourceSpan::from((10, 1)), Token::Identifier { name: "x" }),
+                (SourceSpan::from((12, 3)), Token::CommandEnd),
+                (
+                    SourceSpan::from((18, 1)),
+                    Token::Text {
+                        index: 1,
+                        text: "B"
+                    }
+                ),
+                (SourceSpan::from((21, 3)), Token::ParamStart),
+                (SourceSpan::from((25, 1)), Token::Identifier { name: "y" }),
+                (SourceSpan::from((27, 2)), Token::ParamEnd),
+                (
+                    SourceSpan::from((29, 2)),
+                    Token::Text {
+                        index: 2,
+                        text: " \n"
+                    }
+                ),
+                (
+                    SourceSpan::from((41, 1)),
+                    Token::Text {
+                        index: 3,
+                        text: "C"
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn trim_blocks_test() {
+        let text = "<ul>\n  {% for x in xs %}\n  <li>{{ x }}</li> {% if x %}!{% endif %}\n  {# item #}\n  {% endfor %}\n</ul>\n";
+        let texts = |trim_blocks, lstrip_blocks| {
+            let source = Arc::new(NamedSource::new("trim_blocks_test", text));
+            let tokens = Tokenizer::new(source, text)
+                .trim_blocks(trim_blocks)
+                .lstrip_blocks(lstrip_blocks)
+                .tokenize()
+                .unwrap();
+            tokens
+                .into_iter()
+                .filter_map(|(_, token)| match token {
+                    Token::Text { text, .. } => Some(text),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            texts(false, false),
+            vec![
+                "<ul>\n  ",
+                "\n  <li>",
+                "</li> ",
+                "!",
+                "\n  ",
+                "\n  ",
+                "\n</ul>\n"
+            ]
+        );
+        assert_eq!(
+            texts(true, false),
+            vec!["<ul>\n  ", "  <li>", "</li> ", "!", "  ", "  ", "</ul>\n"]
+        );
+        assert_eq!(
+            texts(false, true),
+            vec!["<ul>\n", "\n  <li>", "</li> ", "!", "\n", "\n", "\n</ul>\n"]
+        );
+        assert_eq!(
+            texts(true, true),
+            vec!["<ul>\n", "  <li>", "</li> ", "!", "</ul>\n"]
+        );
+    }
+
+    #[test]
+    fn raw_test() {
+        let text = "A{% raw %}{{ x }}{% if %}{# #}{% endraw %}B {%- raw -%} {{ y }} {%- endraw -%} C{%raw%}{%endraw%}";
+        let source = Arc::new(NamedSource::new("raw_test", text));
+        let tokenizer = Tokenizer::new(source, text);
+        let tokens = match tokenizer.tokenize() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                println!("{:?}", Report::new(error));
+                panic!("test failed");
+            }
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    SourceSpan::from((0, 1)),
+                    Token::Text {
+                        index: 0,
+                        text: "A"
+                    }
+                ),
+                (
+                    SourceSpan::from((10, 20)),
+                    Token::Text {
+                        index: 1,
+                        text: "{{ x }}{% if %}{# #}"
+                    }
+                ),
+                (
+                    SourceSpan::from((42, 1)),
+                    Token::Text {
+                        index: 2,
+                        text: "B"
+                    }
+                ),
+                (
+                    SourceSpan::from((56, 7)),
+                    Token::Text {
+                        index: 3,
+                        text: "{{ y }}"
+                    }
+                ),
+                (
+                    SourceSpan::from((79, 1)),
+                    Token::Text {
+                        index: 4,
+                        text: "C"
+                    }
+                ),
+            ]
+        );
+
+        let text = "A {% raw %}{{ x }}{% endif %}";
+        let source = Arc::new(NamedSource::new("raw_test", text));
+        let error = Tokenizer::new(source, text).tokenize().unwrap_err();
+        assert_eq!(error.span, SourceSpan::from((2, 9)));
+        assert_eq!(error.to_string(), "Expected Raw End \"{% endraw %}\"");
+    }
 }
@@ -0,0 +1,53 @@
+/// Runs the compiler binary and checks how it reports errors
+use std::{env, fs, path::PathBuf, process::Command};
+
+use pretty_assertions::assert_eq;
+
+fn write_input(name: &str, text: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("template-compiler-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn test_json_diagnostics() {
+    let input = write_input("broken.html", "Hello {% if user %}\n{{ user. }}\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_template-compiler"))
+        .arg("-i")
+        .arg(&input)
+        .arg("-o")
+        .arg(input.with_extension("wasm"))
+        .args(["--message-format", "json"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected = r#"{"message": "Expected identifier, found \"}}\"","severity": "error","causes": [],"filename": "broken.html","labels": [{"label": "Here","span": {"offset": 29,"length": 2}}],"related": []}"#;
+    assert_eq!(stdout.trim_end(), expected);
+}
+
+#[test]
+fn test_human_diagnostics() {
+    let input = write_input("unclosed.html", "{% for post in posts %}{{ post }}");
+    let output = Command::new(env!("CARGO_BIN_EXE_template-compiler"))
+        .arg("-i")
+        .arg(&input)
+        .arg("-o")
+        .arg(input.with_extension("wasm"))
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unclosed for"), "{}", stderr);
+    // the report includes the offending source line
+    assert!(
+        stderr.contains("{% for post in posts %}{{ post }}"),
+        "{}",
+        stderr
+    );
+}
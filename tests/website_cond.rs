@@ -33,7 +33,7 @@ mod bindings {
     });
 }
 
-const TEMPLATE: &'static str = "
+const TEMPLATE: &'static str = "{# A page with an optional footer -#}
 <!DOCTYPE html>
 <html>
 <head>
@@ -43,9 +43,9 @@ const TEMPLATE: &'static str = "
     <h1>{{ title }}</h1>
     {{ content }}
 
-    {% if include_footer %}
+    {%- if include_footer %}
     Thanks!!
-    {% endif %}
+    {%- endif %}
 </body>
 </html>
 ";
@@ -54,12 +54,13 @@ const TEMPLATE: &'static str = "
 fn test_website() -> Result<()> {
     let compiler_config = CompilerConfig {
         export_func_name: "apply".into(),
+        ..Default::default()
     };
     let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
-    let file_data = parse_file(source, TEMPLATE).unwrap();
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
 
-    let params = Params::new(&file_data.contents);
-    let template = TemplateGenerator::new(params, &file_data);
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
     let component = gen_component(&compiler_config, &template);
     let component_bytes = component.finish();
 
@@ -77,8 +78,7 @@ fn test_website() -> Result<()> {
     let content =
         "WebAssembly, commonly abreviated as Wasm, is a secure, portable, and fast compile target";
     let expected = format!(
-        "
-<!DOCTYPE html>
+        "<!DOCTYPE html>
 <html>
 <head>
     <title>{}</title>
@@ -86,10 +86,7 @@ fn test_website() -> Result<()> {
 <body>
     <h1>{}</h1>
     {}
-
-    
     Thanks!!
-    
 </body>
 </html>
 ",
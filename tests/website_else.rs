@@ -0,0 +1,85 @@
+/// Generates a template component for a simple website with alternate branches
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record params {
+                    name: string,
+                    is-admin: bool,
+                    logged-in: bool,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "<nav>{% if is_admin %}Admin {{ name }}{% elif logged_in %}Hello {{ name }}{% else %}Log in{% endif %}</nav>\
+<footer>{% if logged_in %}Log out{% else %}Sign up{% endif %}</footer>";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let cases = [
+        (true, true, "<nav>Admin Robin</nav><footer>Log out</footer>"),
+        (
+            false,
+            true,
+            "<nav>Hello Robin</nav><footer>Log out</footer>",
+        ),
+        (false, false, "<nav>Log in</nav><footer>Sign up</footer>"),
+    ];
+    for (is_admin, logged_in, expected) in cases {
+        let params = bindings::Params {
+            name: "Robin".to_owned(),
+            is_admin,
+            logged_in,
+        };
+        let result = website.call_apply(&mut store, &params)?;
+        assert_eq!(result, expected);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+/// Generates a template component for a simple website with HTML escaping
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record params {
+                    banner: string,
+                    comment: string,
+                    title: string,
+                    comments: list<string>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "<h1>{{ title }}</h1>
+<div>{{ banner | safe }}</div>
+<p>{{ comment | upper }}</p>
+{% for comment in comments %}<li>{{ comment }}</li>{% endfor %}";
+
+fn render(autoescape: bool, params: &bindings::Params) -> Result<String> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        autoescape,
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let template_params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, template_params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    website.call_apply(&mut store, params)
+}
+
+fn params() -> bindings::Params {
+    bindings::Params {
+        banner: "<b>Welcome!</b>".to_owned(),
+        comment: "<script>alert('hi & bye')</script>".to_owned(),
+        title: "Tom \"Tiny\" Jones".to_owned(),
+        comments: vec!["a < b".to_owned(), "plain".to_owned(), "".to_owned()],
+    }
+}
+
+#[test]
+fn test_autoescape() -> Result<()> {
+    let result = render(true, &params())?;
+    let expected = "<h1>Tom &quot;Tiny&quot; Jones</h1>
+<div><b>Welcome!</b></div>
+<p>&lt;SCRIPT&gt;ALERT(&#39;HI &amp; BYE&#39;)&lt;/SCRIPT&gt;</p>
+<li>a &lt; b</li><li>plain</li><li></li>";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_no_autoescape() -> Result<()> {
+    let result = render(false, &params())?;
+    let expected = "<h1>Tom \"Tiny\" Jones</h1>
+<div><b>Welcome!</b></div>
+<p><SCRIPT>ALERT('HI & BYE')</SCRIPT></p>
+<li>a < b</li><li>plain</li><li></li>";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
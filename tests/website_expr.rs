@@ -0,0 +1,148 @@
+/// Generates a template component for a website whose conditions combine and compare params
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record post {
+                    author: string,
+                    title: string,
+                    draft: bool,
+                    pinned: bool,
+                    views: s32,
+                }
+
+                record params {
+                    editor: string,
+                    balance: s64,
+                    count: u32,
+                    level: u8,
+                    temperature: f32,
+                    posts: list<post>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "{{ count: u32 }} posts{% if count > 3000000000 %} (many){% endif %}, \
+level {{ level: u8 }}{% if level > 200 %} (expert){% endif %}
+Balance {{ balance: s64 }}{% if balance >= 1000 %} (rich){% elif balance < 0 %} (in debt){% endif %}, \
+{{ temperature: f32 }} degrees{% if temperature <= 0 %} (freezing){% endif %}
+{% for post in posts %}{{ post.title }}{% if not post.draft and (post.views > 100 or post.pinned) %} [popular]{% endif %}\
+{% if post.author == 'admin' %} by staff{% elif post.author != editor %} by {{ post.author }}{% else %} by the editor{% endif %}
+{% endfor %}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.txt", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let posts = vec![
+        bindings::Post {
+            author: "admin".to_owned(),
+            title: "Welcome".to_owned(),
+            draft: false,
+            pinned: true,
+            views: 0,
+        },
+        bindings::Post {
+            author: "Robin".to_owned(),
+            title: "Trending".to_owned(),
+            draft: false,
+            pinned: false,
+            views: 101,
+        },
+        bindings::Post {
+            author: "Sam".to_owned(),
+            title: "Unread".to_owned(),
+            draft: false,
+            pinned: false,
+            views: -500,
+        },
+        bindings::Post {
+            author: "Sam".to_owned(),
+            title: "Upcoming".to_owned(),
+            draft: true,
+            pinned: true,
+            views: 1000,
+        },
+    ];
+    let params = bindings::Params {
+        editor: "Sam".to_owned(),
+        balance: 1000,
+        count: 3000000001,
+        level: 201,
+        temperature: 0.0,
+        posts: posts.clone(),
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "3000000001 posts (many), level 201 (expert)
+Balance 1000 (rich), 0 degrees (freezing)
+Welcome [popular] by staff
+Trending [popular] by Robin
+Unread by the editor
+Upcoming by the editor
+";
+    assert_eq!(result, expected);
+
+    let params = bindings::Params {
+        editor: "Robin".to_owned(),
+        balance: -1,
+        count: 2,
+        level: 200,
+        temperature: 0.5,
+        posts,
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "2 posts, level 200
+Balance -1 (in debt), 0.5 degrees
+Welcome [popular] by staff
+Trending [popular] by the editor
+Unread by Sam
+Upcoming by Sam
+";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
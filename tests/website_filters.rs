@@ -0,0 +1,98 @@
+/// Generates a template component for a simple website using filters
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record params {
+                    content: string,
+                    subtitle: string,
+                    title: string,
+                    tags: list<string>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "<h1>{{ title | upper }}</h1>
+<h2>{{ subtitle | trim | default(\"No subtitle\") }}</h2>
+<p>{{ content | truncate(12) }}</p>
+<p>{{ content | replace(\"Wasm\", \"WebAssembly\") | lower }}</p>
+{% for tag in tags %}[{{ tag | trim | upper }}]{% endfor %}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        content: "Wasm is fast and Wasm is portable".to_owned(),
+        subtitle: "  A compile target \n".to_owned(),
+        title: "What is Wasm?".to_owned(),
+        tags: vec![" fast".to_owned(), "portable ".to_owned()],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<h1>WHAT IS WASM?</h1>
+<h2>A compile target</h2>
+<p>Wasm is fast...</p>
+<p>webassembly is fast and webassembly is portable</p>
+[FAST][PORTABLE]";
+    assert_eq!(result, expected);
+
+    let params = bindings::Params {
+        content: "Short ünïcödé".to_owned(),
+        subtitle: "   ".to_owned(),
+        title: "Ünïcödé".to_owned(),
+        tags: vec![],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<h1>ÜNïCöDé</h1>
+<h2>No subtitle</h2>
+<p>Short ünïcöd...</p>
+<p>short ünïcödé</p>
+";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+/// Generates a template component for a website assembled from included partials
+use std::{env, fs, path::PathBuf};
+
+use miette::JSONReportHandler;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, Config as CompilerConfig, Params, SourceFiles, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record post {
+                    author: string,
+                    title: string,
+                }
+
+                record params {
+                    footer: string,
+                    title: string,
+                    posts: list<post>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+/// Writes the files of a template to a fresh directory and returns the path of the first one
+fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("template-compiler-{}-{}", test, std::process::id()));
+    for (name, text) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, text).unwrap();
+    }
+    dir.join(files[0].0)
+}
+
+/// Loads and checks a template, returning the message and file of the first error
+fn error(test: &str, files: &[(&str, &str)]) -> (String, String) {
+    let compiler_config = CompilerConfig::default();
+    let path = write_files(test, files);
+    let report = SourceFiles::load(&compiler_config, &path)
+        .and_then(|sources| {
+            let file_data = sources.parse(&compiler_config)?;
+            Params::new(&file_data)?;
+            Ok(())
+        })
+        .unwrap_err();
+
+    let mut json = String::new();
+    JSONReportHandler::new()
+        .render_report(&mut json, report.as_ref())
+        .unwrap();
+    let filename = json
+        .split("\"filename\": \"")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    (report.to_string(), filename.to_owned())
+}
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let path = write_files(
+        "include",
+        &[
+            (
+                "page.html",
+                "{% include \"partials/header.html\" %}\
+                {% for post in posts %}{% include \"partials/post.html\" %}{% endfor %}\
+                {% include 'partials/footer.html' %}",
+            ),
+            ("partials/header.html", "<h1>{{ title }}</h1>\n"),
+            (
+                "partials/post.html",
+                "<p>{{ post.title }}{% include \"byline.html\" %}</p>\n",
+            ),
+            ("partials/byline.html", " by {{ post.author }}"),
+            ("partials/footer.html", "<footer>{{ footer }}</footer>"),
+        ],
+    );
+    let sources = SourceFiles::load(&compiler_config, &path).unwrap();
+    let file_data = sources.parse(&compiler_config).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        footer: "Thanks for reading".to_owned(),
+        title: "Blog".to_owned(),
+        posts: vec![
+            bindings::Post {
+                author: "Robin".to_owned(),
+                title: "First".to_owned(),
+            },
+            bindings::Post {
+                author: "Sam".to_owned(),
+                title: "Second".to_owned(),
+            },
+        ],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<h1>Blog</h1>
+<p>First by Robin</p>
+<p>Second by Sam</p>
+<footer>Thanks for reading</footer>";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_include_errors() {
+    let (message, filename) = error(
+        "cycle",
+        &[
+            ("a.html", "A{% include 'b.html' %}"),
+            ("b.html", "B{% include 'a.html' %}"),
+        ],
+    );
+    assert_eq!(message, "Include cycle: a.html -> b.html -> a.html");
+    assert_eq!(filename, "b.html");
+
+    let (message, filename) = error("missing", &[("page.html", "{% include 'missing.html' %}")]);
+    assert!(
+        message.starts_with("Can't read `missing.html`: "),
+        "{}",
+        message
+    );
+    assert_eq!(filename, "page.html");
+
+    // errors in included files point into them
+    let (message, filename) = error(
+        "parse",
+        &[
+            ("page.html", "{% include 'partials/list.html' %}"),
+            ("partials/list.html", "{% for x in xs %}"),
+        ],
+    );
+    assert_eq!(message, "Unclosed for");
+    assert_eq!(filename, "partials/list.html");
+
+    let (message, filename) = error(
+        "conflict",
+        &[
+            ("page.html", "{{ tags }}{% include 'tags.html' %}"),
+            ("tags.html", "{% for tag in tags %}{{ tag }}{% endfor %}"),
+        ],
+    );
+    assert_eq!(
+        message,
+        "`tags` is used as a list here but as a string elsewhere"
+    );
+    assert_eq!(filename, "tags.html");
+}
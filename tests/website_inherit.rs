@@ -0,0 +1,209 @@
+/// Generates a template component for a website page extending a chain of layouts
+use std::{env, fs, path::PathBuf};
+
+use miette::JSONReportHandler;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, Config as CompilerConfig, Params, SourceFiles, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record post {
+                    title: string,
+                }
+
+                record params {
+                    site: string,
+                    title: string,
+                    posts: list<post>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+/// Writes the files of a template to a fresh directory and returns the path of the first one
+fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!(
+        "template-compiler-inherit-{}-{}",
+        test,
+        std::process::id()
+    ));
+    for (name, text) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, text).unwrap();
+    }
+    dir.join(files[0].0)
+}
+
+/// Loads and checks a template, returning the message and file of the first error
+fn error(test: &str, files: &[(&str, &str)]) -> (String, String) {
+    let compiler_config = CompilerConfig::default();
+    let path = write_files(test, files);
+    let report = SourceFiles::load(&compiler_config, &path)
+        .and_then(|sources| {
+            let file_data = sources.parse(&compiler_config)?;
+            Params::new(&file_data)?;
+            Ok(())
+        })
+        .unwrap_err();
+
+    let mut json = String::new();
+    JSONReportHandler::new()
+        .render_report(&mut json, report.as_ref())
+        .unwrap();
+    let filename = json
+        .split("\"filename\": \"")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    (report.to_string(), filename.to_owned())
+}
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let path = write_files(
+        "website",
+        &[
+            (
+                "page.html",
+                "{% extends 'layouts/blog.html' %}\
+                {% block title %}{{ title }} | {{ super() }}{% endblock %}\
+                {% block post %}<li>{{ post.title }}</li>{% endblock post %}",
+            ),
+            (
+                "layouts/blog.html",
+                "{% extends \"base.html\" %}\
+                {% block main %}<ul>{% for post in posts %}{% block post %}{% endblock %}{% endfor %}</ul>\
+                {% endblock %}",
+            ),
+            (
+                "layouts/base.html",
+                "<title>{% block title %}{{ site }}{% endblock %}</title>\n\
+                <main>{% block main %}Nothing here{% endblock %}</main>\n\
+                {% block footer %}<footer>{{ site }}</footer>{% endblock %}",
+            ),
+        ],
+    );
+    let sources = SourceFiles::load(&compiler_config, &path).unwrap();
+    let file_data = sources.parse(&compiler_config).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        site: "Blog".to_owned(),
+        title: "Posts".to_owned(),
+        posts: vec![
+            bindings::Post {
+                title: "First".to_owned(),
+            },
+            bindings::Post {
+                title: "Second".to_owned(),
+            },
+        ],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<title>Posts | Blog</title>
+<main><ul><li>First</li><li>Second</li></ul></main>
+<footer>Blog</footer>";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_inherit_errors() {
+    let (message, filename) = error(
+        "cycle",
+        &[
+            ("a.html", "{% extends 'b.html' %}"),
+            ("b.html", "{% extends 'a.html' %}"),
+        ],
+    );
+    assert_eq!(message, "Inheritance cycle: a.html -> b.html -> a.html");
+    assert_eq!(filename, "b.html");
+
+    let (message, filename) = error(
+        "twice",
+        &[
+            ("page.html", "{% extends 'a.html' %}{% extends 'b.html' %}"),
+            ("a.html", "A"),
+            ("b.html", "B"),
+        ],
+    );
+    assert_eq!(message, "A template can only extend one other");
+    assert_eq!(filename, "page.html");
+
+    // errors in parent templates point into them
+    let (message, filename) = error(
+        "parse",
+        &[
+            (
+                "page.html",
+                "{% extends 'base.html' %}{% block main %}{{ super() }}{% endblock %}",
+            ),
+            ("base.html", "{% block main %}{{ super() }}{% endblock %}"),
+        ],
+    );
+    assert_eq!(
+        message,
+        "No block `main` in a parent template for `super()` to render"
+    );
+    assert_eq!(filename, "base.html");
+
+    let (message, filename) = error(
+        "conflict",
+        &[
+            (
+                "page.html",
+                "{% extends 'base.html' %}{% block main %}{{ tags }}{% endblock %}",
+            ),
+            (
+                "base.html",
+                "{% for tag in tags %}{{ tag }}{% endfor %}{% block main %}{% endblock %}",
+            ),
+        ],
+    );
+    assert_eq!(
+        message,
+        "`tags` is used as a string here but as a list elsewhere"
+    );
+    assert_eq!(filename, "page.html");
+}
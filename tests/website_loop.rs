@@ -0,0 +1,124 @@
+/// Generates a template component for a simple website with repeated sections
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record params {
+                    title: string,
+                    authors: list<string>,
+                    tags: list<string>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "
+<!DOCTYPE html>
+<html>
+<head>
+    <title>{{ title }}</title>
+</head>
+<body>
+    <ul>
+    {% for tag in tags %}<li>{{ tag }}</li>{% endfor %}
+    </ul>
+    {% for author in authors %}{% for tag in tags %}[{{ author }}/{{ tag }}]{% endfor %}{% endfor %}
+</body>
+</html>
+";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let expected = "
+<!DOCTYPE html>
+<html>
+<head>
+    <title>Tags</title>
+</head>
+<body>
+    <ul>
+    <li>wasm</li><li>templates</li>
+    </ul>
+    [Robin/wasm][Robin/templates][Alex/wasm][Alex/templates]
+</body>
+</html>
+";
+    let params = bindings::Params {
+        title: "Tags".to_owned(),
+        authors: vec!["Robin".to_owned(), "Alex".to_owned()],
+        tags: vec!["wasm".to_owned(), "templates".to_owned()],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    assert_eq!(result, expected);
+
+    let params = bindings::Params {
+        title: "Tags".to_owned(),
+        authors: vec!["Robin".to_owned()],
+        tags: vec![],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    assert_eq!(
+        result,
+        "
+<!DOCTYPE html>
+<html>
+<head>
+    <title>Tags</title>
+</head>
+<body>
+    <ul>
+    
+    </ul>
+    
+</body>
+</html>
+"
+    );
+
+    Ok(())
+}
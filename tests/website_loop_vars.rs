@@ -0,0 +1,124 @@
+/// Generates a template component for a website that uses loop variables for separators and striping
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record post {
+                    title: string,
+                    tags: list<string>,
+                }
+
+                record params {
+                    posts: list<post>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "{% for post in posts -%}
+<li class=\"{% if loop.index > 1 and loop.last %}end{% elif loop.first %}start{% endif %}\">\
+{{ loop.index }}/{{ loop.length }} {{ post.title }}: \
+{% for tag in post.tags %}{{ tag }}{% if not loop.last %}, {% endif %}{% endfor %}</li>
+{% endfor %}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let post = |title: &str, tags: &[&str]| bindings::Post {
+        title: title.to_owned(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+    };
+    let params = bindings::Params {
+        posts: vec![
+            post("First", &["rust", "wasm"]),
+            post("Second", &[]),
+            post("Third", &["wit"]),
+        ],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<li class=\"start\">1/3 First: rust, wasm</li>
+<li class=\"\">2/3 Second: </li>
+<li class=\"end\">3/3 Third: wit</li>
+";
+    assert_eq!(result, expected);
+
+    let params = bindings::Params {
+        posts: vec![post("Only", &["a"])],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    assert_eq!(result, "<li class=\"start\">1/1 Only: a</li>\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_loop_errors() {
+    let error = |text: &'static str| {
+        let source = Arc::new(NamedSource::new("website.html", text));
+        let report = parse_file(&CompilerConfig::default(), source, text)
+            .and_then(|file_data| Ok(Params::new(&file_data).map(|_| ())?))
+            .unwrap_err();
+        report.to_string()
+    };
+
+    assert_eq!(
+        error("{% for x in xs %}{{ loop.first }}{% endfor %}"),
+        "Expected a string, found a bool"
+    );
+    assert_eq!(
+        error("{% for x in xs %}{{ loop.count }}{% endfor %}"),
+        "Record `loop` has no field `count`"
+    );
+    assert_eq!(
+        error("{% for x in xs %}{% for y in loop.index %}{% endfor %}{% endfor %}"),
+        "`loop.index` is used as a list here but it's a loop variable"
+    );
+    assert_eq!(
+        error("{% for x in xs %}{{ loop.index: u64 }}{% endfor %}"),
+        "Expected `u64`, found `u32`"
+    );
+}
@@ -0,0 +1,146 @@
+/// Generates a template component for a website that renders repeated snippets with macros
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record post {
+                    tag: string,
+                    title: string,
+                }
+
+                record featured {
+                    author: string,
+                    title: string,
+                }
+
+                record params {
+                    site: string,
+                    posts: list<post>,
+                    tags: list<string>,
+                    featured: featured,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "{% macro badge(label) %}[{{ label | upper }}]{% endmacro -%}
+{% macro card(title, byline, tag) %}<div>{{ title }}{% if byline %} by {{ byline }}{% endif %}\
+{% if tag == \"new\" %} {{ badge(tag) }}{% endif %} on {{ site }}</div>{% endmacro -%}
+{% macro tag_list() %}{% for tag in tags %}#{{ tag }} {% endfor %}{% endmacro -%}
+{{ card(featured.title, featured.author, \"new\") }}
+{% for post in posts %}{{ card(post.title, \"\", post.tag) }}
+{% endfor %}{{ tag_list() }}{{ badge(\"end\") }}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        site: "Blog".to_owned(),
+        posts: vec![
+            bindings::Post {
+                tag: "new".to_owned(),
+                title: "First".to_owned(),
+            },
+            bindings::Post {
+                tag: "old".to_owned(),
+                title: "Second".to_owned(),
+            },
+        ],
+        tags: vec!["rust".to_owned(), "wasm".to_owned()],
+        featured: bindings::Featured {
+            author: "Robin".to_owned(),
+            title: "Welcome".to_owned(),
+        },
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<div>Welcome by Robin [NEW] on Blog</div>
+<div>First [NEW] on Blog</div>
+<div>Second on Blog</div>
+#rust #wasm [END]";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_macro_errors() {
+    let error = |text: &'static str| {
+        let source = Arc::new(NamedSource::new("website.html", text));
+        let report = parse_file(&CompilerConfig::default(), source, text)
+            .and_then(|file_data| Ok(Params::new(&file_data).map(|_| ())?))
+            .unwrap_err();
+        report.to_string()
+    };
+
+    assert_eq!(error("{{ card(title) }}"), "Unknown macro \"card\"");
+    assert_eq!(
+        error("{% macro card(title, body) %}{{ title }}{% endmacro %}{{ card(title) }}"),
+        "Macro \"card\" takes 2 argument(s) but was given 1"
+    );
+    assert_eq!(
+        error("{% macro card(items) %}{% for item in items %}{% endfor %}{% endmacro %}"),
+        "`items` is used as a list here but macro arguments are strings"
+    );
+    assert_eq!(
+        error("{% macro card(post) %}{{ post.title }}{% endmacro %}"),
+        "`post` is used as a record here but macro arguments are strings"
+    );
+    assert_eq!(
+        error(
+            "{% macro card(tags) %}{% endmacro %}{% for tag in tags %}{{ card(tags) }}{% endfor %}"
+        ),
+        "`tags` is used as a string here but as a list elsewhere"
+    );
+    assert_eq!(
+        error("{% macro a() %}x{{ a() }}{% endmacro %}{{ a() }}"),
+        "Macro \"a\" is called recursively"
+    );
+    assert_eq!(
+        error("{% macro a() %}{% if b %}{{ b() }}{% endif %}{% endmacro %}{% macro b() %}{{ a() }}{% endmacro %}"),
+        "Macro \"a\" is called recursively"
+    );
+}
@@ -0,0 +1,142 @@
+/// Generates a template component for a website that matches on enums
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                enum state {
+                    draft,
+                    in-review,
+                    published,
+                }
+
+                record post {
+                    title: string,
+                    state: state,
+                }
+
+                enum status {
+                    active,
+                    suspended,
+                    deleted,
+                }
+
+                record params {
+                    name: string,
+                    status: status,
+                    posts: list<post>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "{{ name }} {% match status %}
+    {% case active %}<span class=\"ok\">Active</span>
+    {% case suspended %}<span class=\"warn\">Suspended</span>
+{% endmatch %}
+{% for post in posts %}{{ post.title }}{% match post.state %}{% case draft %} (draft){% case in_review %} (in review, {% match status %}{% case active %}ready{% case deleted %}gone{% endmatch %}){% case published %}{% endmatch %}
+{% endfor %}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let posts = vec![
+        bindings::Post {
+            title: "First".to_owned(),
+            state: bindings::State::Published,
+        },
+        bindings::Post {
+            title: "Second".to_owned(),
+            state: bindings::State::InReview,
+        },
+        bindings::Post {
+            title: "Third".to_owned(),
+            state: bindings::State::Draft,
+        },
+    ];
+    let params = bindings::Params {
+        name: "Robin".to_owned(),
+        status: bindings::Status::Active,
+        posts: posts.clone(),
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "Robin <span class=\"ok\">Active</span>
+    
+First
+Second (in review, ready)
+Third (draft)
+";
+    assert_eq!(result, expected);
+
+    let params = bindings::Params {
+        name: "Robin".to_owned(),
+        status: bindings::Status::Suspended,
+        posts: posts.clone(),
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "Robin <span class=\"warn\">Suspended</span>
+
+First
+Second (in review, )
+Third (draft)
+";
+    assert_eq!(result, expected);
+
+    let params = bindings::Params {
+        name: "Robin".to_owned(),
+        status: bindings::Status::Deleted,
+        posts,
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "Robin 
+First
+Second (in review, gone)
+Third (draft)
+";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
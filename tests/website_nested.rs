@@ -0,0 +1,197 @@
+/// Generates template components for websites using nested parameters
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record post {
+                    title: string,
+                    draft: bool,
+                    tags: list<string>,
+                }
+
+                record owner {
+                    email: string,
+                    name: string,
+                }
+
+                record site {
+                    title: string,
+                    owner: owner,
+                }
+
+                record user {
+                    name: string,
+                    admin: bool,
+                }
+
+                record params {
+                    posts: list<post>,
+                    site: site,
+                    user: user,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+mod spilled_bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:spilled;
+
+            world spilled {
+                record person {
+                    first: string,
+                    last: string,
+                    nickname: string,
+                    title: string,
+                    verified: bool,
+                }
+
+                record params {
+                    author: person,
+                    editor: person,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "<title>{{ site.title }}</title>
+<p>{{ user.name }}{% if user.admin %} (admin){% endif %}</p>
+{% for post in posts %}<h2>{{ post.title }}{% if post.draft %} [draft]{% endif %}</h2>{% for tag in post.tags %}#{{ tag }} {% endfor %}
+{% endfor %}<footer>{{ site.owner.name }} &lt;{{ site.owner.email }}&gt;</footer>";
+
+const SPILLED_TEMPLATE: &str = "{{ author.title }} {{ author.first }} \"{{ author.nickname }}\" {{ author.last }}{% if author.verified %}*{% endif %}, \
+edited by {{ editor.title }} {{ editor.first }} \"{{ editor.nickname }}\" {{ editor.last }}{% if editor.verified %}*{% endif %}";
+
+fn compile(name: &str, text: &str) -> Result<Vec<u8>> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new(name, text.to_owned()));
+    let file_data = parse_file(&compiler_config, source, text).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    Ok(component.finish())
+}
+
+#[test]
+fn test_website() -> Result<()> {
+    let component_bytes = compile("website.html", TEMPLATE)?;
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        posts: vec![
+            bindings::Post {
+                title: "Hello".to_owned(),
+                draft: false,
+                tags: vec!["intro".to_owned(), "wasm".to_owned()],
+            },
+            bindings::Post {
+                title: "Upcoming".to_owned(),
+                draft: true,
+                tags: vec![],
+            },
+        ],
+        site: bindings::Site {
+            title: "Blog".to_owned(),
+            owner: bindings::Owner {
+                email: "robin@example.com".to_owned(),
+                name: "Robin".to_owned(),
+            },
+        },
+        user: bindings::User {
+            name: "Alex".to_owned(),
+            admin: true,
+        },
+    };
+    let result = website.call_apply(&mut store, &params)?;
+
+    let expected = "<title>Blog</title>
+<p>Alex (admin)</p>
+<h2>Hello</h2>#intro #wasm 
+<h2>Upcoming [draft]</h2>
+<footer>Robin &lt;robin@example.com&gt;</footer>";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_spilled() -> Result<()> {
+    let component_bytes = compile("spilled.txt", SPILLED_TEMPLATE)?;
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (spilled, _) = spilled_bindings::Spilled::instantiate(&mut store, &component, &linker)?;
+
+    let params = spilled_bindings::Params {
+        author: spilled_bindings::Person {
+            first: "Robin".to_owned(),
+            last: "Brown".to_owned(),
+            nickname: "Rob".to_owned(),
+            title: "Dr.".to_owned(),
+            verified: true,
+        },
+        editor: spilled_bindings::Person {
+            first: "Alex".to_owned(),
+            last: "Smith".to_owned(),
+            nickname: "Al".to_owned(),
+            title: "Mx.".to_owned(),
+            verified: false,
+        },
+    };
+    let result = spilled.call_apply(&mut store, &params)?;
+    assert_eq!(
+        result,
+        "Dr. Robin \"Rob\" Brown*, edited by Mx. Alex \"Al\" Smith"
+    );
+
+    Ok(())
+}
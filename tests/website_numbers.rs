@@ -0,0 +1,182 @@
+/// Generates a template component for a website with numeric params
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record item {
+                    label: string,
+                    change: s8,
+                    quantity: u16,
+                    weight: f32,
+                }
+
+                record params {
+                    name: string,
+                    balance: s32,
+                    count: u32,
+                    offset: s64,
+                    price: f64,
+                    views: u64,
+                    items: list<item>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+mod float_bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:floats;
+
+            world floats {
+                record item {
+                    weight: f32,
+                }
+
+                record params {
+                    price: f64,
+                    items: list<item>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "{{ name }}: {{ count: u32 }} orders, balance {{ balance: s32 }}, {{ views: u64 }} views ({{ offset: s64 }})
+Price: {{ price: f64 }}
+{% for item in items %}{{ item.label }} x{{ item.quantity: u16 }} ({{ item.change: s8 }}) {{ item.weight: f32 }}kg{% if item.quantity %} in stock{% endif %}
+{% endfor %}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.txt", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        name: "Shop".to_owned(),
+        count: 0,
+        balance: -1250,
+        views: u64::MAX,
+        offset: i64::MIN,
+        price: 19.99,
+        items: vec![
+            bindings::Item {
+                label: "Tea".to_owned(),
+                quantity: 65535,
+                change: -128,
+                weight: 0.25,
+            },
+            bindings::Item {
+                label: "Cups".to_owned(),
+                quantity: 0,
+                change: 7,
+                weight: 3.0,
+            },
+        ],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected =
+        "Shop: 0 orders, balance -1250, 18446744073709551615 views (-9223372036854775808)
+Price: 19.99
+Tea x65535 (-128) 0.25kg in stock
+Cups x0 (7) 3kg
+";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_floats() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let template = "{% for item in items %}[{{ item.weight: f32 }}]{% endfor %}{{ price: f64 }}";
+    let source = Arc::new(NamedSource::new("floats.txt", template));
+    let file_data = parse_file(&compiler_config, source, template).unwrap();
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component_bytes = gen_component(&compiler_config, &template).finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+    let component = Component::new(&engine, component_bytes)?;
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (floats, _) = float_bindings::Floats::instantiate(&mut store, &component, &linker)?;
+    let items = vec![
+        float_bindings::Item { weight: 1.5 },
+        float_bindings::Item { weight: -2.0 },
+    ];
+
+    let cases = [
+        (0.0, "0"),
+        (-0.5, "-0.5"),
+        (1234.5678, "1234.5678"),
+        (0.000001, "0.000001"),
+        (0.0000001, "0"),
+        (2.5e20, "2.5e20"),
+        (f64::NAN, "NaN"),
+        (f64::INFINITY, "inf"),
+        (f64::NEG_INFINITY, "-inf"),
+    ];
+    for (price, expected) in cases {
+        let params = float_bindings::Params {
+            price,
+            items: items.clone(),
+        };
+        let result = floats.call_apply(&mut store, &params)?;
+        assert_eq!(result, format!("[1.5][-2]{}", expected));
+    }
+
+    Ok(())
+}
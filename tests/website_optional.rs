@@ -0,0 +1,174 @@
+/// Generates a template component for a website with optional strings
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record post {
+                    title: string,
+                    summary: option<string>,
+                }
+
+                record params {
+                    title: string,
+                    subtitle: option<string>,
+                    posts: list<post>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+mod spilled_bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:spilled;
+
+            world spilled {
+                record params {
+                    a: string,
+                    b: string,
+                    c: string,
+                    d: string,
+                    e: string,
+                    f: string,
+                    g: string,
+                    h: option<string>,
+                    i: option<string>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "<h1>{{ title }}</h1>{% if subtitle %}<h2>{{ subtitle | upper }}</h2>{% endif %}
+<p>{{ subtitle }}</p>
+{% for post in posts %}<h3>{{ post.title }}</h3>{% if post.summary %}<p>{{ post.summary }}</p>{% endif %}({{ post.summary | default(\"n/a\") }})
+{% endfor %}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        title: "Blog".to_owned(),
+        subtitle: Some("posts and more".to_owned()),
+        posts: vec![
+            bindings::Post {
+                title: "First".to_owned(),
+                summary: Some("The beginning".to_owned()),
+            },
+            bindings::Post {
+                title: "Second".to_owned(),
+                summary: None,
+            },
+        ],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<h1>Blog</h1><h2>POSTS AND MORE</h2>
+<p>posts and more</p>
+<h3>First</h3><p>The beginning</p>(The beginning)
+<h3>Second</h3>(n/a)
+";
+    assert_eq!(result, expected);
+
+    let params = bindings::Params {
+        title: "Blog".to_owned(),
+        subtitle: None,
+        posts: vec![],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    assert_eq!(result, "<h1>Blog</h1>\n<p></p>\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_spilled() -> Result<()> {
+    // 7 strings and 2 optional strings flatten to 20 values, so the params are passed in memory
+    let template = "{{ a }}{{ b }}{{ c }}{{ d }}{{ e }}{{ f }}{{ g }}|{% if h %}+{% endif %}{{ h | default(\"none\") }}|{% if i %}{{ i }}{% else %}-{% endif %}";
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("spilled.txt", template));
+    let file_data = parse_file(&compiler_config, source, template).unwrap();
+
+    let params = Params::new(&file_data)?;
+    assert!(params.must_spill());
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component_bytes = gen_component(&compiler_config, &template).finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+    let component = Component::new(&engine, component_bytes)?;
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (spilled, _) = spilled_bindings::Spilled::instantiate(&mut store, &component, &linker)?;
+
+    let mut params = spilled_bindings::Params {
+        a: "a".to_owned(),
+        b: "b".to_owned(),
+        c: "c".to_owned(),
+        d: "d".to_owned(),
+        e: "e".to_owned(),
+        f: "f".to_owned(),
+        g: "g".to_owned(),
+        h: Some("h".to_owned()),
+        i: None,
+    };
+    assert_eq!(spilled.call_apply(&mut store, &params)?, "abcdefg|+h|-");
+
+    params.h = None;
+    params.i = Some("i".to_owned());
+    assert_eq!(spilled.call_apply(&mut store, &params)?, "abcdefg|none|i");
+
+    Ok(())
+}
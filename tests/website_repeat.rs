@@ -0,0 +1,77 @@
+/// Renders a template many times with the same instance to check that memory is reused
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record params {
+                    content: string,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "<p>{{ content }}</p><p>{{ content | upper }}</p>";
+
+// Each call allocates about 4 MiB, so without freeing memory between calls
+// the instance would run out of its 4 GiB address space
+const CALLS: usize = 2000;
+const CONTENT_LEN: usize = 1 << 20;
+
+#[test]
+fn test_repeated_calls() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        content: "a".repeat(CONTENT_LEN),
+    };
+    let expected_len = "<p></p><p></p>".len() + 2 * CONTENT_LEN;
+    for _ in 0..CALLS {
+        let result = website.call_apply(&mut store, &params)?;
+        assert_eq!(result.len(), expected_len);
+    }
+
+    Ok(())
+}
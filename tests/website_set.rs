@@ -0,0 +1,127 @@
+/// Generates a template component for a website that computes values once with variables
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record post {
+                    author: string,
+                    title: string,
+                }
+
+                record params {
+                    site: string,
+                    title: string,
+                    posts: list<post>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "{% macro shout(text) %}{% set loud = text | upper ~ \"!\" %}{{ loud }}{% endmacro -%}
+{% set heading = title | trim | upper ~ \" | \" ~ site -%}
+<title>{{ heading }}</title><h1>{{ heading }}</h1>
+{% for post in posts %}{% set title = post.title ~ \" by \" ~ post.author | default(\"anonymous\") -%}
+<a title=\"{{ title }}\">{{ title }}</a>
+{% endfor %}{{ title }}{{ shout(site) }}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        autoescape: true,
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        site: "Blog".to_owned(),
+        title: " Posts ".to_owned(),
+        posts: vec![
+            bindings::Post {
+                title: "Fish & Chips".to_owned(),
+                author: "Robin".to_owned(),
+            },
+            bindings::Post {
+                title: "Untitled".to_owned(),
+                author: "".to_owned(),
+            },
+        ],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<title>POSTS | Blog</title><h1>POSTS | Blog</h1>
+<a title=\"Fish &amp; Chips by Robin\">Fish &amp; Chips by Robin</a>
+<a title=\"Untitled by anonymous\">Untitled by anonymous</a>
+ Posts BLOG!";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_set_errors() {
+    let error = |text: &'static str| {
+        let source = Arc::new(NamedSource::new("website.html", text));
+        let report = parse_file(&CompilerConfig::default(), source, text)
+            .and_then(|file_data| Ok(Params::new(&file_data).map(|_| ())?))
+            .unwrap_err();
+        report.to_string()
+    };
+
+    assert_eq!(error("{% set x %}"), "Expected \"=\", found \"%}\"");
+    assert_eq!(
+        error("{% set x = 3 %}"),
+        "Expected string or parameter, found integer"
+    );
+    assert_eq!(
+        error("{% set posts = site %}{% for post in posts %}{% endfor %}"),
+        "`posts` is used as a list here but variables are strings"
+    );
+    assert_eq!(
+        error("{% set user = name %}{{ user.name }}"),
+        "`user` is used as a record here but variables are strings"
+    );
+    // a variable is only in scope until the end of the block it's set in
+    assert_eq!(
+        error("{% if a %}{% set tags = a %}{% endif %}{% for tag in tags %}{% endfor %}{{ tags }}"),
+        "`tags` is used as a string here but as a list elsewhere"
+    );
+}
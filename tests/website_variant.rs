@@ -0,0 +1,143 @@
+/// Generates a template component for a website that matches on variants and binds their payloads
+use std::sync::Arc;
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                record member {
+                    name: string,
+                    admin: bool,
+                }
+
+                record imported {
+                    site: string,
+                    id: u32,
+                }
+
+                variant author {
+                    member(member),
+                    anonymous,
+                    imported(imported),
+                }
+
+                record post {
+                    title: string,
+                    author: author,
+                }
+
+                variant visitor {
+                    user(string),
+                    guest,
+                }
+
+                record params {
+                    visitor: visitor,
+                    posts: list<post>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "{% match visitor %}
+    {% case user(name) %}Hello {{ name }}{% endcase %}
+    {% case guest %}Hello guest{% endcase %}
+{% endmatch %}
+{% for post in posts %}{{ post.title }}{% match post.author %}\
+{% case member(member) %} by {{ member.name }}{% if member.admin %} (admin){% endif %}\
+{% case anonymous %} anonymously\
+{% case imported(source) %} from {{ source.site }} #{{ source.id: u32 }}\
+{% endmatch %}
+{% endfor %}";
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::new(&file_data)?;
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let posts = vec![
+        bindings::Post {
+            title: "First".to_owned(),
+            author: bindings::Author::Member(bindings::Member {
+                name: "Robin".to_owned(),
+                admin: true,
+            }),
+        },
+        bindings::Post {
+            title: "Second".to_owned(),
+            author: bindings::Author::Anonymous,
+        },
+        bindings::Post {
+            title: "Third".to_owned(),
+            author: bindings::Author::Imported(bindings::Imported {
+                site: "example.com".to_owned(),
+                id: 42,
+            }),
+        },
+    ];
+    let params = bindings::Params {
+        visitor: bindings::Visitor::User("Sam".to_owned()),
+        posts: posts.clone(),
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "Hello Sam
+First by Robin (admin)
+Second anonymously
+Third from example.com #42
+";
+    assert_eq!(result, expected);
+
+    let params = bindings::Params {
+        visitor: bindings::Visitor::Guest,
+        posts,
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "Hello guest
+First by Robin (admin)
+Second anonymously
+Third from example.com #42
+";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
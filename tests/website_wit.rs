@@ -0,0 +1,619 @@
+/// Generates a template component for a website whose params are declared in WIT
+use std::{path::Path, sync::Arc};
+
+use miette::NamedSource;
+use pretty_assertions::assert_eq;
+use template_compiler::{
+    gen_component, gen_wit, parse_file, Config as CompilerConfig, Params, TemplateGenerator,
+};
+
+use anyhow::Result;
+
+use wasmtime::{
+    component::{Component, Linker},
+    Config, Engine, Store,
+};
+use wit_parser::{Resolve, UnresolvedPackage};
+
+const WIT: &str = "
+    package template:website;
+
+    world website {
+        enum role {
+            reader,
+            editor,
+            admin,
+        }
+
+        record author {
+            display-name: string,
+            nickname: option<string>,
+            verified: bool,
+            role: role,
+        }
+
+        variant contact {
+            email(string),
+            phone(u32),
+            hidden,
+        }
+
+        record params {
+            draft: bool,
+            page-title: string,
+            views: u32,
+            author: author,
+            contact: contact,
+            unused: list<string>,
+            tags: list<string>,
+        }
+
+        export apply: func(param: params) -> string;
+    }
+";
+
+mod bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:website;
+
+            world website {
+                enum role {
+                    reader,
+                    editor,
+                    admin,
+                }
+
+                record author {
+                    display-name: string,
+                    nickname: option<string>,
+                    verified: bool,
+                    role: role,
+                }
+
+                variant contact {
+                    email(string),
+                    phone(u32),
+                    hidden,
+                }
+
+                record params {
+                    draft: bool,
+                    page-title: string,
+                    views: u32,
+                    author: author,
+                    contact: contact,
+                    unused: list<string>,
+                    tags: list<string>,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+const TEMPLATE: &str = "<h1>{{ page_title }}</h1> ({{ views }} views)
+{% if draft %}<p>Draft</p>{% endif %}{% if draft == author.verified and views >= 1000 %}<p>Verified draft</p>{% endif %}
+<p>By {{ author.display_name }}{% if author.nickname %} aka {{ author.nickname }}{% endif %}{% if author.verified %} (verified){% endif %}{% match author.role %}{% case editor %}, editor{% case admin %}, admin{% endmatch %}</p>
+{% match contact %}{% case email(address) %}<p>Email {{ address }}</p>{% case phone(number) %}<p>Call {{ number }}</p>{% endmatch %}
+{% for tag in tags %}[{{ tag }}]{% endfor %}";
+
+fn resolve(wit: &str) -> Resolve {
+    let mut resolve = Resolve::new();
+    let package = UnresolvedPackage::parse(Path::new("world.wit"), wit).unwrap();
+    resolve.push(package).unwrap();
+    resolve
+}
+
+fn check(template: &str) -> miette::Result<()> {
+    let resolve = resolve(WIT);
+    let world = resolve.worlds.iter().next().unwrap().0;
+    let source = Arc::new(NamedSource::new("website.html", template.to_owned()));
+    let file_data = parse_file(&CompilerConfig::default(), source, template).unwrap();
+    Params::from_wit(&resolve, world, "apply", &file_data)?;
+    Ok(())
+}
+
+#[test]
+fn test_website() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let resolve = resolve(WIT);
+    let world = resolve.worlds.iter().next().unwrap().0;
+    let source = Arc::new(NamedSource::new("website.html", TEMPLATE));
+    let file_data = parse_file(&compiler_config, source, TEMPLATE).unwrap();
+
+    let params = Params::from_wit(&resolve, world, "apply", &file_data).unwrap();
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (website, _) = bindings::Website::instantiate(&mut store, &component, &linker)?;
+
+    let params = bindings::Params {
+        draft: true,
+        page_title: "Declared types".to_owned(),
+        views: 1024,
+        author: bindings::Author {
+            display_name: "Robin".to_owned(),
+            nickname: Some("Rob".to_owned()),
+            verified: true,
+            role: bindings::Role::Admin,
+        },
+        contact: bindings::Contact::Email("robin@example.com".to_owned()),
+        unused: vec!["ignored".to_owned()],
+        tags: vec!["wit".to_owned(), "wasm".to_owned()],
+    };
+    let result = website.call_apply(&mut store, &params)?;
+    let expected = "<h1>Declared types</h1> (1024 views)
+<p>Draft</p><p>Verified draft</p>
+<p>By Robin aka Rob (verified), admin</p>
+<p>Email robin@example.com</p>
+[wit][wasm]";
+    assert_eq!(result, expected);
+
+    Ok(())
+}
+
+const SHARED_WIT: &str = "
+    package template:shared;
+
+    world shared {
+        enum color {
+            red,
+            blue,
+        }
+
+        record person {
+            name: string,
+            favorite: color,
+        }
+
+        record params {
+            background: color,
+            foreground: color,
+            owner: person,
+            editor: person,
+        }
+
+        export apply: func(param: params) -> string;
+    }
+";
+
+mod shared_bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:shared;
+
+            world shared {
+                enum color {
+                    red,
+                    blue,
+                }
+
+                record person {
+                    name: string,
+                    favorite: color,
+                }
+
+                record params {
+                    background: color,
+                    foreground: color,
+                    owner: person,
+                    editor: person,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+#[test]
+fn test_shared_types() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let resolve = resolve(SHARED_WIT);
+    let world = resolve.worlds.iter().next().unwrap().0;
+    let template = "{% match background %}{% case red %}red{% case blue %}blue{% endmatch %} on \
+        {% match foreground %}{% case red %}red{% case blue %}blue{% endmatch %}, \
+        {{ owner.name }} and {{ editor.name }}{% match editor.favorite %}{% case blue %} (blue){% endmatch %}";
+    let source = Arc::new(NamedSource::new("website.html", template));
+    let file_data = parse_file(&compiler_config, source, template).unwrap();
+
+    let params = Params::from_wit(&resolve, world, "apply", &file_data).unwrap();
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (shared, _) = shared_bindings::Shared::instantiate(&mut store, &component, &linker)?;
+
+    let params = shared_bindings::Params {
+        background: shared_bindings::Color::Red,
+        foreground: shared_bindings::Color::Blue,
+        owner: shared_bindings::Person {
+            name: "Robin".to_owned(),
+            favorite: shared_bindings::Color::Red,
+        },
+        editor: shared_bindings::Person {
+            name: "Sam".to_owned(),
+            favorite: shared_bindings::Color::Blue,
+        },
+    };
+    let result = shared.call_apply(&mut store, &params)?;
+    assert_eq!(result, "red on blue, Robin and Sam (blue)");
+
+    Ok(())
+}
+
+#[test]
+fn test_unknown_field() {
+    let error = check("{{ author.email }}").unwrap_err();
+    assert_eq!(error.to_string(), "Record `author` has no field `email`");
+
+    let error = check("{{ subtitle }}").unwrap_err();
+    assert_eq!(error.to_string(), "Record `params` has no field `subtitle`");
+}
+
+#[test]
+fn test_wrong_type() {
+    let error = check("{{ draft }}").unwrap_err();
+    assert_eq!(error.to_string(), "Expected a string, found a bool");
+
+    let error = check("{% for name in author %}{% endfor %}").unwrap_err();
+    assert_eq!(error.to_string(), "Expected a list, found record `author`");
+
+    let error = check("{% if author %}{% endif %}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Expected a bool, string, or list, found record `author`"
+    );
+
+    let error = check("{{ page_title.length }}").unwrap_err();
+    assert_eq!(error.to_string(), "Expected a record, found a string");
+
+    let error = check("{% for name in author.nickname %}{% endfor %}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Expected a list, found an optional string"
+    );
+
+    let error = check("{% match draft %}{% endmatch %}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Expected an enum or variant, found a bool"
+    );
+
+    let error =
+        check("{% match author.role %}{% case admin %}{% case owner %}{% endmatch %}").unwrap_err();
+    assert_eq!(error.to_string(), "Enum `role` has no case `owner`");
+
+    let error = check("{% match contact %}{% case fax(number) %}{% endmatch %}").unwrap_err();
+    assert_eq!(error.to_string(), "Variant `contact` has no case `fax`");
+
+    let error = check("{% match contact %}{% case hidden(reason) %}{% endmatch %}").unwrap_err();
+    assert_eq!(error.to_string(), "Case `hidden` has no payload");
+
+    let error = check("{% match author.role %}{% case admin(level) %}{% endmatch %}").unwrap_err();
+    assert_eq!(error.to_string(), "Case `admin` has no payload");
+
+    let error = check("{% match contact %}{% case phone(number) %}{{ number: u8 }}{% endmatch %}")
+        .unwrap_err();
+    assert_eq!(error.to_string(), "Expected `u8`, found `u32`");
+
+    let error = check("{% if author.role %}{% endif %}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Expected a bool, string, or list, found enum `role`"
+    );
+
+    let error = check("{{ views: s64 }}").unwrap_err();
+    assert_eq!(error.to_string(), "Expected `s64`, found `u32`");
+
+    let error = check("{{ page_title: u32 }}").unwrap_err();
+    assert_eq!(error.to_string(), "Expected `u32`, found a string");
+}
+
+#[test]
+fn test_bad_comparison() {
+    let error = check("{% if views > 'many' %}{% endif %}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Can't compare `u32` with a string literal"
+    );
+
+    let error = check("{% if views == 5000000000 %}{% endif %}").unwrap_err();
+    assert_eq!(error.to_string(), "`5000000000` doesn't fit in `u32`");
+
+    let error = check("{% if author.role == 'admin' %}{% endif %}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Can't compare enum `role` with a string literal"
+    );
+
+    let error = check("{% if draft and page_title >= author.nickname %}{% endif %}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Can't order a string and an optional string, only numbers"
+    );
+
+    let error = check("{% if not author %}{% endif %}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Expected a bool, string, or list, found record `author`"
+    );
+}
+
+#[test]
+fn test_bad_world() {
+    let resolve = resolve(
+        "
+        package template:website;
+
+        world website {
+            export apply: func(initial: char) -> string;
+        }
+    ",
+    );
+    let world = resolve.worlds.iter().next().unwrap().0;
+    let source = Arc::new(NamedSource::new("website.html", ""));
+    let file_data = parse_file(&CompilerConfig::default(), source, "").unwrap();
+
+    let error = Params::from_wit(&resolve, world, "render", &file_data)
+        .err()
+        .unwrap();
+    assert_eq!(
+        error.to_string(),
+        "World `website` has no exported function `render`"
+    );
+
+    let error = Params::from_wit(&resolve, world, "apply", &file_data)
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "Unsupported type `char`");
+}
+
+#[test]
+fn test_unsupported_payload() {
+    // payloads are flattened into i32s, so wider numbers aren't supported
+    let resolve = resolve(
+        "
+        package template:website;
+
+        world website {
+            variant price {
+                exact(f64),
+                free,
+            }
+
+            record params {
+                price: price,
+            }
+
+            export apply: func(param: params) -> string;
+        }
+    ",
+    );
+    let world = resolve.worlds.iter().next().unwrap().0;
+    let source = Arc::new(NamedSource::new("website.html", ""));
+    let file_data = parse_file(&CompilerConfig::default(), source, "").unwrap();
+
+    let error = Params::from_wit(&resolve, world, "apply", &file_data)
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "Unsupported type `price`");
+}
+
+#[test]
+fn test_emit_wit() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    let template = "{{ page_title }}{% if user.admin %}{{ user.type }}{% endif %}\
+        {% for post in posts %}{{ post.title }}{% for tag in post.tags %}{{ tag }}{% endfor %}{% endfor %}\
+        {% match user.role %}{% case read_only %}{% case admin %}{% endmatch %}\
+        {% match user.login %}{% case account(account) %}{{ account.email }}{% case guest %}{% endmatch %}";
+    let source = Arc::new(NamedSource::new("website.html", template));
+    let file_data = parse_file(&compiler_config, source, template).unwrap();
+    let params = Params::new(&file_data)?;
+
+    let wit = gen_wit(&compiler_config, &params);
+    let expected = "package template:generated;
+
+world template {
+    record post {
+        title: string,
+        tags: list<string>,
+    }
+
+    enum role {
+        read-only,
+        admin,
+    }
+
+    record account {
+        email: string,
+    }
+
+    variant login {
+        account(account),
+        guest,
+    }
+
+    record user {
+        %type: string,
+        admin: bool,
+        role: role,
+        login: login,
+    }
+
+    record params {
+        page-title: string,
+        posts: list<post>,
+        user: user,
+    }
+
+    export apply: func(param: params) -> string;
+}
+";
+    assert_eq!(wit, expected);
+
+    // the emitted world declares the same params the template was inferred with
+    let resolve = resolve(&wit);
+    let world = resolve.worlds.iter().next().unwrap().0;
+    let declared = Params::from_wit(&resolve, world, "apply", &file_data).unwrap();
+    assert_eq!(gen_wit(&compiler_config, &declared), wit);
+
+    Ok(())
+}
+
+mod ambiguous_bindings {
+    use wasmtime::component::bindgen;
+
+    bindgen!({
+        inline: "
+            package template:generated;
+
+            world template {
+                record x-item2 {
+                    c: string,
+                }
+
+                record x {
+                    item: x-item2,
+                }
+
+                record x-item {
+                    e: string,
+                    f: string,
+                }
+
+                record y-item {
+                    d: string,
+                }
+
+                record y {
+                    item: y-item,
+                }
+
+                record params {
+                    x: x,
+                    x-item: x-item,
+                    y: y,
+                }
+
+                export apply: func(param: params) -> string;
+            }
+        ",
+
+    });
+}
+
+#[test]
+fn test_ambiguous_names() -> Result<()> {
+    let compiler_config = CompilerConfig {
+        export_func_name: "apply".into(),
+        ..Default::default()
+    };
+    // `x.item` would be named `x-item` like the record `x_item` is, so it's numbered instead
+    let template = "{{ x.item.c }}{{ y.item.d }}{{ x_item.e }}{{ x_item.f }}";
+    let source = Arc::new(NamedSource::new("website.html", template));
+    let file_data = parse_file(&compiler_config, source, template).unwrap();
+    let params = Params::new(&file_data)?;
+
+    let wit = gen_wit(&compiler_config, &params);
+    let expected = "package template:generated;
+
+world template {
+    record x-item2 {
+        c: string,
+    }
+
+    record x {
+        item: x-item2,
+    }
+
+    record x-item {
+        e: string,
+        f: string,
+    }
+
+    record y-item {
+        d: string,
+    }
+
+    record y {
+        item: y-item,
+    }
+
+    record params {
+        x: x,
+        x-item: x-item,
+        y: y,
+    }
+
+    export apply: func(param: params) -> string;
+}
+";
+    assert_eq!(wit, expected);
+
+    let template = TemplateGenerator::new(&compiler_config, params, &file_data);
+    let component = gen_component(&compiler_config, &template);
+    let component_bytes = component.finish();
+
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+
+    let component = Component::new(&engine, component_bytes)?;
+
+    let linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, ());
+    let (ambiguous, _) =
+        ambiguous_bindings::Template::instantiate(&mut store, &component, &linker)?;
+
+    let params = ambiguous_bindings::Params {
+        x: ambiguous_bindings::X {
+            item: ambiguous_bindings::XItem2 { c: "c".to_owned() },
+        },
+        x_item: ambiguous_bindings::XItem {
+            e: "e".to_owned(),
+            f: "f".to_owned(),
+        },
+        y: ambiguous_bindings::Y {
+            item: ambiguous_bindings::YItem { d: "d".to_owned() },
+        },
+    };
+    let result = ambiguous.call_apply(&mut store, &params)?;
+    assert_eq!(result, "cdef");
+
+    Ok(())
+}